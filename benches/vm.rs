@@ -0,0 +1,43 @@
+//! Times `VM::run` on straight-line workloads. Run with `cargo bench`.
+//!
+//! Each workload is compiled, verified and loaded once per optimization level,
+//! and only the calls to `run` are timed, so the numbers measure dispatch in
+//! the VM loop rather than the compiler, the verifier or linking.
+
+use std::time::Instant;
+
+use rust_lox::{optimize::OptLevel, vm::{self, InterpretResult}};
+
+const RUNS: u32 = 20_000;
+
+fn workloads() -> Vec<(&'static str, String)>
+{
+    vec![
+        ("increment", "var x = 0;\n".to_string() + &"x = x + 1;\n".repeat(80)),
+        ("arithmetic", "var a = 3; var b = 4;\n".to_string() + &"a = (a * b - a) / b + 1;\n".repeat(40)),
+        ("compare", "var a = 3; var b = 4;\n".to_string() + &"a <= b == !(a > b);\n".repeat(60)),
+        ("concat", "var s = \"\";\n".to_string() + &"s = \"x\" + \"y\";\n".repeat(80)),
+    ]
+}
+
+fn main()
+{
+    for (name, source) in workloads()
+    {
+        for opt_level in [OptLevel::O0, OptLevel::O1]
+        {
+            let mut vm = vm::init_vm();
+            vm.set_opt_level(opt_level);
+            let chunk = vm.compile(source.clone()).expect("benchmark script should compile");
+            let code_size = chunk.code.len();
+            vm.load_chunk(chunk).expect("benchmark chunk should verify");
+            let start = Instant::now();
+            for _ in 0..RUNS
+            {
+                assert_eq!(vm.run(), InterpretResult::InterpretOk);
+            }
+            let elapsed = start.elapsed();
+            println!("{:<12} {:?}  {:>8.2} us/run  ({} bytes of code)", name, opt_level, elapsed.as_secs_f64() * 1e6 / RUNS as f64, code_size);
+        }
+    }
+}
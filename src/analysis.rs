@@ -0,0 +1,151 @@
+//! Name resolution for editor tooling: where each variable is declared and
+//! used, worked out from whatever part of the program parses.
+
+use crate::ast::{Expr, Span, Stmt};
+use crate::diagnostic::Diagnostic;
+use crate::parser;
+use crate::vm;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SymbolKind
+{
+    Variable,
+    Native { arity: u8 },
+}
+
+/// One `var` statement.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Declaration
+{
+    pub name_span: Span,
+    pub span: Span, // The whole statement.
+}
+
+/// A use of a name.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Reference
+{
+    pub span: Span,
+    pub is_write: bool, // An assignment rather than a read.
+}
+
+/// A name and every place it appears. All variables are globals, so one
+/// symbol covers every declaration of its name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Symbol
+{
+    pub name: String,
+    pub kind: SymbolKind,
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+}
+
+pub struct Analysis
+{
+    pub symbols: Vec<Symbol>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Resolves the names in `source`. `natives` are the host's native functions,
+/// which are in scope without a declaration.
+///
+/// The diagnostics are the errors the compiler reports for the source, the
+/// same ones `rust_lox file.lox` prints.
+pub fn analyze(source: &str, natives: &[(&str, u8)]) -> Analysis
+{
+    let (program, _) = parser::parse_recovering(source);
+    let diagnostics = vm::init_vm().try_compile(source).err().unwrap_or_default();
+
+    let mut analysis = Analysis { symbols: Vec::new(), diagnostics: diagnostics };
+    for (name, arity) in natives
+    {
+        analysis.symbol(name, SymbolKind::Native { arity: *arity });
+    }
+    for statement in &program
+    {
+        analysis.statement(statement);
+    }
+    return analysis;
+}
+
+impl Analysis
+{
+    /// The symbol whose name is at byte `offset`, in a declaration or a use.
+    pub fn symbol_at(&self, offset: u32) -> Option<&Symbol>
+    {
+        let covers = |span: &Span| span.offset <= offset && offset <= span.end();
+        self.symbols.iter().find(|symbol|
+        {
+            symbol.declarations.iter().any(|declaration| covers(&declaration.name_span)) || symbol.references.iter().any(|reference| covers(&reference.span))
+        })
+    }
+
+    /// Natives, and variables declared before byte `offset`.
+    pub fn names_in_scope(&self, offset: u32) -> Vec<&Symbol>
+    {
+        self.symbols.iter().filter(|symbol| match symbol.kind
+        {
+            SymbolKind::Native { .. } => true,
+            SymbolKind::Variable => symbol.declarations.iter().any(|declaration| declaration.name_span.offset < offset),
+        }).collect()
+    }
+
+    fn statement(&mut self, statement: &Stmt)
+    {
+        match statement
+        {
+            Stmt::Expression { expr, .. } | Stmt::Print { expr, .. } => self.expression(expr),
+            Stmt::Var { name, name_span, initializer, span } =>
+            {
+                // The initializer runs before the name is defined.
+                if let Some(initializer) = initializer
+                {
+                    self.expression(initializer);
+                }
+                // Declaring a native's name replaces the native.
+                let symbol = self.symbol(name, SymbolKind::Variable);
+                symbol.kind = SymbolKind::Variable;
+                symbol.declarations.push(Declaration { name_span: *name_span, span: *span });
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr)
+    {
+        match expr
+        {
+            Expr::Literal { .. } => (),
+            Expr::Grouping { expr, .. } => self.expression(expr),
+            Expr::Unary { operand, .. } => self.expression(operand),
+            Expr::Binary { left, right, .. } =>
+            {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Variable { name, span } =>
+            {
+                self.symbol(name, SymbolKind::Variable).references.push(Reference { span: *span, is_write: false });
+            }
+            Expr::Assign { name, name_span, value } =>
+            {
+                self.expression(value);
+                self.symbol(name, SymbolKind::Variable).references.push(Reference { span: *name_span, is_write: true });
+            }
+        }
+    }
+
+    // The symbol called `name`, created with `kind` if there isn't one yet.
+    fn symbol(&mut self, name: &str, kind: SymbolKind) -> &mut Symbol
+    {
+        let index = match self.symbols.iter().position(|symbol| symbol.name == name)
+        {
+            Some(index) => index,
+            None =>
+            {
+                self.symbols.push(Symbol { name: name.to_string(), kind: kind, declarations: Vec::new(), references: Vec::new() });
+                self.symbols.len() - 1
+            }
+        };
+        return &mut self.symbols[index];
+    }
+}
@@ -0,0 +1,119 @@
+use crate::scanner::Token;
+
+/// Location of a node in the source: a byte range plus the lines of its first
+/// and last tokens.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct Span
+{
+    pub offset: u32,
+    pub length: u32,
+    pub line: u32,
+    pub end_line: u32,
+}
+
+impl Span
+{
+    pub fn from_token(token: &Token) -> Span
+    {
+        Span { offset: token.offset, length: token.length, line: token.line, end_line: token.line }
+    }
+
+    pub fn end(&self) -> u32
+    {
+        self.offset + self.length
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span
+    {
+        let (first, last) = if self.offset <= other.offset { (self, other) } else { (other, self) };
+        let end_line = if first.end() > last.end() { first.end_line } else { last.end_line };
+        Span
+        {
+            offset: first.offset,
+            length: first.end().max(last.end()) - first.offset,
+            line: first.line,
+            end_line: end_line,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Literal
+{
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Nil,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum UnaryOp
+{
+    Negate,
+    Not,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BinaryOp
+{
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr
+{
+    Literal { value: Literal, span: Span },
+    Grouping { expr: Box<Expr>, span: Span },
+    Unary { operator: UnaryOp, operator_span: Span, operand: Box<Expr> },
+    Binary { operator: BinaryOp, operator_span: Span, left: Box<Expr>, right: Box<Expr> },
+    Variable { name: String, span: Span },
+    Assign { name: String, name_span: Span, value: Box<Expr> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Stmt
+{
+    Expression { expr: Expr, span: Span },
+    Print { expr: Expr, span: Span },
+    Var { name: String, name_span: Span, initializer: Option<Expr>, span: Span },
+}
+
+impl Expr
+{
+    /// The full extent of the expression, including its operands.
+    pub fn span(&self) -> Span
+    {
+        match self
+        {
+            Expr::Literal { span, .. } => *span,
+            Expr::Grouping { span, .. } => *span,
+            Expr::Unary { operator_span, operand, .. } => operator_span.to(operand.span()),
+            Expr::Binary { left, right, .. } => left.span().to(right.span()),
+            Expr::Variable { span, .. } => *span,
+            Expr::Assign { name_span, value, .. } => name_span.to(value.span()),
+        }
+    }
+}
+
+impl Stmt
+{
+    pub fn span(&self) -> Span
+    {
+        match self
+        {
+            Stmt::Expression { span, .. } => *span,
+            Stmt::Print { span, .. } => *span,
+            Stmt::Var { span, .. } => *span,
+        }
+    }
+}
@@ -1,58 +1,121 @@
-use std::u32;
-
-use crate::value::{self, Value, ValueArray};
-
-#[repr(u8)]
-pub enum OpCode
-{
-    OpConstant = 0,
-    OpReturn = 1,
-    OpNegate = 2,
-    OpAdd = 3,
-    OpSubtract = 4,
-    OpMultiply = 5,
-    OpDivide = 6,
-    OpNil = 7,
-    OpTrue = 8,
-    OpFalse = 9,
-    OpNot = 10,
-    OpEqual = 11,
-    OpGreater = 12,
-    OpLess = 13,
-    OpPrint = 14,
-    OpPop = 15,
-    OpDefineGlobal = 16,
-    OpGetGlobal = 17,
-    OpSetGlobal = 18,
-}
-
-#[derive(Clone)]
-pub struct Chunk
-{
-    pub code: Vec<u8>, // Array of bytes.
-    pub constants: ValueArray, // Vec<f64>
-    pub lines: Vec<u32>, // Array of lines
-}
-
-pub fn init_chunk() -> Chunk
-{
-    let chunk_init = Chunk
-    {
-        code: Vec::new(), //with_capacity(0),
-        constants: value::init_value_array(),
-        lines: Vec::new(), //with_capacity(0),
-        };
-    return chunk_init;
-}
-
-pub fn write_chunk(chunk: &mut Chunk, byte: u8, line: u32)
-{
-    chunk.code.push(byte);
-    chunk.lines.push(line);
-}
-
-pub fn add_constant(chunk: &mut Chunk, value: Value) -> u32
-{
-    value::write_value_array(&mut chunk.constants, value);
-    return (chunk.constants.values.len() - 1) as u32;
-}
\ No newline at end of file
+use std::convert::TryFrom;
+
+use crate::value::{self, Value, ValueArray};
+
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OpCode
+{
+    OpConstant = 0,
+    OpReturn = 1,
+    OpNegate = 2,
+    OpAdd = 3,
+    OpSubtract = 4,
+    OpMultiply = 5,
+    OpDivide = 6,
+    OpNil = 7,
+    OpTrue = 8,
+    OpFalse = 9,
+    OpNot = 10,
+    OpEqual = 11,
+    OpGreater = 12,
+    OpLess = 13,
+    OpPrint = 14,
+    OpPop = 15,
+    OpDefineGlobal = 16,
+    OpGetGlobal = 17,
+    OpSetGlobal = 18,
+    // Emitted by the optimizer in place of a comparison followed by OpNot.
+    OpNotEqual = 19,
+    OpGreaterEqual = 20,
+    OpLessEqual = 21,
+    // Emitted by the optimizer in place of OpConstant followed by OpAdd.
+    OpAddConstant = 22,
+}
+
+/// Decodes an instruction byte, giving back the byte if it isn't an opcode.
+impl TryFrom<u8> for OpCode
+{
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<OpCode, u8>
+    {
+        // Indexed by discriminant, so this must list the opcodes in declaration order.
+        const OPCODES: [OpCode; 23] = [
+            OpCode::OpConstant, OpCode::OpReturn, OpCode::OpNegate, OpCode::OpAdd,
+            OpCode::OpSubtract, OpCode::OpMultiply, OpCode::OpDivide, OpCode::OpNil,
+            OpCode::OpTrue, OpCode::OpFalse, OpCode::OpNot, OpCode::OpEqual,
+            OpCode::OpGreater, OpCode::OpLess, OpCode::OpPrint, OpCode::OpPop,
+            OpCode::OpDefineGlobal, OpCode::OpGetGlobal, OpCode::OpSetGlobal, OpCode::OpNotEqual,
+            OpCode::OpGreaterEqual, OpCode::OpLessEqual, OpCode::OpAddConstant,
+        ];
+        match OPCODES.get(byte as usize)
+        {
+            Some(opcode) => Ok(*opcode),
+            None => Err(byte),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Chunk
+{
+    pub code: Vec<u8>, // Array of bytes.
+    pub constants: ValueArray, // Vec<f64>
+    pub lines: Vec<u32>, // Array of lines
+}
+
+pub fn init_chunk() -> Chunk
+{
+    let chunk_init = Chunk
+    {
+        code: Vec::new(), //with_capacity(0),
+        constants: value::init_value_array(),
+        lines: Vec::new(), //with_capacity(0),
+        };
+    return chunk_init;
+}
+
+pub fn write_chunk(chunk: &mut Chunk, byte: u8, line: u32)
+{
+    chunk.code.push(byte);
+    chunk.lines.push(line);
+}
+
+pub fn has_constant_operand(opcode: u8) -> bool
+{
+    opcode == OpCode::OpConstant as u8 || opcode == OpCode::OpAddConstant as u8 || is_global_op(opcode)
+}
+
+/// The global ops take the index of a string constant naming the variable.
+pub fn is_global_op(opcode: u8) -> bool
+{
+    opcode == OpCode::OpDefineGlobal as u8
+        || opcode == OpCode::OpGetGlobal as u8
+        || opcode == OpCode::OpSetGlobal as u8
+}
+
+pub fn add_constant(chunk: &mut Chunk, value: Value) -> u32
+{
+    value::write_value_array(&mut chunk.constants, value);
+    return (chunk.constants.values.len() - 1) as u32;
+}
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn decodes_every_opcode_and_nothing_else()
+    {
+        for byte in 0..=22u8
+        {
+            let opcode = OpCode::try_from(byte).expect("bytes up to 22 are opcodes");
+            assert_eq!(opcode as u8, byte);
+        }
+        for byte in 23..=255u8
+        {
+            assert_eq!(OpCode::try_from(byte), Err(byte));
+        }
+    }
+}
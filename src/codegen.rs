@@ -0,0 +1,213 @@
+use std::convert::TryInto;
+
+use crate::ast::{BinaryOp, Expr, Literal, Span, Stmt, UnaryOp};
+use crate::chunk::{Chunk, OpCode, add_constant, write_chunk};
+use crate::diagnostic::Diagnostic;
+use crate::value::{self, Value};
+use crate::vm::VM;
+
+/// Walks a parsed program and emits the same bytecode, line table and errors
+/// `Compiler` would for the same source.
+///
+/// `Compiler` tags each instruction with the line of the last token it had
+/// read, so an operator's instruction gets the line its right operand ends
+/// on and a statement's the line of its `;`.
+pub struct CodeGenerator<'a, 'b, 'c>
+{
+    chunk: &'a mut Chunk,
+    vm: &'b mut VM,
+    source: &'c str, // The text that was parsed, for error locations.
+    diagnostics: Vec<Diagnostic>,
+    statement_failed: bool, // Like `Compiler`, report one error per statement.
+}
+
+impl CodeGenerator<'_, '_, '_>
+{
+    pub fn new<'a, 'b, 'c>(chunk: &'a mut Chunk, vm: &'b mut VM, source: &'c str) -> CodeGenerator<'a, 'b, 'c>
+    {
+        CodeGenerator
+        {
+            chunk: chunk,
+            vm: vm,
+            source: source,
+            diagnostics: Vec::new(),
+            statement_failed: false,
+        }
+    }
+
+    pub fn generate(&mut self, program: &[Stmt]) -> Result<(), Vec<Diagnostic>>
+    {
+        for statement in program
+        {
+            self.statement_failed = false;
+            self.statement(statement);
+        }
+
+        // `Compiler` returns on the line of the end of the file.
+        let line = 1 + self.source.matches('\n').count() as u32;
+        self.emit_byte(OpCode::OpReturn as u8, line);
+
+        if self.diagnostics.is_empty()
+        {
+            return Ok(());
+        }
+        return Err(self.diagnostics.clone());
+    }
+
+    /// Emits `expr` followed by `OpReturn`, so running the chunk leaves the
+    /// expression's value on top of the stack.
+    pub fn generate_expression(&mut self, expr: &Expr) -> Result<(), Vec<Diagnostic>>
+    {
+        self.expression(expr);
+        self.emit_byte(OpCode::OpReturn as u8, expr.span().end_line);
+
+        if self.diagnostics.is_empty()
+        {
+            return Ok(());
+        }
+        return Err(self.diagnostics.clone());
+    }
+
+    fn statement(&mut self, statement: &Stmt)
+    {
+        match statement
+        {
+            Stmt::Expression { expr, span } =>
+            {
+                self.expression(expr);
+                self.emit_byte(OpCode::OpPop as u8, span.end_line);
+            }
+            Stmt::Print { expr, span } =>
+            {
+                self.expression(expr);
+                self.emit_byte(OpCode::OpPrint as u8, span.end_line);
+            }
+            Stmt::Var { name, name_span, initializer, span } =>
+            {
+                let global = self.identifier_constant(name, *name_span);
+                match initializer
+                {
+                    Some(initializer) => self.expression(initializer),
+                    None => self.emit_byte(OpCode::OpNil as u8, name_span.line),
+                }
+                self.emit_bytes(OpCode::OpDefineGlobal as u8, global, span.end_line);
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr)
+    {
+        match expr
+        {
+            Expr::Literal { value, span } => self.literal(value, *span),
+            Expr::Grouping { expr, .. } => self.expression(expr),
+            Expr::Unary { operator, operand, .. } =>
+            {
+                self.expression(operand);
+                match operator
+                {
+                    UnaryOp::Negate => self.emit_byte(OpCode::OpNegate as u8, expr.span().end_line),
+                    UnaryOp::Not => self.emit_byte(OpCode::OpNot as u8, expr.span().end_line),
+                }
+            }
+            Expr::Binary { operator, left, right, .. } =>
+            {
+                self.expression(left);
+                self.expression(right);
+                self.binary(*operator, expr.span().end_line);
+            }
+            Expr::Variable { name, span } =>
+            {
+                let argument = self.identifier_constant(name, *span);
+                self.emit_bytes(OpCode::OpGetGlobal as u8, argument, span.line);
+            }
+            Expr::Assign { name, name_span, value } =>
+            {
+                let argument = self.identifier_constant(name, *name_span);
+                self.expression(value);
+                self.emit_bytes(OpCode::OpSetGlobal as u8, argument, expr.span().end_line);
+            }
+        }
+    }
+
+    fn binary(&mut self, operator: BinaryOp, line: u32)
+    {
+        match operator
+        {
+            BinaryOp::Add => self.emit_byte(OpCode::OpAdd as u8, line),
+            BinaryOp::Subtract => self.emit_byte(OpCode::OpSubtract as u8, line),
+            BinaryOp::Multiply => self.emit_byte(OpCode::OpMultiply as u8, line),
+            BinaryOp::Divide => self.emit_byte(OpCode::OpDivide as u8, line),
+            BinaryOp::NotEqual => self.emit_bytes(OpCode::OpEqual as u8, OpCode::OpNot as u8, line),
+            BinaryOp::Equal => self.emit_byte(OpCode::OpEqual as u8, line),
+            BinaryOp::Greater => self.emit_byte(OpCode::OpGreater as u8, line),
+            BinaryOp::GreaterEqual => self.emit_bytes(OpCode::OpLess as u8, OpCode::OpNot as u8, line),
+            BinaryOp::Less => self.emit_byte(OpCode::OpLess as u8, line),
+            BinaryOp::LessEqual => self.emit_bytes(OpCode::OpGreater as u8, OpCode::OpNot as u8, line),
+        }
+    }
+
+    fn literal(&mut self, literal: &Literal, span: Span)
+    {
+        match literal
+        {
+            Literal::Number(number) => self.emit_constant(value::NumberAsValue(*number), span),
+            Literal::String(string) =>
+            {
+                let string = self.vm.heap.CopyString(string);
+                self.emit_constant(value::ObjAsValue(string), span);
+            }
+            Literal::Bool(true) => self.emit_byte(OpCode::OpTrue as u8, span.line),
+            Literal::Bool(false) => self.emit_byte(OpCode::OpFalse as u8, span.line),
+            Literal::Nil => self.emit_byte(OpCode::OpNil as u8, span.line),
+        }
+    }
+
+    fn identifier_constant(&mut self, name: &str, span: Span) -> u8
+    {
+        let identifier = self.vm.heap.CopyString(name);
+        return self.make_constant(value::ObjAsValue(identifier), span);
+    }
+
+    fn make_constant(&mut self, value: Value, span: Span) -> u8
+    {
+        let constant = add_constant(self.chunk, value);
+        if constant > 255
+        {
+            self.error_at(span, "Too many constants in one chunk.");
+            return 0;
+        }
+        let constant_byte: u8 = constant.try_into().unwrap();
+        return constant_byte;
+    }
+
+    fn emit_constant(&mut self, value: Value, span: Span)
+    {
+        let byte_constant = self.make_constant(value, span);
+        self.emit_bytes(OpCode::OpConstant as u8, byte_constant, span.line);
+    }
+
+    // Reports an error at the token `span` covers, as `Compiler::error` would.
+    fn error_at(&mut self, span: Span, message: &str)
+    {
+        if self.statement_failed
+        {
+            return;
+        }
+        self.statement_failed = true;
+        let lexeme = &self.source[span.offset as usize..span.end() as usize];
+        let diagnostic = Diagnostic::at_span(span, message.to_string());
+        self.diagnostics.push(Diagnostic { location: format!(" at {}", lexeme), ..diagnostic });
+    }
+
+    fn emit_byte(&mut self, byte: u8, line: u32)
+    {
+        write_chunk(self.chunk, byte, line)
+    }
+
+    fn emit_bytes(&mut self, byte1: u8, byte2: u8, line: u32)
+    {
+        self.emit_byte(byte1, line);
+        self.emit_byte(byte2, line);
+    }
+}
@@ -1,549 +1,546 @@
-use std::convert::TryInto;
-
-use crate::chunk::{Chunk, OpCode, add_constant, init_chunk, write_chunk};
-use crate::debug::disassemble_chunk;
-use crate::object::{Obj, ObjString};
-use crate::scanner::{self, Make_Token, Scan_Token, Scanner, Token, TokenType};
-use crate::value::{self, ObjAsValue, Value};
-use crate::vm::{VM};
-
-const debug_print_code: bool = true;
-const debug_trace_execution: bool = true;
-
-#[derive(Clone)]
-pub struct Parser
-{
-    current: Token,
-    previous: Token,
-    had_error: bool,
-    panic_mode: bool
-}
-
-impl Parser
-{
-    pub fn new() -> Parser
-    {
-        Parser
-        {
-            current: Token
-            {
-                token_type: TokenType::TokenWhile,
-                line: 79680,
-                length: 0,
-                start: "".to_string(),
-            },
-            previous: Token
-            {
-                token_type: TokenType::TokenWhile,
-                line: 79680,
-                length: 0,
-                start: "".to_string(),
-            },
-            had_error: false,
-            panic_mode: false
-        }
-    }
-}
-
-#[repr(u8)]
-#[derive(Copy, Clone, PartialEq, PartialOrd)]
-pub enum Precedence
-{
-    PrecNone = 1,
-    PrecAssignment = 2,
-    PrecOr = 3,
-    PrecAnd = 4,
-    PrecEquality = 5,
-    PrecComparison = 6,
-    PrecTerm = 7,
-    PrecFactor = 8,
-    PrecUnary = 9,
-    PrecCall = 10,
-    PrecPrimary = 11
-}
-
-impl Precedence
-{
-    const fn get_precedence(precedence: u8) -> Option<Precedence>
-    {
-        match precedence
-        {
-            1 => Some(Precedence::PrecNone),
-            2 => Some(Precedence::PrecAssignment),
-            3 => Some(Precedence::PrecOr),
-            4 => Some(Precedence::PrecAnd),
-            5 => Some(Precedence::PrecEquality),
-            6 => Some(Precedence::PrecComparison),
-            7 => Some(Precedence::PrecTerm),
-            8 => Some(Precedence::PrecFactor),
-            9 => Some(Precedence::PrecUnary),
-            10 => Some(Precedence::PrecCall),
-            11 => Some(Precedence::PrecPrimary),
-            _ => None
-        }
-    }
-}
-
-fn init_parser(scanner: &mut Scanner, _chunk: &Chunk) -> Parser
-{
-    return Parser
-    {
-        current: Scan_Token(scanner),
-        previous: Make_Token(TokenType::TokenSuper, &scanner),
-        had_error: false,
-        panic_mode: false,
-    };
-
-}
-
-pub struct Compiler<'a, 'b>
-{
-    scanner: Scanner,
-    parser: Parser,
-    current_chunk: &'a mut Chunk,
-    vm: &'b mut VM,
-}
-
-impl Compiler<'_, '_>
-{
-    pub fn new_compiler<'a, 'b>(chunk: &'a mut Chunk, vm: &'b mut VM) -> Compiler<'a, 'b>
-    {
-        Compiler
-        {
-            scanner: scanner::Init_Scanner("".to_string()),
-            parser: Parser::new(),
-            current_chunk: chunk,
-            vm: vm
-        }
-    }
-    
-    pub fn compile(&mut self, source: String) -> bool
-    {
-        self.scanner = scanner::Init_Scanner(source);
-        self.parser = init_parser(&mut self.scanner, self.current_chunk);
-        let _compiling_chunk: Chunk = init_chunk();
-
-        while !self.match_token(TokenType::TokenEof)
-        {
-            self.declaration();
-        }
-
-        self.end_compiler();
-        !self.parser.had_error
-    }
-
-    fn advance(&mut self)
-    {
-        self.parser.previous = self.parser.current.clone();
-
-        loop
-        {
-            self.parser.current = Scan_Token(&mut self.scanner);
-            if self.parser.current.token_type != TokenType::TokenError
-            {
-                break;
-            }
-            self.error_at_current(self.parser.current.start.clone());
-        }
-    }
-
-    fn consume(&mut self, token_type: TokenType, message: String)
-    {
-        if self.parser.current.token_type == token_type
-        {
-            self.advance();
-            return;
-        }
-        self.error_at_current(message);
-    }
-
-    fn check(&mut self, token_type: TokenType) -> bool
-    {
-        self.parser.current.token_type == token_type
-    }
-
-    fn match_token(&mut self, token_type: TokenType) -> bool
-    {
-        if self.parser.current.token_type != token_type
-        {
-            return false;
-        }
-            
-        self.advance();
-        return true;
-    }
-
-    fn grouping(&mut self)
-    {
-        self.expression();
-        self.consume(TokenType::TokenRightParen, "Expect ')' after expression.".to_string());
-    }
-
-    fn binary(&mut self)
-    {
-        let operator_type = self.parser.previous.token_type;
-        let rule = get_rule(operator_type);
-        self.parse_precedence(Precedence::get_precedence(rule.precedence as u8 + 1).unwrap());
-
-        match operator_type
-        {
-            x if x == TokenType::TokenPlus => self.emit_byte(OpCode::OpAdd as u8),
-            x if x == TokenType::TokenMinus => self.emit_byte(OpCode::OpSubtract as u8),
-            x if x == TokenType::TokenStar => self.emit_byte(OpCode::OpMultiply as u8),
-            x if x == TokenType::TokenSlash => self.emit_byte(OpCode::OpDivide as u8),
-            x if x == TokenType::TokenBangEqual => self.emit_bytes(OpCode::OpEqual as u8, OpCode::OpNot as u8),
-            x if x == TokenType::TokenEqualEqual => self.emit_byte(OpCode::OpEqual as u8),
-            x if x == TokenType::TokenGreater => self.emit_byte(OpCode::OpGreater as u8),
-            x if x == TokenType::TokenGreaterEqual => self.emit_bytes(OpCode::OpLess as u8, OpCode::OpNot as u8),
-            x if x == TokenType::TokenLess => self.emit_byte(OpCode::OpLess as u8),
-            x if x == TokenType::TokenLessEqual => self.emit_bytes(OpCode::OpGreater as u8, OpCode::OpNot as u8),
-            _ => return,
-        }
-    }
-
-    fn literal(&mut self)
-    {
-        let operator_type = self.parser.previous.token_type;
-        match operator_type
-        {
-            x if x == TokenType::TokenFalse => self.emit_byte(OpCode::OpFalse as u8),
-            x if x == TokenType::TokenTrue => self.emit_byte(OpCode::OpTrue as u8),
-            x if x == TokenType::TokenNil => self.emit_byte(OpCode::OpNil as u8),
-            _ => return,
-        }
-    }
-
-    fn expression(&mut self)
-    {
-        self.parse_precedence(Precedence::PrecAssignment);
-    }
-
-    fn var_declaration(&mut self)
-    {
-        let global = self.parse_variable("Expect variable name.".to_owned());
-
-        if self.match_token(TokenType::TokenEqual)
-        {
-            self.expression()
-        } else
-        {
-            self.emit_byte(OpCode::OpNil as u8);
-        }
-
-        self.consume(TokenType::TokenSemicolon, "Expect ';' after variable declaration.".to_owned());
-
-        self.define_variable(global);
-    }
-
-    fn expression_statement(&mut self)
-    {
-        self.expression();
-        self.consume(TokenType::TokenSemicolon, "Expect ';' after expression.".to_owned());
-        self.emit_byte(OpCode::OpPop as u8)
-    }
-
-    fn print_statement(&mut self)
-    {
-        self.expression();
-        self.consume(TokenType::TokenSemicolon, "Expect ';' after value.".to_owned());
-        self.emit_byte(OpCode::OpPrint as u8)
-    }
-
-    fn synchronize(&mut self)
-    {
-        self.parser.panic_mode = false;
-
-        while self.parser.current.token_type != TokenType::TokenEof
-        {
-            if self.parser.previous.token_type == TokenType::TokenSemicolon
-            {
-                return
-            }
-
-            match self.parser.current.token_type
-            {
-                x if x == TokenType::TokenClass => return,
-                x if x == TokenType::TokenFun => return,
-                x if x == TokenType::TokenVar => return,
-                x if x == TokenType::TokenFor => return,
-                x if x == TokenType::TokenIf => return,
-                x if x == TokenType::TokenWhile => return,
-                x if x == TokenType::TokenPrint => return,
-                x if x == TokenType::TokenReturn => return,
-                _ => (),
-            }
-
-            self.advance();
-        }
-    }
-
-    fn declaration(&mut self)
-    {
-        if self.match_token(TokenType::TokenVar)
-        {
-            self.var_declaration()
-        }
-        else
-        {
-            self.statement();   
-        }
-
-        if self.parser.panic_mode
-        {
-            self.synchronize();
-        }
-    }
-
-    fn statement(&mut self)
-    {
-        if self.match_token(TokenType::TokenPrint)
-        {
-            self.print_statement();
-        } else
-        {
-            self.expression_statement();
-        }
-    }
-
-    
-    fn number(&mut self)
-    {
-        let value: f64 = self.parser.previous.start.replace(" ", "").parse().unwrap();
-        self.emit_constant(crate::value::NumberAsValue(value));
-    }
-
-    fn string(&mut self)
-    {
-        let val: Value = value::ObjAsValue(
-            Obj::CopyString(
-                &mut self.vm,
-                self.parser.previous.start.clone()[(1 as usize)..((self.parser.previous.length-1) as usize)].to_string(),
-                self.parser.previous.length-2)
-        );
-        self.emit_constant(val);
-    }
-
-    fn variable(&mut self)
-    {
-        self.named_variable(self.parser.previous.clone())
-    }
-
-    fn named_variable(&mut self, name: Token)
-    {
-        let argument: u8 = self.identifier_constant(name);
-
-        if self.match_token(TokenType::TokenEqual)
-        {
-            self.expression();
-            self.emit_bytes(OpCode::OpSetGlobal as u8, argument)
-        } else {
-            self.emit_bytes(OpCode::OpGetGlobal as u8, argument)
-        }
-        self.emit_bytes(OpCode::OpDefineGlobal as u8, argument)
-    }
-
-    fn unary(&mut self)
-    {
-        let operator_type: TokenType = self.parser.previous.token_type;
-
-        self.parse_precedence(Precedence::PrecUnary);
-
-        match operator_type
-        {
-            x if x == TokenType::TokenMinus => self.emit_byte(OpCode::OpNegate as u8),
-            x if x == TokenType::TokenBang => self.emit_byte(OpCode::OpNot as u8),
-            _ => 
-            {
-                return;
-            }
-        }
-    }
-
-
-    fn parse_precedence(&mut self, precedence: Precedence)
-    {
-        self.advance();
-        let prefixRule = get_rule(self.parser.previous.token_type).prefix;
-
-        let can_assign = precedence <= Precedence::PrecAssignment;
-
-        match prefixRule
-        {
-            Some(prefix) => prefix(self),
-            None => self.error("Expect expression".to_owned())
-        }
-
-        while precedence as u8 <= get_rule(self.parser.current.token_type).precedence as u8
-        {
-            self.advance();
-            let infixRule = get_rule(self.parser.previous.token_type).infix;
-            match infixRule
-            {
-                Some(infix) => infix(self),
-                None => (),
-            }
-        }
-    }
-
-    fn identifier_constant(&mut self, name: Token) -> u8
-    {
-        let identifier = Obj::CopyString(
-            self.vm,
-            name.start[(1 as usize)..((name.length-1) as usize)].to_string(),
-            name.length);
-        return self.make_constant(value::ObjAsValue(identifier));
-    }
-
-    fn parse_variable(&mut self, error_message: String) -> u8
-    {
-        self.consume(TokenType::TokenIdentifier, error_message);
-        return self.identifier_constant(self.parser.previous.clone())
-    }
-
-    fn define_variable(&mut self, global: u8)
-    {
-        self.emit_bytes(OpCode::OpDefineGlobal as u8, global);
-    }
-
-    fn make_constant(&mut self, value: Value) -> u8
-    {
-        let constant = add_constant(&mut self.current_chunk, value);
-        if constant > 255
-        {
-            self.error("Too many constants in one chunk.".to_string());
-            return 0;
-        }
-        let constant_byte: u8 = constant.try_into().unwrap();
-        return constant_byte;
-    }
-
-    fn emit_byte(&mut self, byte: u8)
-    {
-        write_chunk(&mut self.current_chunk, byte, self.parser.previous.line)
-    }
-
-    fn emit_bytes(&mut self, byte1: u8, byte2: u8)
-    {
-        self.emit_byte(byte1);
-        self.emit_byte(byte2);
-    }
-
-    fn emit_return(&mut self)
-    {
-        self.emit_byte(OpCode::OpReturn as u8)
-    }
-
-
-
-    fn emit_constant(&mut self, value: Value)
-    {
-        let byte_constant = self.make_constant(value);
-        self.emit_bytes(OpCode::OpConstant as u8, byte_constant);
-    }
-
-    fn end_compiler(&mut self)
-    {
-        self.emit_return();
-        if debug_print_code
-        {
-            if self.parser.had_error
-            {
-                disassemble_chunk(self.current_chunk, "code".to_owned())
-            }
-        }
-    }
-
-    fn error_at_current(&mut self, message: String)
-    {
-        self.error_at(&self.parser.current.clone(), message);
-    }
-
-    fn error(&mut self, message: String)
-    {
-        self.error_at(&self.parser.previous.clone(), message);
-    }
-
-    fn error_at(&mut self, token: &Token, message: String)
-    {
-        if self.parser.panic_mode
-        {
-            return;
-        }
-        self.parser.panic_mode = true;
-        print!("[line {}] Error", token.line);
-        if token.token_type == TokenType::TokenEof
-        {
-            print!(" at end");
-        }
-        else if token.token_type == TokenType::TokenError
-        {
-            
-        }
-        else
-        {
-            print!(" at {}", token.start)
-        }
-        print!(": {}\n", message);
-        self.parser.had_error = true;
-    }
-}
-
-type ParserFn = fn(&mut Compiler) -> ();
-
-#[derive(Copy, Clone)]
-pub struct ParseRule
-{
-    prefix: Option<ParserFn>,
-    infix: Option<ParserFn>,
-    precedence: Precedence
-}
-
-fn get_rule(token_type: TokenType) -> ParseRule
-{
-    parse_rules[token_type as usize]
-}
-
-static parse_rules : [ParseRule; 40] = [
-    ParseRule {prefix: Some(|compiler| compiler.grouping()), infix: None, precedence: Precedence::PrecNone}, //'('
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // ')'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '{'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '}'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // ','
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '.'
-    ParseRule {prefix: Some(|compiler | compiler.unary()), infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecTerm}, // '-'
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecTerm}, // '+'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // ';'
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecFactor}, // '/'
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecFactor}, // '*'
-    ParseRule {prefix: Some(|compiler | compiler.unary()), infix: None, precedence: Precedence::PrecNone}, // '!'
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecEquality}, // '!='
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '='
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecComparison}, // '=='
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecComparison}, // '>'
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecComparison}, // '>='
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecComparison}, // '<'
-    ParseRule {prefix: None, infix: Some(|compiler| compiler.binary()), precedence: Precedence::PrecComparison}, // '<='
-    ParseRule {prefix: Some(|compiler | compiler.variable()), infix: None, precedence: Precedence::PrecNone}, // 'identifier'
-    ParseRule {prefix: Some(|compiler| compiler.string()), infix: None, precedence: Precedence::PrecNone}, // 'string'
-    ParseRule {prefix: Some(|compiler| compiler.number()), infix: None, precedence: Precedence::PrecNone}, // 'number'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'and'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'class'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'else'
-    ParseRule {prefix: Some(|compiler|compiler.literal()), infix: None, precedence: Precedence::PrecNone}, // 'false'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'for'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'fun'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'if'
-    ParseRule {prefix: Some(|compiler|compiler.literal()), infix: None, precedence: Precedence::PrecNone}, // 'nil'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'or'
-    ParseRule {prefix: Some(|compiler|compiler.expression()), infix: None, precedence: Precedence::PrecNone}, // 'print'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'return'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'super'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'this'
-    ParseRule {prefix: Some(|compiler|compiler.literal()), infix: None, precedence: Precedence::PrecNone}, // 'true'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'var'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'while'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'error'
-    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'eof'
-];
-
-
+use std::convert::TryInto;
+
+use crate::chunk::{Chunk, OpCode, add_constant, init_chunk, write_chunk};
+use crate::debug::disassemble_chunk;
+use crate::diagnostic::Diagnostic;
+use crate::scanner::{self, Make_Token, Scan_Token, Scanner, Token, TokenType};
+use crate::value::{self, Value};
+use crate::vm::{VM};
+
+const debug_print_code: bool = false;
+pub(crate) const debug_trace_execution: bool = false;
+
+#[derive(Clone)]
+pub struct Parser
+{
+    current: Token,
+    previous: Token,
+    had_error: bool,
+    panic_mode: bool
+}
+
+impl Parser
+{
+    pub fn new() -> Parser
+    {
+        Parser
+        {
+            current: Token
+            {
+                token_type: TokenType::TokenWhile,
+                line: 79680,
+                length: 0,
+                start: "".to_string(),
+                offset: 0,
+            },
+            previous: Token
+            {
+                token_type: TokenType::TokenWhile,
+                line: 79680,
+                length: 0,
+                start: "".to_string(),
+                offset: 0,
+            },
+            had_error: false,
+            panic_mode: false
+        }
+    }
+}
+
+#[repr(u8)]
+#[derive(Copy, Clone, PartialEq, PartialOrd)]
+pub enum Precedence
+{
+    PrecNone = 1,
+    PrecAssignment = 2,
+    PrecOr = 3,
+    PrecAnd = 4,
+    PrecEquality = 5,
+    PrecComparison = 6,
+    PrecTerm = 7,
+    PrecFactor = 8,
+    PrecUnary = 9,
+    PrecCall = 10,
+    PrecPrimary = 11
+}
+
+impl Precedence
+{
+    pub(crate) const fn get_precedence(precedence: u8) -> Option<Precedence>
+    {
+        match precedence
+        {
+            1 => Some(Precedence::PrecNone),
+            2 => Some(Precedence::PrecAssignment),
+            3 => Some(Precedence::PrecOr),
+            4 => Some(Precedence::PrecAnd),
+            5 => Some(Precedence::PrecEquality),
+            6 => Some(Precedence::PrecComparison),
+            7 => Some(Precedence::PrecTerm),
+            8 => Some(Precedence::PrecFactor),
+            9 => Some(Precedence::PrecUnary),
+            10 => Some(Precedence::PrecCall),
+            11 => Some(Precedence::PrecPrimary),
+            _ => None
+        }
+    }
+}
+
+fn init_parser(scanner: &mut Scanner, _chunk: &Chunk) -> Parser
+{
+    return Parser
+    {
+        current: Scan_Token(scanner),
+        previous: Make_Token(TokenType::TokenSuper, scanner),
+        had_error: false,
+        panic_mode: false,
+    };
+
+}
+
+pub struct Compiler<'a, 'b>
+{
+    scanner: Scanner,
+    parser: Parser,
+    current_chunk: &'a mut Chunk,
+    vm: &'b mut VM,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Compiler<'_, '_>
+{
+    pub fn new_compiler<'a, 'b>(chunk: &'a mut Chunk, vm: &'b mut VM) -> Compiler<'a, 'b>
+    {
+        Compiler
+        {
+            scanner: scanner::Init_Scanner("".to_string()),
+            parser: Parser::new(),
+            current_chunk: chunk,
+            vm: vm,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Compiles `source` into the chunk, or returns every error found.
+    pub fn compile(&mut self, source: String) -> Result<(), Vec<Diagnostic>>
+    {
+        self.scanner = scanner::Init_Scanner(source);
+        self.parser = init_parser(&mut self.scanner, self.current_chunk);
+        let _compiling_chunk: Chunk = init_chunk();
+
+        while !self.match_token(TokenType::TokenEof)
+        {
+            self.declaration();
+        }
+
+        self.end_compiler();
+        if self.parser.had_error
+        {
+            return Err(std::mem::take(&mut self.diagnostics));
+        }
+        return Ok(());
+    }
+
+    fn advance(&mut self)
+    {
+        self.parser.previous = self.parser.current.clone();
+
+        loop
+        {
+            self.parser.current = Scan_Token(&mut self.scanner);
+            if self.parser.current.token_type != TokenType::TokenError
+            {
+                break;
+            }
+            self.error_at_current(self.parser.current.start.clone());
+        }
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: String)
+    {
+        if self.parser.current.token_type == token_type
+        {
+            self.advance();
+            return;
+        }
+        self.error_at_current(message);
+    }
+
+    fn match_token(&mut self, token_type: TokenType) -> bool
+    {
+        if self.parser.current.token_type != token_type
+        {
+            return false;
+        }
+            
+        self.advance();
+        return true;
+    }
+
+    fn grouping(&mut self, _can_assign: bool)
+    {
+        self.expression();
+        self.consume(TokenType::TokenRightParen, "Expect ')' after expression.".to_string());
+    }
+
+    fn binary(&mut self, _can_assign: bool)
+    {
+        let operator_type = self.parser.previous.token_type;
+        let rule = get_rule(operator_type);
+        self.parse_precedence(Precedence::get_precedence(rule.precedence as u8 + 1).unwrap());
+
+        match operator_type
+        {
+            x if x == TokenType::TokenPlus => self.emit_byte(OpCode::OpAdd as u8),
+            x if x == TokenType::TokenMinus => self.emit_byte(OpCode::OpSubtract as u8),
+            x if x == TokenType::TokenStar => self.emit_byte(OpCode::OpMultiply as u8),
+            x if x == TokenType::TokenSlash => self.emit_byte(OpCode::OpDivide as u8),
+            x if x == TokenType::TokenBangEqual => self.emit_bytes(OpCode::OpEqual as u8, OpCode::OpNot as u8),
+            x if x == TokenType::TokenEqualEqual => self.emit_byte(OpCode::OpEqual as u8),
+            x if x == TokenType::TokenGreater => self.emit_byte(OpCode::OpGreater as u8),
+            x if x == TokenType::TokenGreaterEqual => self.emit_bytes(OpCode::OpLess as u8, OpCode::OpNot as u8),
+            x if x == TokenType::TokenLess => self.emit_byte(OpCode::OpLess as u8),
+            x if x == TokenType::TokenLessEqual => self.emit_bytes(OpCode::OpGreater as u8, OpCode::OpNot as u8),
+            _ => return,
+        }
+    }
+
+    fn literal(&mut self, _can_assign: bool)
+    {
+        let operator_type = self.parser.previous.token_type;
+        match operator_type
+        {
+            x if x == TokenType::TokenFalse => self.emit_byte(OpCode::OpFalse as u8),
+            x if x == TokenType::TokenTrue => self.emit_byte(OpCode::OpTrue as u8),
+            x if x == TokenType::TokenNil => self.emit_byte(OpCode::OpNil as u8),
+            _ => return,
+        }
+    }
+
+    fn expression(&mut self)
+    {
+        self.parse_precedence(Precedence::PrecAssignment);
+    }
+
+    fn var_declaration(&mut self)
+    {
+        let global = self.parse_variable("Expect variable name.".to_owned());
+
+        if self.match_token(TokenType::TokenEqual)
+        {
+            self.expression()
+        } else
+        {
+            self.emit_byte(OpCode::OpNil as u8);
+        }
+
+        self.consume(TokenType::TokenSemicolon, "Expect ';' after variable declaration.".to_owned());
+
+        self.define_variable(global);
+    }
+
+    fn expression_statement(&mut self)
+    {
+        self.expression();
+        self.consume(TokenType::TokenSemicolon, "Expect ';' after expression.".to_owned());
+        self.emit_byte(OpCode::OpPop as u8)
+    }
+
+    fn print_statement(&mut self)
+    {
+        self.expression();
+        self.consume(TokenType::TokenSemicolon, "Expect ';' after value.".to_owned());
+        self.emit_byte(OpCode::OpPrint as u8)
+    }
+
+    fn synchronize(&mut self)
+    {
+        self.parser.panic_mode = false;
+
+        while self.parser.current.token_type != TokenType::TokenEof
+        {
+            if self.parser.previous.token_type == TokenType::TokenSemicolon
+            {
+                return
+            }
+
+            match self.parser.current.token_type
+            {
+                x if x == TokenType::TokenClass => return,
+                x if x == TokenType::TokenFun => return,
+                x if x == TokenType::TokenVar => return,
+                x if x == TokenType::TokenFor => return,
+                x if x == TokenType::TokenIf => return,
+                x if x == TokenType::TokenWhile => return,
+                x if x == TokenType::TokenPrint => return,
+                x if x == TokenType::TokenReturn => return,
+                _ => (),
+            }
+
+            self.advance();
+        }
+    }
+
+    fn declaration(&mut self)
+    {
+        if self.match_token(TokenType::TokenVar)
+        {
+            self.var_declaration()
+        }
+        else
+        {
+            self.statement();   
+        }
+
+        if self.parser.panic_mode
+        {
+            self.synchronize();
+        }
+    }
+
+    fn statement(&mut self)
+    {
+        if self.match_token(TokenType::TokenPrint)
+        {
+            self.print_statement();
+        } else
+        {
+            self.expression_statement();
+        }
+    }
+
+    
+    fn number(&mut self, _can_assign: bool)
+    {
+        let value: f64 = self.parser.previous.start.replace(" ", "").parse().unwrap();
+        self.emit_constant(crate::value::NumberAsValue(value));
+    }
+
+    fn string(&mut self, _can_assign: bool)
+    {
+        let contents = &self.parser.previous.start[1..((self.parser.previous.length-1) as usize)];
+        let val: Value = value::ObjAsValue(self.vm.heap.CopyString(contents));
+        self.emit_constant(val);
+    }
+
+    fn variable(&mut self, can_assign: bool)
+    {
+        self.named_variable(self.parser.previous.clone(), can_assign)
+    }
+
+    fn named_variable(&mut self, name: Token, can_assign: bool)
+    {
+        let argument: u8 = self.identifier_constant(name);
+
+        if can_assign && self.match_token(TokenType::TokenEqual)
+        {
+            self.expression();
+            self.emit_bytes(OpCode::OpSetGlobal as u8, argument)
+        } else {
+            self.emit_bytes(OpCode::OpGetGlobal as u8, argument)
+        }
+    }
+
+    fn unary(&mut self, _can_assign: bool)
+    {
+        let operator_type: TokenType = self.parser.previous.token_type;
+
+        self.parse_precedence(Precedence::PrecUnary);
+
+        match operator_type
+        {
+            x if x == TokenType::TokenMinus => self.emit_byte(OpCode::OpNegate as u8),
+            x if x == TokenType::TokenBang => self.emit_byte(OpCode::OpNot as u8),
+            _ => 
+            {
+                return;
+            }
+        }
+    }
+
+
+    fn parse_precedence(&mut self, precedence: Precedence)
+    {
+        self.advance();
+        let prefixRule = get_rule(self.parser.previous.token_type).prefix;
+
+        let can_assign = precedence <= Precedence::PrecAssignment;
+
+        match prefixRule
+        {
+            Some(prefix) => prefix(self, can_assign),
+            None => self.error("Expect expression".to_owned())
+        }
+
+        while precedence as u8 <= get_rule(self.parser.current.token_type).precedence as u8
+        {
+            self.advance();
+            let infixRule = get_rule(self.parser.previous.token_type).infix;
+            if let Some(infix) = infixRule
+            {
+                infix(self, can_assign);
+            }
+        }
+
+        // `a + b = c` would otherwise leave `= c` unparsed.
+        if can_assign && self.match_token(TokenType::TokenEqual)
+        {
+            self.error("Invalid assignment target.".to_owned());
+        }
+    }
+
+    fn identifier_constant(&mut self, name: Token) -> u8
+    {
+        let identifier = self.vm.heap.CopyString(&name.start);
+        return self.make_constant(value::ObjAsValue(identifier));
+    }
+
+    fn parse_variable(&mut self, error_message: String) -> u8
+    {
+        self.consume(TokenType::TokenIdentifier, error_message);
+        return self.identifier_constant(self.parser.previous.clone())
+    }
+
+    fn define_variable(&mut self, global: u8)
+    {
+        self.emit_bytes(OpCode::OpDefineGlobal as u8, global);
+    }
+
+    fn make_constant(&mut self, value: Value) -> u8
+    {
+        let constant = add_constant(self.current_chunk, value);
+        if constant > 255
+        {
+            self.error("Too many constants in one chunk.".to_string());
+            return 0;
+        }
+        let constant_byte: u8 = constant.try_into().unwrap();
+        return constant_byte;
+    }
+
+    fn emit_byte(&mut self, byte: u8)
+    {
+        write_chunk(self.current_chunk, byte, self.parser.previous.line)
+    }
+
+    fn emit_bytes(&mut self, byte1: u8, byte2: u8)
+    {
+        self.emit_byte(byte1);
+        self.emit_byte(byte2);
+    }
+
+    fn emit_return(&mut self)
+    {
+        self.emit_byte(OpCode::OpReturn as u8)
+    }
+
+
+
+    fn emit_constant(&mut self, value: Value)
+    {
+        let byte_constant = self.make_constant(value);
+        self.emit_bytes(OpCode::OpConstant as u8, byte_constant);
+    }
+
+    fn end_compiler(&mut self)
+    {
+        self.emit_return();
+        if debug_print_code
+        {
+            if self.parser.had_error
+            {
+                disassemble_chunk(self.current_chunk, &self.vm.heap, "code".to_owned())
+            }
+        }
+    }
+
+    fn error_at_current(&mut self, message: String)
+    {
+        self.error_at(&self.parser.current.clone(), message);
+    }
+
+    fn error(&mut self, message: String)
+    {
+        self.error_at(&self.parser.previous.clone(), message);
+    }
+
+    fn error_at(&mut self, token: &Token, message: String)
+    {
+        if self.parser.panic_mode
+        {
+            return;
+        }
+        self.parser.panic_mode = true;
+        self.diagnostics.push(Diagnostic::at_token(token, message));
+        self.parser.had_error = true;
+    }
+}
+
+type ParserFn = fn(&mut Compiler, bool) -> ();
+
+#[derive(Copy, Clone)]
+pub struct ParseRule
+{
+    prefix: Option<ParserFn>,
+    infix: Option<ParserFn>,
+    precedence: Precedence
+}
+
+fn get_rule(token_type: TokenType) -> ParseRule
+{
+    parse_rules[token_type as usize]
+}
+
+// Shared with the AST parser so both front ends agree on operator binding.
+pub(crate) fn infix_precedence(token_type: TokenType) -> Precedence
+{
+    get_rule(token_type).precedence
+}
+
+static parse_rules : [ParseRule; 43] = [
+    ParseRule {prefix: Some(|compiler, can_assign| compiler.grouping(can_assign)), infix: None, precedence: Precedence::PrecNone}, //'('
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // ')'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '{'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '}'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // ','
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '.'
+    ParseRule {prefix: Some(|compiler, can_assign| compiler.unary(can_assign)), infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecTerm}, // '-'
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecTerm}, // '+'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // ';'
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecFactor}, // '/'
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecFactor}, // '*'
+    ParseRule {prefix: Some(|compiler, can_assign| compiler.unary(can_assign)), infix: None, precedence: Precedence::PrecNone}, // '!'
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecEquality}, // '!='
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // '='
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecComparison}, // '=='
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecComparison}, // '>'
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecComparison}, // '>='
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecComparison}, // '<'
+    ParseRule {prefix: None, infix: Some(|compiler, can_assign| compiler.binary(can_assign)), precedence: Precedence::PrecComparison}, // '<='
+    ParseRule {prefix: Some(|compiler, can_assign| compiler.variable(can_assign)), infix: None, precedence: Precedence::PrecNone}, // 'identifier'
+    ParseRule {prefix: Some(|compiler, can_assign| compiler.string(can_assign)), infix: None, precedence: Precedence::PrecNone}, // 'string'
+    ParseRule {prefix: Some(|compiler, can_assign| compiler.number(can_assign)), infix: None, precedence: Precedence::PrecNone}, // 'number'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'and'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'class'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'else'
+    ParseRule {prefix: Some(|compiler, can_assign|compiler.literal(can_assign)), infix: None, precedence: Precedence::PrecNone}, // 'false'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'for'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'fun'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'if'
+    ParseRule {prefix: Some(|compiler, can_assign|compiler.literal(can_assign)), infix: None, precedence: Precedence::PrecNone}, // 'nil'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'or'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'print'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'return'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'super'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'this'
+    ParseRule {prefix: Some(|compiler, can_assign|compiler.literal(can_assign)), infix: None, precedence: Precedence::PrecNone}, // 'true'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'var'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'while'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'error'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // 'eof'
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // whitespace
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // newline
+    ParseRule {prefix: None, infix: None, precedence: Precedence::PrecNone}, // comment
+];
+
+
@@ -0,0 +1,358 @@
+use std::{fs, io::{self, StdinLock}, path::Path, sync::{Arc, atomic::{AtomicU64, Ordering}}};
+
+use rust_lox::{chunk::Chunk, debugger::{DebugHook, PauseReason, StepMode, Stepper}, json::Json, protocol::{read_message, write_message}, vm::{InterpretResult, VM}};
+
+const THREAD_ID: f64 = 1.0; // Scripts run on a single thread.
+const GLOBALS_REFERENCE: f64 = 1.0;
+
+// Sends messages to the client. Clones share the sequence number, so output
+// sent from the VM's output hook is numbered along with everything else.
+#[derive(Clone)]
+struct Client
+{
+    seq: Arc<AtomicU64>,
+}
+
+struct Session
+{
+    client: Client,
+    input: StdinLock<'static>,
+    program: Option<(String, Chunk)>, // Path and code, once launched.
+    configured: bool, // The client has sent `configurationDone`.
+    stepper: Stepper,
+    stop_on_entry: bool,
+    entered: bool, // The hook has run at least once.
+    disconnected: bool,
+}
+
+/// Serves one debugging session over stdin and stdout: `launch` names the
+/// script, and it starts once the client sends `configurationDone`.
+///
+/// Lines are numbered from 1, and the only variables are the globals. Scripts
+/// run in a single frame, so `stepIn` pauses at the next line just like `next`,
+/// and `stepOut` behaves like `continue`: it runs to the next breakpoint, or to
+/// the end of the script.
+pub fn serve(mut vm: VM)
+{
+    let client = Client { seq: Arc::new(AtomicU64::new(0)) };
+    let output = client.clone();
+    vm.set_output(Some(Arc::new(move |text: &str|
+    {
+        output.event("output", Json::object(vec![("category", Json::string("stdout")), ("output", Json::string(text))]));
+    })));
+    let mut session = Session
+    {
+        client: client,
+        input: io::stdin().lock(),
+        program: None,
+        configured: false,
+        stepper: Stepper::new(),
+        stop_on_entry: false,
+        entered: false,
+        disconnected: false,
+    };
+
+    while !(session.configured && session.program.is_some())
+    {
+        if !session.serve_one(&mut vm, false)
+        {
+            return;
+        }
+    }
+
+    if !session.stop_on_entry
+    {
+        session.stepper.resume(StepMode::Continue);
+    }
+    let chunk = match &session.program
+    {
+        Some((_, chunk)) => chunk.clone(),
+        None => return,
+    };
+    let exit_code = match vm.debug_chunk(chunk, &mut session)
+    {
+        Ok(InterpretResult::InterpretOk) => 0,
+        _ => 70,
+    };
+    if session.disconnected
+    {
+        return;
+    }
+    session.client.event("exited", Json::object(vec![("exitCode", Json::Number(exit_code as f64))]));
+    session.client.event("terminated", Json::object(vec![]));
+    while session.serve_one(&mut vm, false)
+    {
+    }
+}
+
+impl DebugHook for Session
+{
+    fn on_line(&mut self, vm: &mut VM, line: u32) -> bool
+    {
+        let reason = match self.stepper.should_pause(line)
+        {
+            Some(reason) => reason,
+            None => return true,
+        };
+        let reason = match reason
+        {
+            _ if !self.entered && self.stop_on_entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+        };
+        self.entered = true;
+        self.client.event("stopped", Json::object(vec![
+            ("reason", Json::string(reason)),
+            ("threadId", Json::Number(THREAD_ID)),
+            ("allThreadsStopped", Json::Bool(true)),
+        ]));
+
+        loop
+        {
+            let request = match self.next_request()
+            {
+                Some(request) => request,
+                None => return false,
+            };
+            if let Some(keep_running) = self.request(&request, vm, true)
+            {
+                return keep_running;
+            }
+        }
+    }
+}
+
+impl Session
+{
+    // Handles one request. Returns false once the session is over.
+    fn serve_one(&mut self, vm: &mut VM, paused: bool) -> bool
+    {
+        match self.next_request()
+        {
+            Some(request) => self.request(&request, vm, paused) != Some(false),
+            None => false,
+        }
+    }
+
+    // Returns None at end of input or when the client disconnects.
+    fn next_request(&mut self) -> Option<Json>
+    {
+        loop
+        {
+            match read_message(&mut self.input)
+            {
+                Ok(Some(message)) if message.get("type").and_then(Json::as_str) == Some("request") => return Some(message),
+                Ok(Some(_)) => continue, // Responses to reverse requests we never send.
+                Ok(None) | Err(_) =>
+                {
+                    self.disconnected = true;
+                    return None;
+                }
+            }
+        }
+    }
+
+    // Answers `request`. Returns Some when the program should resume (true) or
+    // stop (false).
+    fn request(&mut self, request: &Json, vm: &mut VM, paused: bool) -> Option<bool>
+    {
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+        match request.get("command").and_then(Json::as_str).unwrap_or("")
+        {
+            "initialize" =>
+            {
+                self.client.respond(request, Json::object(vec![("supportsConfigurationDoneRequest", Json::Bool(true))]));
+            }
+            "launch" if self.program.is_none() => self.launch(request, &arguments, vm),
+            "setBreakpoints" => self.set_breakpoints(request, &arguments),
+            "configurationDone" =>
+            {
+                self.configured = true;
+                self.client.respond(request, Json::Null);
+            }
+            "threads" =>
+            {
+                let thread = Json::object(vec![("id", Json::Number(THREAD_ID)), ("name", Json::string("main"))]);
+                self.client.respond(request, Json::object(vec![("threads", Json::Array(vec![thread]))]));
+            }
+            "stackTrace" if paused =>
+            {
+                let path = self.program.as_ref().map(|(path, _)| path.clone()).unwrap_or_default();
+                let name = Path::new(&path).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+                let source = Json::object(vec![("name", Json::string(&name)), ("path", Json::string(&path))]);
+                let frames: Vec<Json> = vm.frames().iter().enumerate().map(|(index, frame)| Json::object(vec![
+                    ("id", Json::Number(index as f64)),
+                    ("name", Json::string(&frame.name)),
+                    ("line", Json::Number(frame.line as f64)),
+                    ("column", Json::Number(1.0)),
+                    ("source", source.clone()),
+                ])).collect();
+                let total = frames.len() as f64;
+                self.client.respond(request, Json::object(vec![("stackFrames", Json::Array(frames)), ("totalFrames", Json::Number(total))]));
+            }
+            "scopes" if paused =>
+            {
+                let globals = Json::object(vec![
+                    ("name", Json::string("Globals")),
+                    ("variablesReference", Json::Number(GLOBALS_REFERENCE)),
+                    ("expensive", Json::Bool(false)),
+                ]);
+                self.client.respond(request, Json::object(vec![("scopes", Json::Array(vec![globals]))]));
+            }
+            "variables" if paused =>
+            {
+                let variables = if arguments.get("variablesReference").and_then(Json::as_f64) == Some(GLOBALS_REFERENCE)
+                {
+                    vm.globals().iter().map(|(name, value)| Json::object(vec![
+                        ("name", Json::string(name)),
+                        ("value", Json::string(value)),
+                        ("variablesReference", Json::Number(0.0)),
+                    ])).collect()
+                } else
+                {
+                    Vec::new()
+                };
+                self.client.respond(request, Json::object(vec![("variables", Json::Array(variables))]));
+            }
+            "continue" if paused => return self.resume(request, StepMode::Continue),
+            "next" if paused => return self.resume(request, StepMode::StepOver),
+            "stepIn" if paused => return self.resume(request, StepMode::StepIn),
+            "stepOut" if paused => return self.resume(request, StepMode::StepOut),
+            "disconnect" | "terminate" =>
+            {
+                self.client.respond(request, Json::Null);
+                self.disconnected = true;
+                return Some(false);
+            }
+            "stackTrace" | "scopes" | "variables" | "continue" | "next" | "stepIn" | "stepOut" =>
+            {
+                self.client.respond_error(request, "The program is not paused.");
+            }
+            "launch" => self.client.respond_error(request, "A program has already been launched."),
+            command => self.client.respond_error(request, &format!("Unsupported request '{}'.", command)),
+        }
+        return None;
+    }
+
+    // Compiles the program without running it, the same way `rust_lox debug`
+    // does, so both debuggers stop on the same lines. Compile errors are sent
+    // as output, since printing them would corrupt the protocol on stdout.
+    fn launch(&mut self, request: &Json, arguments: &Json, vm: &mut VM)
+    {
+        let path = arguments.get("program").and_then(Json::as_str).unwrap_or("").to_string();
+        let source = match fs::read_to_string(&path)
+        {
+            Ok(source) => source,
+            Err(error) =>
+            {
+                self.client.respond_error(request, &format!("Could not read '{}': {}.", path, error));
+                return;
+            }
+        };
+        let chunk = match vm.try_compile(&source)
+        {
+            Ok(chunk) => chunk,
+            Err(diagnostics) =>
+            {
+                let report: String = diagnostics.iter().map(|diagnostic| diagnostic.to_string() + "\n").collect();
+                self.client.event("output", Json::object(vec![("category", Json::string("stderr")), ("output", Json::string(&report))]));
+                self.client.respond_error(request, "The program has compile errors.");
+                return;
+            }
+        };
+
+        self.stop_on_entry = arguments.get("stopOnEntry").and_then(Json::as_bool).unwrap_or(false);
+        self.program = Some((path, chunk));
+        self.client.respond(request, Json::Null);
+        // Breakpoints can only be placed once there is code to place them in.
+        self.client.event("initialized", Json::Null);
+    }
+
+    // Replaces the breakpoints. Each moves to the first line with code at or
+    // after the one requested; the response says where.
+    fn set_breakpoints(&mut self, request: &Json, arguments: &Json)
+    {
+        let requested: Vec<u32> = arguments.get("breakpoints").and_then(Json::as_array).unwrap_or(&[]).iter()
+            .filter_map(|breakpoint| breakpoint.get("line").and_then(Json::as_f64))
+            .map(|line| line as u32)
+            .collect();
+        let path = arguments.get("source").and_then(|source| source.get("path")).and_then(Json::as_str);
+        let chunk = match &self.program
+        {
+            Some((program, chunk)) if path.map(|path| same_file(path, program)).unwrap_or(true) => Some(chunk),
+            _ => None,
+        };
+
+        self.stepper.clear_breakpoints();
+        let mut breakpoints = Vec::new();
+        for line in requested
+        {
+            let placed = match chunk
+            {
+                Some(chunk) => self.stepper.set_breakpoint(chunk, line),
+                None => None,
+            };
+            breakpoints.push(Json::object(vec![
+                ("verified", Json::Bool(placed.is_some())),
+                ("line", Json::Number(placed.unwrap_or(line) as f64)),
+            ]));
+        }
+        self.client.respond(request, Json::object(vec![("breakpoints", Json::Array(breakpoints))]));
+    }
+
+    fn resume(&mut self, request: &Json, mode: StepMode) -> Option<bool>
+    {
+        let body = if mode == StepMode::Continue { Json::object(vec![("allThreadsContinued", Json::Bool(true))]) } else { Json::Null };
+        self.client.respond(request, body);
+        self.stepper.resume(mode);
+        return Some(true);
+    }
+}
+
+impl Client
+{
+    // Fields that are null are left out; DAP bodies are optional.
+    fn send(&self, kind: &str, mut fields: Vec<(&str, Json)>)
+    {
+        fields.retain(|(_, value)| *value != Json::Null);
+        let seq = self.seq.fetch_add(1, Ordering::Relaxed) + 1;
+        fields.insert(0, ("seq", Json::Number(seq as f64)));
+        fields.insert(1, ("type", Json::string(kind)));
+        // There is no one left to tell if the client has gone away.
+        let _ = write_message(&mut io::stdout().lock(), &Json::object(fields));
+    }
+
+    fn event(&self, event: &str, body: Json)
+    {
+        self.send("event", vec![("event", Json::string(event)), ("body", body)]);
+    }
+
+    fn respond(&self, request: &Json, body: Json)
+    {
+        self.send("response", vec![
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", Json::Bool(true)),
+            ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+            ("body", body),
+        ]);
+    }
+
+    fn respond_error(&self, request: &Json, message: &str)
+    {
+        self.send("response", vec![
+            ("request_seq", request.get("seq").cloned().unwrap_or(Json::Null)),
+            ("success", Json::Bool(false)),
+            ("command", request.get("command").cloned().unwrap_or(Json::Null)),
+            ("message", Json::string(message)),
+        ]);
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool
+{
+    match (Path::new(a).canonicalize(), Path::new(b).canonicalize())
+    {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
@@ -1,72 +1,366 @@
-use std::usize;
-
-use crate::chunk::{Chunk, OpCode};
-use crate::value::{self};
-
-
-pub fn disassemble_chunk(chunk: &Chunk, string: String)
-{
-    println!("{}", string);
-
-    let mut offset: u8 = 0;
-    while (offset as usize) < chunk.code.len()
-    {
-        offset = disassemble_instruction(&chunk, offset);
-    }
-}
-
-pub fn disassemble_instruction(chunk: &Chunk, offset: u8) -> u8
-{
-    print!("{:04} ", offset);
-
-    if offset > 0 && chunk.lines[offset as usize] == chunk.lines[(offset as usize) - 1]
-    {
-        print!("   | ");
-    } else {
-        print!("{:4} ", chunk.lines[offset as usize]);
-    }
-
-    let instruction: u8 = chunk.code[offset as usize];
-    match instruction
-    {
-        x if x == OpCode::OpReturn as u8 => return simple_instruction("OpReturn\n".to_string(), offset),
-        x if x == OpCode::OpConstant as u8 => return constant_instruction("OpConstant\n".to_string(), chunk, offset),
-        x if x == OpCode::OpNegate as u8 => return simple_instruction("OpNegate\n".to_string(), offset),
-        x if x == OpCode::OpAdd as u8 => return simple_instruction("OpAdd\n".to_string(), offset),
-        x if x == OpCode::OpSubtract as u8 => return simple_instruction("OpSubtract\n".to_string(), offset),
-        x if x == OpCode::OpMultiply as u8 => return simple_instruction("OpMultiply\n".to_string(), offset),
-        x if x == OpCode::OpDivide as u8 => return simple_instruction("OpDivide\n".to_string(), offset),
-        x if x == OpCode::OpNil as u8 =>  return simple_instruction("OpNil\n".to_string(), offset),
-        x if x == OpCode::OpTrue as u8 =>  return simple_instruction("OpTrue\n".to_string(), offset),
-        x if x == OpCode::OpFalse as u8 =>  return simple_instruction("OpFalse\n".to_string(), offset),
-        x if x == OpCode::OpNot as u8 =>  return simple_instruction("OpNot\n".to_string(), offset),
-        x if x == OpCode::OpEqual as u8 =>  return simple_instruction("OpEqual\n".to_string(), offset),
-        x if x == OpCode::OpGreater as u8 =>  return simple_instruction("OpGreater\n".to_string(), offset),
-        x if x == OpCode::OpLess as u8 =>  return simple_instruction("OpLess\n".to_string(), offset),
-        x if x == OpCode::OpPrint as u8 =>  return simple_instruction("OpPrint\n".to_string(), offset),
-        x if x == OpCode::OpPop as u8 =>  return simple_instruction("OpPop\n".to_string(), offset),
-        x if x == OpCode::OpDefineGlobal as u8 =>  return constant_instruction("OpDefineVariable\n".to_string(), chunk, offset),
-        x if x == OpCode::OpGetGlobal as u8 =>  return constant_instruction("OpGetGlobal\n".to_string(), chunk, offset),
-        x if x == OpCode::OpSetGlobal as u8 =>  return constant_instruction("OpSetGlobal\n".to_string(), chunk, offset),
-        _ => 
-        {
-            print!("Unknown opcode {}\n", instruction);
-            return offset + 1;
-        }
-    }
-}
-
-pub fn simple_instruction(name: String, offset: u8) -> u8
-{
-    print!("{}", name);
-    return offset + 1;
-}
-
-pub fn constant_instruction(name: String, chunk: &Chunk, offset: u8) -> u8
-{
-    let constant: u8 = chunk.code[offset as usize + 1];
-    print!("{:16}\t{:4} '", name, constant);
-    value::print_value(chunk.constants.values[constant as usize].clone());
-    print!("'\n");
-    return offset + 2;
-}
\ No newline at end of file
+use std::convert::TryFrom;
+
+use crate::chunk::{Chunk, OpCode, has_constant_operand};
+use crate::json::Json;
+use crate::object::Heap;
+use crate::value;
+
+/// One decoded instruction of a chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction
+{
+    pub offset: usize,
+    pub line: u32,
+    pub opcode: u8,
+    pub name: String, // "OpConstant", or "Unknown" for a byte that isn't an opcode.
+    pub operands: Vec<Operand>,
+    pub length: usize, // Bytes, including operands.
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operand
+{
+    Constant { index: u8, preview: String },
+    Missing, // The chunk ends where the operand should be.
+}
+
+/// Decodes every instruction of `chunk`, in order.
+pub fn disassemble(chunk: &Chunk, heap: &Heap) -> Vec<Instruction>
+{
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len()
+    {
+        let instruction = decode_instruction(chunk, heap, offset);
+        offset = offset + instruction.length;
+        instructions.push(instruction);
+    }
+    return instructions;
+}
+
+pub fn decode_instruction(chunk: &Chunk, heap: &Heap, offset: usize) -> Instruction
+{
+    let opcode = chunk.code[offset];
+    let name = match OpCode::try_from(opcode)
+    {
+        Ok(decoded) => format!("{:?}", decoded),
+        Err(_) => "Unknown".to_string(),
+    };
+    let line = chunk.lines.get(offset).copied().unwrap_or(0);
+
+    let mut operands = Vec::new();
+    if has_constant_operand(opcode)
+    {
+        operands.push(match chunk.code.get(offset + 1)
+        {
+            Some(index) =>
+            {
+                let preview = match chunk.constants.values.get(*index as usize)
+                {
+                    Some(constant) => value::value_to_string(*constant, heap),
+                    None => "<out of bounds>".to_string(),
+                };
+                Operand::Constant { index: *index, preview: preview }
+            }
+            None => Operand::Missing,
+        });
+    }
+    let length = if has_constant_operand(opcode) { 2 } else { 1 };
+    return Instruction { offset: offset, line: line, opcode: opcode, name: name, operands: operands, length: length };
+}
+
+/// Formats one instruction like clox: offset, line (or `|` when it repeats the
+/// previous instruction's line), name and operands.
+pub fn render_instruction(instruction: &Instruction, previous_line: Option<u32>) -> String
+{
+    let line = if previous_line == Some(instruction.line) { "   |".to_string() } else { format!("{:4}", instruction.line) };
+    let mut text = format!("{:04} {} ", instruction.offset, line);
+    if instruction.name == "Unknown"
+    {
+        return text + &format!("Unknown opcode {}", instruction.opcode);
+    }
+    text = text + &instruction.name;
+    for operand in &instruction.operands
+    {
+        match operand
+        {
+            Operand::Constant { index, preview } => text = format!("{:<28} {:4} '{}'", text, index, escape_newlines(preview)),
+            Operand::Missing => text = text + " <missing operand>",
+        }
+    }
+    return text;
+}
+
+pub fn render_text(name: &str, instructions: &[Instruction]) -> String
+{
+    let mut text = format!("== {} ==\n", name);
+    let mut previous_line = None;
+    for instruction in instructions
+    {
+        text = text + &render_instruction(instruction, previous_line) + "\n";
+        previous_line = Some(instruction.line);
+    }
+    return text;
+}
+
+/// Lists the constant pool, then each source line followed by the code
+/// compiled from it. Lines that produced no code are shown too, for context.
+pub fn render_listing(name: &str, source: &str, chunk: &Chunk, heap: &Heap) -> String
+{
+    let mut text = format!("== {} ==\n", name);
+    if !chunk.constants.values.is_empty()
+    {
+        text = text + "constants:\n";
+        for (index, constant) in chunk.constants.values.iter().enumerate()
+        {
+            text = text + &format!("{:6} {:<8} {}\n", index, constant_kind(*constant), escape_newlines(&value::value_to_string(*constant, heap)));
+        }
+        text = text + "\n";
+    }
+
+    let lines: Vec<&str> = source.lines().collect();
+    let mut printed = 0; // Source lines listed so far.
+    let mut previous_line = None;
+    for instruction in disassemble(chunk, heap)
+    {
+        let line = instruction.line as usize;
+        if line > printed
+        {
+            while printed < line.min(lines.len())
+            {
+                text = text + &format!("{:4} | {}\n", printed + 1, lines[printed]);
+                printed = printed + 1;
+            }
+            printed = line;
+            previous_line = None;
+        }
+        text = text + "     " + &render_instruction(&instruction, previous_line) + "\n";
+        previous_line = Some(instruction.line);
+    }
+    return text;
+}
+
+// Strings may span lines; keep each instruction on one.
+fn escape_newlines(text: &str) -> String
+{
+    text.replace('\r', "\\r").replace('\n', "\\n")
+}
+
+fn constant_kind(constant: value::Value) -> &'static str
+{
+    if constant.IsNumber() { "number" }
+    else if constant.IsString() { "string" }
+    else if constant.IsBool() { "bool" }
+    else if constant.IsNil() { "nil" }
+    else { "object" }
+}
+
+pub fn render_json(name: &str, instructions: &[Instruction]) -> Json
+{
+    let instructions = instructions.iter().map(|instruction|
+    {
+        let operands = instruction.operands.iter().map(|operand| match operand
+        {
+            Operand::Constant { index, preview } => Json::object(vec![
+                ("constant", Json::Number(*index as f64)),
+                ("preview", Json::string(preview)),
+            ]),
+            Operand::Missing => Json::Null,
+        }).collect();
+        Json::object(vec![
+            ("offset", Json::Number(instruction.offset as f64)),
+            ("line", Json::Number(instruction.line as f64)),
+            ("opcode", Json::string(&instruction.name)),
+            ("operands", Json::Array(operands)),
+        ])
+    }).collect();
+    return Json::object(vec![("name", Json::string(name)), ("instructions", Json::Array(instructions))]);
+}
+
+/// Splits a chunk's instructions into basic blocks: runs that are only entered
+/// at their first instruction and only left after their last.
+///
+/// A block ends after an instruction that leaves the chunk. Straight-line code
+/// has no other way to branch; jump opcodes will also end a block and start one
+/// at their target.
+pub fn basic_blocks(instructions: &[Instruction]) -> Vec<&[Instruction]>
+{
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for (index, instruction) in instructions.iter().enumerate()
+    {
+        if instruction.opcode == OpCode::OpReturn as u8
+        {
+            blocks.push(&instructions[start..=index]);
+            start = index + 1;
+        }
+    }
+    if start < instructions.len()
+    {
+        blocks.push(&instructions[start..]);
+    }
+    return blocks;
+}
+
+/// Renders the control-flow graph of a chunk in Graphviz DOT, one box per basic
+/// block listing its instructions.
+///
+/// Every block ends in OpReturn until there are jumps, so the graph has no
+/// edges yet.
+pub fn render_dot(name: &str, instructions: &[Instruction]) -> String
+{
+    let blocks = basic_blocks(instructions);
+    let mut text = format!("digraph \"{}\" {{\n", dot_escape(name));
+    text = text + "    node [shape=box, fontname=\"monospace\"];\n";
+    for (index, block) in blocks.iter().enumerate()
+    {
+        let mut label = String::new();
+        let mut previous_line = None;
+        for instruction in block.iter()
+        {
+            label = label + &dot_escape(&render_instruction(instruction, previous_line)) + "\\l";
+            previous_line = Some(instruction.line);
+        }
+        text = text + &format!("    block{} [label=\"{}\"];\n", index, label);
+    }
+    return text + "}\n";
+}
+
+// Newlines show as `\n` rather than breaking the label.
+fn dot_escape(text: &str) -> String
+{
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\r', "\\\\r").replace('\n', "\\\\n")
+}
+
+pub fn disassemble_chunk(chunk: &Chunk, heap: &Heap, string: String)
+{
+    print!("{}", render_text(&string, &disassemble(chunk, heap)));
+}
+
+/// Prints the instruction at `offset` and returns the offset of the next one.
+pub fn disassemble_instruction(chunk: &Chunk, heap: &Heap, offset: usize) -> usize
+{
+    let instruction = decode_instruction(chunk, heap, offset);
+    let previous_line = if offset > 0 { chunk.lines.get(offset - 1).copied() } else { None };
+    println!("{}", render_instruction(&instruction, previous_line));
+    return offset + instruction.length;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::optimize::OptLevel;
+    use crate::vm;
+
+    fn compiled(source: &str) -> (Chunk, vm::VM)
+    {
+        let mut vm = vm::init_vm();
+        vm.set_opt_level(OptLevel::O0);
+        let chunk = vm.try_compile(source).expect("the source should compile");
+        return (chunk, vm);
+    }
+
+    #[test]
+    fn disassembles_each_instruction()
+    {
+        let (chunk, vm) = compiled("print 1;\nprint -2;");
+        let instructions = disassemble(&chunk, &vm.heap);
+        let constant = |index: u8, preview: &str| vec![Operand::Constant { index: index, preview: preview.to_string() }];
+        let decoded: Vec<(usize, u32, &str, Vec<Operand>, usize)> = instructions.iter()
+            .map(|instruction| (instruction.offset, instruction.line, instruction.name.as_str(), instruction.operands.clone(), instruction.length))
+            .collect();
+        assert_eq!(decoded, vec![
+            (0, 1, "OpConstant", constant(0, "1"), 2),
+            (2, 1, "OpPrint", vec![], 1),
+            (3, 2, "OpConstant", constant(1, "2"), 2),
+            (5, 2, "OpNegate", vec![], 1),
+            (6, 2, "OpPrint", vec![], 1),
+            (7, 2, "OpReturn", vec![], 1),
+        ]);
+        assert_eq!(render_text("script", &instructions), concat!(
+            "== script ==\n",
+            "0000    1 OpConstant            0 '1'\n",
+            "0002    | OpPrint\n",
+            "0003    2 OpConstant            1 '2'\n",
+            "0005    | OpNegate\n",
+            "0006    | OpPrint\n",
+            "0007    | OpReturn\n",
+        ));
+    }
+
+    #[test]
+    fn reports_unknown_opcodes_and_missing_operands()
+    {
+        let mut chunk = crate::chunk::init_chunk();
+        crate::chunk::write_chunk(&mut chunk, 200, 1);
+        crate::chunk::write_chunk(&mut chunk, OpCode::OpConstant as u8, 1);
+        let instructions = disassemble(&chunk, &Heap::new());
+        assert_eq!(render_text("broken", &instructions), "== broken ==\n0000    1 Unknown opcode 200\n0001    | OpConstant <missing operand>\n");
+    }
+
+    #[test]
+    fn renders_json()
+    {
+        let (chunk, vm) = compiled("print 1;");
+        let json = render_json("script", &disassemble(&chunk, &vm.heap));
+        assert_eq!(json.to_string(), concat!(
+            "{\"name\":\"script\",\"instructions\":[",
+            "{\"offset\":0,\"line\":1,\"opcode\":\"OpConstant\",\"operands\":[{\"constant\":0,\"preview\":\"1\"}]},",
+            "{\"offset\":2,\"line\":1,\"opcode\":\"OpPrint\",\"operands\":[]},",
+            "{\"offset\":3,\"line\":1,\"opcode\":\"OpReturn\",\"operands\":[]}",
+            "]}",
+        ));
+    }
+
+    #[test]
+    fn disassembles_past_255_bytes()
+    {
+        let (chunk, vm) = compiled(&"print 1;\n".repeat(100));
+        assert!(chunk.code.len() > 255);
+        let instructions = disassemble(&chunk, &vm.heap);
+        assert_eq!(instructions.len(), 201);
+        assert_eq!(instructions.iter().map(|instruction| instruction.length).sum::<usize>(), chunk.code.len());
+        let last = &instructions[200];
+        assert_eq!((last.offset, last.line, last.name.as_str()), (300, 101, "OpReturn"));
+        assert_eq!(render_instruction(&instructions[198], None), "0297  100 OpConstant           99 '1'");
+    }
+
+    #[test]
+    fn splits_blocks_after_returns()
+    {
+        let mut chunk = crate::chunk::init_chunk();
+        for opcode in [OpCode::OpNil, OpCode::OpReturn, OpCode::OpTrue, OpCode::OpPop]
+        {
+            crate::chunk::write_chunk(&mut chunk, opcode as u8, 1);
+        }
+        let instructions = disassemble(&chunk, &Heap::new());
+        let blocks = basic_blocks(&instructions);
+        assert_eq!(blocks, vec![&instructions[0..2], &instructions[2..4]]);
+        assert!(render_dot("script", &instructions).contains("block1 [label="));
+        assert!(!render_dot("script", &instructions).contains("->"));
+    }
+
+    #[test]
+    fn renders_dot()
+    {
+        let (chunk, vm) = compiled("print 1;");
+        assert_eq!(render_dot("a \"script\"", &disassemble(&chunk, &vm.heap)), concat!(
+            "digraph \"a \\\"script\\\"\" {\n",
+            "    node [shape=box, fontname=\"monospace\"];\n",
+            "    block0 [label=\"0000    1 OpConstant            0 '1'\\l0002    | OpPrint\\l0003    | OpReturn\\l\"];\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn escapes_newlines_in_constants()
+    {
+        let (chunk, vm) = compiled("print \"one\ntwo\";");
+        let instructions = disassemble(&chunk, &vm.heap);
+        assert_eq!(render_instruction(&instructions[0], None), "0000    2 OpConstant            0 'one\\ntwo'");
+        assert!(render_listing("script", "print \"one\ntwo\";", &chunk, &vm.heap).contains("     0 string   one\\ntwo\n"));
+        assert!(render_dot("script", &instructions).contains("'one\\\\ntwo'"));
+        assert_eq!(dot_escape("a\n\"b\"\\"), "a\\\\n\\\"b\\\"\\\\");
+    }
+}
@@ -0,0 +1,208 @@
+use rust_lox::{chunk::Chunk, diagnostic::Diagnostic, debugger::{DebugHook, PauseReason, StepMode, Stepper}, line_editor::{LineEditor, ReadLine}, vm::{InterpretResult, VM}};
+
+const HELP: &str = "\
+break <line>       Pause when execution reaches <line> (b).
+delete [<line>]    Remove the breakpoint on <line>, or every breakpoint (d).
+info               List the breakpoints.
+step               Run to the next line (s).
+next               Run to the next line, like step: there are no calls yet (n).
+finish             Run to the next breakpoint or the end, like continue (out).
+continue           Run to the next breakpoint (c).
+backtrace          Show the current line; scripts run in a single frame (bt).
+locals             Top-level code has no locals; see globals.
+globals            Show the defined globals and their values.
+print <expr>       Evaluate <expr> against the globals (p).
+list               Show the source around the current line (l).
+quit               Stop the program (q).
+An empty line repeats the last command.";
+
+struct Shell
+{
+    source: Vec<String>,
+    chunk: Chunk, // Kept to place breakpoints on lines that have code.
+    stepper: Stepper,
+    editor: LineEditor,
+    last_command: String,
+}
+
+/// Runs `source` under an interactive debugger, pausing before its first line.
+/// `vm` should not optimize, so every line with code in the source keeps an
+/// instruction; `dap::serve` compiles the same way.
+pub fn debug_shell(mut vm: VM, source: String)
+{
+    let chunk = match vm.compile(source.clone())
+    {
+        Some(chunk) => chunk,
+        None => std::process::exit(65),
+    };
+    let history = LineEditor::default_history_path().with_file_name(".rust_lox_debug_history");
+    let mut shell = Shell
+    {
+        source: source.lines().map(str::to_string).collect(),
+        chunk: chunk.clone(),
+        stepper: Stepper::new(),
+        editor: LineEditor::new(Some(history)),
+        last_command: String::new(),
+    };
+    println!("Type 'help' for a list of commands.");
+
+    match vm.debug_chunk(chunk, &mut shell)
+    {
+        Ok(InterpretResult::InterpretOk) => println!("Program finished."),
+        Ok(InterpretResult::InterpretRuntimeError) => println!("Program stopped by a runtime error."),
+        Ok(_) => (), // Quit from the prompt.
+        Err(error) => println!("{}", error),
+    }
+}
+
+impl DebugHook for Shell
+{
+    fn on_line(&mut self, vm: &mut VM, line: u32) -> bool
+    {
+        let reason = match self.stepper.should_pause(line)
+        {
+            Some(reason) => reason,
+            None => return true,
+        };
+        if reason == PauseReason::Breakpoint
+        {
+            println!("Breakpoint at line {}.", line);
+        }
+        self.show_line(line);
+
+        loop
+        {
+            let mut command = match self.editor.read_line("(debug) ")
+            {
+                Ok(ReadLine::Line(command)) => command.trim().to_string(),
+                Ok(ReadLine::Interrupted) => continue,
+                Ok(ReadLine::Eof) | Err(_) => return false,
+            };
+            if command.is_empty()
+            {
+                command = self.last_command.clone();
+            }
+            self.last_command = command.clone();
+            if let Some(keep_running) = self.command(&command, vm, line)
+            {
+                return keep_running;
+            }
+        }
+    }
+}
+
+impl Shell
+{
+    // Returns Some once the program should resume (true) or stop (false).
+    fn command(&mut self, line: &str, vm: &mut VM, current_line: u32) -> Option<bool>
+    {
+        let (name, argument) = match line.find(char::is_whitespace)
+        {
+            Some(index) => (&line[..index], line[index..].trim()),
+            None => (line, ""),
+        };
+        match (name, argument)
+        {
+            ("", _) => (),
+            ("help", _) => println!("{}", HELP),
+            ("break" | "b", argument) =>
+            {
+                match argument.parse::<u32>()
+                {
+                    Ok(requested) => match self.stepper.set_breakpoint(&self.chunk, requested)
+                    {
+                        Some(line) => println!("Breakpoint at line {}.", line),
+                        None => println!("There is no code on or after line {}.", requested),
+                    },
+                    Err(_) => println!("Usage: break <line>"),
+                }
+            }
+            ("delete" | "d", "") => self.stepper.clear_breakpoints(),
+            ("delete" | "d", argument) =>
+            {
+                match argument.parse::<u32>()
+                {
+                    Ok(line) if self.stepper.clear_breakpoint(line) => (),
+                    Ok(line) => println!("There is no breakpoint on line {}.", line),
+                    Err(_) => println!("Usage: delete [<line>]"),
+                }
+            }
+            ("info", _) =>
+            {
+                let breakpoints: Vec<String> = self.stepper.breakpoints().map(|line| line.to_string()).collect();
+                if breakpoints.is_empty()
+                {
+                    println!("No breakpoints.");
+                } else
+                {
+                    println!("Breakpoints on lines {}.", breakpoints.join(", "));
+                }
+            }
+            ("step" | "s", _) => return self.resume(StepMode::StepIn),
+            ("next" | "n", _) => return self.resume(StepMode::StepOver),
+            ("finish" | "out", _) => return self.resume(StepMode::StepOut),
+            ("continue" | "c", _) => return self.resume(StepMode::Continue),
+            ("backtrace" | "bt", _) =>
+            {
+                for (index, frame) in vm.frames().iter().enumerate()
+                {
+                    println!("#{} {} at line {}", index, frame.name, frame.line);
+                }
+            }
+            ("locals", argument) =>
+            {
+                let frames = vm.frames();
+                match argument.parse::<usize>().unwrap_or(0)
+                {
+                    // Top-level code has no locals; its variables are globals.
+                    index if index < frames.len() => println!("#{} {} has no locals. Use 'globals' for its variables.", index, frames[index].name),
+                    index => println!("There is no frame #{}.", index),
+                }
+            }
+            ("globals", _) =>
+            {
+                for (name, value) in vm.globals()
+                {
+                    println!("{} = {}", name, value);
+                }
+            }
+            ("print" | "p", "") => println!("Usage: print <expr>"),
+            ("print" | "p", expression) =>
+            {
+                match vm.evaluate(expression)
+                {
+                    Ok(Some(value)) => println!("{}", value),
+                    Ok(None) => (), // The VM printed the runtime error.
+                    Err(diagnostics) => diagnostics.iter().for_each(Diagnostic::report),
+                }
+            }
+            ("list" | "l", _) =>
+            {
+                let first = current_line.saturating_sub(3).max(1);
+                for line in first..current_line + 4
+                {
+                    if let Some(text) = self.source.get(line as usize - 1)
+                    {
+                        let marker = if line == current_line { "->" } else if self.stepper.breakpoints().any(|breakpoint| breakpoint == line) { " *" } else { "  " };
+                        println!("{} {:4} | {}", marker, line, text);
+                    }
+                }
+            }
+            ("quit" | "q", _) => return Some(false),
+            _ => println!("Unknown command '{}'. Type 'help' for the list.", line),
+        }
+        return None;
+    }
+
+    fn resume(&mut self, mode: StepMode) -> Option<bool>
+    {
+        self.stepper.resume(mode);
+        return Some(true);
+    }
+
+    fn show_line(&self, line: u32)
+    {
+        let text = self.source.get(line as usize - 1).map(String::as_str).unwrap_or("");
+        println!("{:4} | {}", line, text);
+    }
+}
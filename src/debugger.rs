@@ -0,0 +1,181 @@
+//! What a source-level debugger needs from the VM: a hook `VM::debug_chunk`
+//! calls as execution reaches each source line, and a `Stepper` that decides
+//! from breakpoints and the last step command whether to pause there.
+
+use std::collections::BTreeSet;
+
+use crate::chunk::{Chunk, OpCode};
+use crate::vm::VM;
+
+pub trait DebugHook
+{
+    /// Called before the first instruction of `line` runs, with the VM paused
+    /// there. Returning false stops the script.
+    fn on_line(&mut self, vm: &mut VM, line: u32) -> bool;
+}
+
+/// One entry of the call stack, innermost first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame
+{
+    pub name: String,
+    pub line: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StepMode
+{
+    Continue, // Run to the next breakpoint.
+    StepIn, // Pause at the next line, in whatever frame it is.
+    StepOver, // Pause at the next line of the current frame or a caller.
+    StepOut, // Pause at the next line of a caller.
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PauseReason
+{
+    Breakpoint,
+    Step,
+}
+
+/// Breakpoints and the current step command.
+///
+/// Scripts run in a single frame, so there is nothing to step into or over:
+/// `StepIn` and `StepOver` both pause at the next line, and `StepOut` runs to
+/// the end of the script unless a breakpoint is hit first.
+pub struct Stepper
+{
+    breakpoints: BTreeSet<u32>,
+    mode: StepMode,
+}
+
+impl Stepper
+{
+    /// Starts in `StepIn` mode, so execution pauses on the first line.
+    pub fn new() -> Stepper
+    {
+        Stepper { breakpoints: BTreeSet::new(), mode: StepMode::StepIn }
+    }
+
+    /// Sets a breakpoint on the first line at or after `line` that has code, and
+    /// returns that line, or None if no line from there on has code.
+    pub fn set_breakpoint(&mut self, chunk: &Chunk, line: u32) -> Option<u32>
+    {
+        let line = *breakable_lines(chunk).range(line..).next()?;
+        self.breakpoints.insert(line);
+        return Some(line);
+    }
+
+    /// Returns true if there was a breakpoint on `line`.
+    pub fn clear_breakpoint(&mut self, line: u32) -> bool
+    {
+        self.breakpoints.remove(&line)
+    }
+
+    pub fn clear_breakpoints(&mut self)
+    {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u32> + '_
+    {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn resume(&mut self, mode: StepMode)
+    {
+        self.mode = mode;
+    }
+
+    /// Whether to pause on reaching `line`, and why.
+    pub fn should_pause(&self, line: u32) -> Option<PauseReason>
+    {
+        match self.mode
+        {
+            StepMode::StepIn | StepMode::StepOver => Some(PauseReason::Step),
+            _ if self.breakpoints.contains(&line) => Some(PauseReason::Breakpoint),
+            _ => None,
+        }
+    }
+}
+
+/// Lines with code a debugger can pause at. The `OpReturn` that ends a chunk
+/// doesn't count, since the hook isn't called for it.
+pub fn breakable_lines(chunk: &Chunk) -> BTreeSet<u32>
+{
+    let mut lines = BTreeSet::new();
+    let mut offset = 0;
+    while offset < chunk.code.len()
+    {
+        let opcode = chunk.code[offset];
+        if opcode != OpCode::OpReturn as u8
+        {
+            lines.insert(chunk.lines[offset]);
+        }
+        offset = offset + if crate::chunk::has_constant_operand(opcode) { 2 } else { 1 };
+    }
+    return lines;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::optimize::OptLevel;
+    use crate::vm;
+
+    // Line 2 is blank, line 4 is a comment, and the statement from line 5 ends on line 6.
+    const SOURCE: &str = "var a = 1;\n\nprint a;\n// Done.\nvar b = a\n  + 2;\n";
+
+    fn chunk() -> Chunk
+    {
+        let mut vm = vm::init_vm();
+        vm.set_opt_level(OptLevel::O0);
+        return vm.try_compile(SOURCE).expect("the source should compile");
+    }
+
+    #[test]
+    fn lists_the_lines_with_code()
+    {
+        assert_eq!(breakable_lines(&chunk()).into_iter().collect::<Vec<u32>>(), vec![1, 3, 5, 6]);
+    }
+
+    #[test]
+    fn breakpoints_move_to_the_next_line_with_code()
+    {
+        let chunk = chunk();
+        let mut stepper = Stepper::new();
+        assert_eq!(stepper.set_breakpoint(&chunk, 1), Some(1));
+        assert_eq!(stepper.set_breakpoint(&chunk, 2), Some(3));
+        assert_eq!(stepper.set_breakpoint(&chunk, 4), Some(5));
+        assert_eq!(stepper.set_breakpoint(&chunk, 7), None);
+        assert_eq!(stepper.breakpoints().collect::<Vec<u32>>(), vec![1, 3, 5]);
+
+        assert!(stepper.clear_breakpoint(3));
+        assert!(!stepper.clear_breakpoint(3));
+        assert_eq!(stepper.breakpoints().collect::<Vec<u32>>(), vec![1, 5]);
+        stepper.clear_breakpoints();
+        assert_eq!(stepper.breakpoints().count(), 0);
+    }
+
+    #[test]
+    fn steps_pause_on_every_line_and_the_rest_at_breakpoints()
+    {
+        let mut stepper = Stepper::new();
+        stepper.set_breakpoint(&chunk(), 3);
+        assert_eq!(stepper.should_pause(1), Some(PauseReason::Step));
+
+        for mode in [StepMode::StepIn, StepMode::StepOver]
+        {
+            stepper.resume(mode);
+            assert_eq!(stepper.should_pause(1), Some(PauseReason::Step));
+            assert_eq!(stepper.should_pause(3), Some(PauseReason::Step));
+        }
+        for mode in [StepMode::Continue, StepMode::StepOut]
+        {
+            stepper.resume(mode);
+            assert_eq!(stepper.should_pause(1), None);
+            assert_eq!(stepper.should_pause(3), Some(PauseReason::Breakpoint));
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt;
+
+use crate::ast::Span;
+use crate::scanner::{Token, TokenType};
+
+/// A compile error, or any other finding tied to a place in the source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic
+{
+    pub span: Span,
+    pub message: String,
+    pub location: String, // " at end", " at x", or empty for scanner errors.
+    pub severity: Severity,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Severity
+{
+    Error, // Stops the code from compiling.
+    Warning, // Compiles, but is probably a mistake; see `lint`.
+}
+
+impl Diagnostic
+{
+    pub fn at_token(token: &Token, message: String) -> Diagnostic
+    {
+        let location = match token.token_type
+        {
+            TokenType::TokenEof => " at end".to_string(),
+            TokenType::TokenError => "".to_string(),
+            _ => format!(" at {}", token.start),
+        };
+        Diagnostic { span: Span::from_token(token), message: message, location: location, severity: Severity::Error }
+    }
+
+    pub fn at_span(span: Span, message: String) -> Diagnostic
+    {
+        Diagnostic { span: span, message: message, location: "".to_string(), severity: Severity::Error }
+    }
+
+    pub fn warning(span: Span, message: String) -> Diagnostic
+    {
+        Diagnostic { span: span, message: message, location: "".to_string(), severity: Severity::Warning }
+    }
+
+    pub fn report(&self)
+    {
+        println!("{}", self);
+    }
+}
+
+impl fmt::Display for Diagnostic
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        let severity = match self.severity
+        {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        };
+        write!(f, "[line {}] {}{}: {}", self.span.line, severity, self.location, self.message)
+    }
+}
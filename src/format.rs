@@ -0,0 +1,283 @@
+//! The canonical layout of Lox source, produced from the lossless token stream.
+//!
+//! Layout is decided by the tokens alone: line breaks and spacing in the input
+//! are discarded, except that comments are kept where they were (at the end
+//! of a line or on a line of their own) and a run of blank lines between
+//! statements becomes one blank line. So formatted code formats to itself.
+
+use crate::diagnostic::Diagnostic;
+use crate::scanner::{Lexer, Token, TokenType};
+
+/// Lines are broken after an operator or comma once they would get longer.
+pub const MAX_WIDTH: usize = 80;
+const INDENT: &str = "  ";
+
+#[derive(Clone)]
+struct Formatter
+{
+    output: String,
+    column: usize, // Characters on the current output line.
+    indent: usize, // Block depth.
+    parens: usize, // Open parentheses; `;` only ends a statement outside them.
+    at_line_start: bool, // Nothing but the line break has been written yet.
+    boundary: bool, // Between statements: after `;`, `{` or `}`, or at the start.
+    continuation: bool, // The current line continues a broken statement.
+    previous: Option<TokenType>,
+    previous_unary: bool,
+    measuring: bool, // A copy working out how long a line would get; it never breaks.
+}
+
+/// Formats `source`, or returns the scanner's errors if it has any. Only the
+/// tokens need to be valid, so code the compiler rejects can still be formatted.
+pub fn format(source: &str) -> Result<String, Vec<Diagnostic>>
+{
+    let tokens: Vec<Token> = Lexer::lossless(source).collect();
+    let errors: Vec<Diagnostic> = tokens.iter()
+        .filter(|token| token.token_type == TokenType::TokenError)
+        .map(|token| Diagnostic::at_token(token, token.start.clone()))
+        .collect();
+    if !errors.is_empty()
+    {
+        return Err(errors);
+    }
+
+    let mut formatter = Formatter
+    {
+        output: String::new(),
+        column: 0,
+        indent: 0,
+        parens: 0,
+        at_line_start: true,
+        boundary: true,
+        continuation: false,
+        previous: None,
+        previous_unary: false,
+        measuring: false,
+    };
+    let mut newlines = 0; // Line breaks in the source since the last token.
+    for (index, token) in tokens.iter().enumerate()
+    {
+        match token.token_type
+        {
+            TokenType::TokenWhitespace => (),
+            TokenType::TokenNewline => newlines = newlines + 1,
+            TokenType::TokenComment =>
+            {
+                formatter.comment(token.start.trim_end(), newlines);
+                newlines = 0;
+            }
+            _ =>
+            {
+                formatter.token(token, newlines, &tokens[index + 1..]);
+                newlines = 0;
+            }
+        }
+    }
+
+    let mut output = formatter.output;
+    while output.ends_with('\n')
+    {
+        output.pop();
+    }
+    if !output.is_empty()
+    {
+        output.push('\n');
+    }
+    return Ok(output);
+}
+
+impl Formatter
+{
+    // `rest` is the tokens after `token`, to see how much of them will have to
+    // share its line.
+    fn token(&mut self, token: &Token, newlines: usize, rest: &[Token])
+    {
+        let token_type = token.token_type;
+        let joins_brace = self.previous == Some(TokenType::TokenRightBrace) && token_type == TokenType::TokenElse;
+        if token_type == TokenType::TokenRightBrace
+        {
+            self.indent = self.indent.saturating_sub(1);
+        }
+        if (self.boundary && !joins_brace) || token_type == TokenType::TokenRightBrace
+        {
+            self.start_statement(newlines, token_type == TokenType::TokenRightBrace);
+        }
+
+        if self.at_line_start
+        {
+            self.write_indent();
+        } else
+        {
+            let space = joins_brace || self.space_before(token_type);
+            if !self.measuring && self.breaks_after_previous() && self.run_end(token, rest) > MAX_WIDTH
+            {
+                self.line_break();
+                self.continuation = true;
+                self.write_indent();
+            } else if space
+            {
+                self.write(" ");
+            }
+        }
+        self.write(&token.start);
+
+        self.previous_unary = matches!(token_type, TokenType::TokenMinus | TokenType::TokenBang) && !self.ends_operand();
+        self.previous = Some(token_type);
+        self.boundary = false;
+        match token_type
+        {
+            TokenType::TokenLeftParen => self.parens = self.parens + 1,
+            TokenType::TokenRightParen => self.parens = self.parens.saturating_sub(1),
+            TokenType::TokenSemicolon if self.parens == 0 => self.end_statement(),
+            TokenType::TokenLeftBrace =>
+            {
+                self.indent = self.indent + 1;
+                self.end_statement();
+            }
+            TokenType::TokenRightBrace => self.end_statement(),
+            _ => (),
+        }
+    }
+
+    // The column the line would reach with `token` and the tokens after it,
+    // up to the next place the line may break, written on it.
+    fn run_end(&self, token: &Token, rest: &[Token]) -> usize
+    {
+        let mut probe = Formatter { output: String::new(), measuring: true, ..self.clone() };
+        probe.token(token, 0, &[]);
+        for next in rest
+        {
+            if probe.boundary || probe.breaks_after_previous()
+            {
+                break;
+            }
+            match next.token_type
+            {
+                TokenType::TokenWhitespace | TokenType::TokenNewline => (),
+                TokenType::TokenComment => break,
+                _ => probe.token(next, 0, &[]),
+            }
+        }
+        return probe.column;
+    }
+
+    // A comment stays at the end of the line it was on, or on a line of its own.
+    fn comment(&mut self, text: &str, newlines: usize)
+    {
+        if newlines == 0 && !self.at_line_start
+        {
+            self.write(" ");
+        } else
+        {
+            if self.boundary
+            {
+                self.start_statement(newlines, false);
+            } else
+            {
+                if !self.at_line_start
+                {
+                    self.line_break();
+                }
+                self.continuation = true;
+            }
+            self.write_indent();
+        }
+        self.write(text);
+        self.line_break();
+        if !self.boundary
+        {
+            self.continuation = true;
+        }
+    }
+
+    // Moves to a fresh line for the next statement, keeping one blank line if
+    // the source had any, except just inside braces.
+    fn start_statement(&mut self, newlines: usize, closes_block: bool)
+    {
+        if !self.at_line_start
+        {
+            self.line_break();
+        }
+        let after_open = self.previous == Some(TokenType::TokenLeftBrace) && self.output.ends_with("{\n");
+        if newlines >= 2 && !self.output.is_empty() && !self.output.ends_with("\n\n") && !after_open && !closes_block
+        {
+            self.output.push('\n');
+        }
+    }
+
+    fn end_statement(&mut self)
+    {
+        self.boundary = true;
+        self.continuation = false;
+    }
+
+    fn space_before(&self, token_type: TokenType) -> bool
+    {
+        match token_type
+        {
+            TokenType::TokenSemicolon | TokenType::TokenComma | TokenType::TokenRightParen | TokenType::TokenDot => return false,
+            _ => (),
+        }
+        match self.previous
+        {
+            Some(TokenType::TokenLeftParen) | Some(TokenType::TokenDot) => return false,
+            // `- -a` keeps its space so it doesn't read as `--a`.
+            Some(TokenType::TokenMinus) if self.previous_unary => return token_type == TokenType::TokenMinus,
+            _ if self.previous_unary => return false,
+            _ => (),
+        }
+        // No space between a callee and its arguments.
+        if token_type == TokenType::TokenLeftParen && self.ends_operand() && self.previous != Some(TokenType::TokenNumber) && self.previous != Some(TokenType::TokenString)
+        {
+            return false;
+        }
+        return true;
+    }
+
+    // Whether the previous token can end an operand, which makes a following
+    // `-` binary rather than unary.
+    fn ends_operand(&self) -> bool
+    {
+        matches!(self.previous, Some(TokenType::TokenIdentifier) | Some(TokenType::TokenNumber) | Some(TokenType::TokenString) |
+            Some(TokenType::TokenRightParen) | Some(TokenType::TokenTrue) | Some(TokenType::TokenFalse) | Some(TokenType::TokenNil) |
+            Some(TokenType::TokenThis) | Some(TokenType::TokenSuper))
+    }
+
+    // Lines may be broken after binary operators, `=` and commas.
+    fn breaks_after_previous(&self) -> bool
+    {
+        if self.previous_unary
+        {
+            return false;
+        }
+        matches!(self.previous, Some(TokenType::TokenPlus) | Some(TokenType::TokenMinus) | Some(TokenType::TokenStar) |
+            Some(TokenType::TokenSlash) | Some(TokenType::TokenEqual) | Some(TokenType::TokenEqualEqual) |
+            Some(TokenType::TokenBangEqual) | Some(TokenType::TokenLess) | Some(TokenType::TokenLessEqual) |
+            Some(TokenType::TokenGreater) | Some(TokenType::TokenGreaterEqual) | Some(TokenType::TokenAnd) |
+            Some(TokenType::TokenOr) | Some(TokenType::TokenComma))
+    }
+
+    fn write_indent(&mut self)
+    {
+        let levels = self.indent + if self.continuation { 2 } else { 0 };
+        self.write(&INDENT.repeat(levels));
+    }
+
+    fn write(&mut self, text: &str)
+    {
+        self.output.push_str(text);
+        self.column = match text.rfind('\n')
+        {
+            Some(index) => text[index + 1..].chars().count(),
+            None => self.column + text.chars().count(),
+        };
+        self.at_line_start = false;
+    }
+
+    fn line_break(&mut self)
+    {
+        self.output.push('\n');
+        self.column = 0;
+        self.at_line_start = true;
+    }
+}
@@ -0,0 +1,230 @@
+use std::cmp::Ordering;
+
+use crate::ast::{BinaryOp, Expr, Literal, Stmt, UnaryOp};
+use crate::diagnostic::Diagnostic;
+use crate::native::{self, Capabilities};
+use crate::object::Heap;
+use crate::parser;
+use crate::table::Table;
+use crate::value::{self, Value, print_value, value_to_string};
+use crate::vm::InterpretResult;
+
+/// Reference interpreter that evaluates the AST directly.
+///
+/// It uses the same `Value` representation and prints values and runtime errors
+/// exactly like `VM`, so running a script through both engines and diffing the
+/// output checks the bytecode compiler against a much simpler implementation.
+pub struct Interpreter
+{
+    globals: Table<Value>,
+    heap: Heap,
+    missing_capabilities: Table<&'static str>, // Natives left out, and the capability each needs.
+}
+
+struct RuntimeError
+{
+    message: String,
+    line: u32,
+}
+
+impl Interpreter
+{
+    pub fn new() -> Interpreter
+    {
+        Interpreter { globals: Table::new(), heap: Heap::new(), missing_capabilities: Table::new() }
+    }
+
+    /// Like `VM::with_capabilities`: the globals include the natives of every
+    /// module `capabilities` allows.
+    pub fn with_capabilities(capabilities: &Capabilities) -> Interpreter
+    {
+        let mut interpreter = Interpreter::new();
+        for native in capabilities.natives()
+        {
+            let value = value::ObjAsValue(interpreter.heap.AllocateNative(native.name, native.arity));
+            let name = interpreter.heap.CopyString(native.name);
+            interpreter.globals.set(name, value);
+        }
+        for (name, capability) in capabilities.missing()
+        {
+            let name = interpreter.heap.CopyString(name);
+            interpreter.missing_capabilities.set(name, capability);
+        }
+        return interpreter;
+    }
+
+    pub fn interpret(&mut self, source: String) -> InterpretResult
+    {
+        let program = match parser::parse(&source)
+        {
+            Ok(program) => program,
+            Err(diagnostics) =>
+            {
+                diagnostics.iter().for_each(Diagnostic::report);
+                return InterpretResult::InterpretCompileError;
+            }
+        };
+
+        for statement in &program
+        {
+            if let Err(error) = self.execute(statement)
+            {
+                println!("[line {}] in script", error.line);
+                println!("{}", error.message);
+                return InterpretResult::InterpretRuntimeError;
+            }
+        }
+        return InterpretResult::InterpretOk;
+    }
+
+    /// Each defined global's name and printed value, sorted by name.
+    pub fn globals(&self) -> Vec<(String, String)>
+    {
+        let mut globals: Vec<(String, String)> = self.globals.iter()
+            .map(|(name, value)| (self.heap.GetString(name).str.clone(), value_to_string(value, &self.heap)))
+            .collect();
+        globals.sort();
+        return globals;
+    }
+
+    fn execute(&mut self, statement: &Stmt) -> Result<(), RuntimeError>
+    {
+        match statement
+        {
+            Stmt::Expression { expr, .. } =>
+            {
+                self.evaluate(expr)?;
+            }
+            Stmt::Print { expr, .. } =>
+            {
+                let value = self.evaluate(expr)?;
+                print_value(value, &self.heap);
+                println!("");
+            }
+            Stmt::Var { name, initializer, .. } =>
+            {
+                let value = match initializer
+                {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => value::NilAsValue(),
+                };
+                let name = self.heap.CopyString(name);
+                self.globals.set(name, value);
+            }
+        }
+        return Ok(());
+    }
+
+    // Errors are reported on the line the VM would give: the line of the last
+    // token of the expression that failed.
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError>
+    {
+        let line = expr.span().end_line;
+        match expr
+        {
+            Expr::Literal { value, .. } => Ok(literal_value(value, &mut self.heap)),
+            Expr::Grouping { expr, .. } => self.evaluate(expr),
+            Expr::Unary { operator, operand, .. } =>
+            {
+                let operand = self.evaluate(operand)?;
+                match operator
+                {
+                    UnaryOp::Negate =>
+                    {
+                        if !operand.IsNumber()
+                        {
+                            return Err(RuntimeError { message: "Operand must be a number.".to_string(), line: line });
+                        }
+                        Ok(value::NumberAsValue(-operand.GetNumber()))
+                    }
+                    UnaryOp::Not => Ok(value::BoolAsValue(operand.IsFalsey())),
+                }
+            }
+            Expr::Binary { operator, left, right, .. } =>
+            {
+                let a = self.evaluate(left)?;
+                let b = self.evaluate(right)?;
+                binary(*operator, a, b, &mut self.heap).map_err(|message| RuntimeError { message: message, line: line })
+            }
+            Expr::Variable { name, span } =>
+            {
+                let key = self.heap.CopyString(name);
+                match self.globals.get(key)
+                {
+                    Some(value) => Ok(value),
+                    None => Err(self.undefined_variable(name, span.line)),
+                }
+            }
+            Expr::Assign { name, value, .. } =>
+            {
+                let value = self.evaluate(value)?;
+                let key = self.heap.CopyString(name);
+                if self.globals.set(key, value)
+                {
+                    self.globals.delete(key);
+                    return Err(self.undefined_variable(name, line));
+                }
+                Ok(value)
+            }
+        }
+    }
+
+    fn undefined_variable(&mut self, name: &str, line: u32) -> RuntimeError
+    {
+        let capability = self.missing_capabilities.get(self.heap.CopyString(name));
+        RuntimeError { message: native::undefined_variable(name, capability), line: line }
+    }
+}
+
+fn literal_value(literal: &Literal, heap: &mut Heap) -> Value
+{
+    match literal
+    {
+        Literal::Number(number) => value::NumberAsValue(*number),
+        Literal::String(string) => value::ObjAsValue(heap.CopyString(string)),
+        Literal::Bool(boolean) => value::BoolAsValue(*boolean),
+        Literal::Nil => value::NilAsValue(),
+    }
+}
+
+fn binary(operator: BinaryOp, a: Value, b: Value, heap: &mut Heap) -> Result<Value, String>
+{
+    match operator
+    {
+        BinaryOp::Equal => return Ok(value::BoolAsValue(a.Equals(&b))),
+        BinaryOp::NotEqual => return Ok(value::BoolAsValue(!a.Equals(&b))),
+        BinaryOp::Add =>
+        {
+            if a.IsString() && b.IsString()
+            {
+                let both = heap.GetString(a.GetObject()).str.clone() + &heap.GetString(b.GetObject()).str;
+                return Ok(value::ObjAsValue(heap.TakeString(both)));
+            }
+            if !a.IsNumber() || !b.IsNumber()
+            {
+                return Err("Operands must be numbers or strings.".to_string());
+            }
+        }
+        _ =>
+        {
+            if !a.IsNumber() || !b.IsNumber()
+            {
+                return Err("Operands must be numbers.".to_string());
+            }
+        }
+    }
+
+    let (a, b) = (a.GetNumber(), b.GetNumber());
+    match operator
+    {
+        BinaryOp::Add => Ok(value::NumberAsValue(a + b)),
+        BinaryOp::Subtract => Ok(value::NumberAsValue(a - b)),
+        BinaryOp::Multiply => Ok(value::NumberAsValue(a * b)),
+        BinaryOp::Divide => Ok(value::NumberAsValue(a / b)),
+        BinaryOp::Greater => Ok(value::BoolAsValue(a > b)),
+        // `>=` and `<=` compile to a negated `<`/`>`, which differs from Rust's operators for NaN.
+        BinaryOp::GreaterEqual => Ok(value::BoolAsValue(a.partial_cmp(&b) != Some(Ordering::Less))),
+        BinaryOp::Less => Ok(value::BoolAsValue(a < b)),
+        _ => Ok(value::BoolAsValue(a.partial_cmp(&b) != Some(Ordering::Greater))),
+    }
+}
@@ -0,0 +1,406 @@
+//! A minimal JSON value for the crate's machine-readable output and for the
+//! messages of the debug adapter and language server.
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json
+{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // Keys keep their insertion order.
+}
+
+impl Json
+{
+    /// Builds an object from `(key, value)` pairs.
+    pub fn object(fields: Vec<(&str, Json)>) -> Json
+    {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    pub fn string(string: &str) -> Json
+    {
+        Json::String(string.to_string())
+    }
+
+    /// Parses a complete JSON document.
+    pub fn parse(text: &str) -> Result<Json, String>
+    {
+        let mut parser = JsonParser { chars: text.chars().collect(), position: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len()
+        {
+            return Err(parser.error("Unexpected text after the value"));
+        }
+        return Ok(value);
+    }
+
+    /// The field `key` of an object, or None for a missing key or a non-object.
+    pub fn get(&self, key: &str) -> Option<&Json>
+    {
+        match self
+        {
+            Json::Object(fields) => fields.iter().find(|(name, _)| name == key).map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str>
+    {
+        match self
+        {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64>
+    {
+        match self
+        {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool>
+    {
+        match self
+        {
+            Json::Bool(boolean) => Some(*boolean),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]>
+    {
+        match self
+        {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+struct JsonParser
+{
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl JsonParser
+{
+    fn value(&mut self) -> Result<Json, String>
+    {
+        self.skip_whitespace();
+        match self.peek()
+        {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(character) if character == '-' || character.is_ascii_digit() => self.number(),
+            Some(_) => Err(self.error("Expected a value")),
+            None => Err(self.error("Unexpected end of input")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, String>
+    {
+        self.position = self.position + 1;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}')
+        {
+            self.position = self.position + 1;
+            return Ok(Json::Object(fields));
+        }
+        loop
+        {
+            self.skip_whitespace();
+            if self.peek() != Some('"')
+            {
+                return Err(self.error("Expected a string key"));
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next()
+            {
+                Some(',') => continue,
+                Some('}') => return Ok(Json::Object(fields)),
+                _ => return Err(self.error("Expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, String>
+    {
+        self.position = self.position + 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']')
+        {
+            self.position = self.position + 1;
+            return Ok(Json::Array(items));
+        }
+        loop
+        {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()
+            {
+                Some(',') => continue,
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err(self.error("Expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String>
+    {
+        self.position = self.position + 1;
+        let mut string = String::new();
+        loop
+        {
+            match self.next()
+            {
+                Some('"') => return Ok(string),
+                Some('\\') =>
+                {
+                    let escaped = match self.next()
+                    {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.unicode_escape()?,
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    string.push(escaped);
+                }
+                Some(character) => string.push(character),
+                None => return Err(self.error("Unterminated string")),
+            }
+        }
+    }
+
+    // After `\u`. Characters outside the Basic Multilingual Plane come as a
+    // surrogate pair of escapes; unpaired surrogates become U+FFFD.
+    fn unicode_escape(&mut self) -> Result<char, String>
+    {
+        let high = self.hex4()?;
+        if (0xd800..0xdc00).contains(&high) && self.chars.get(self.position..self.position + 2) == Some(&['\\', 'u'][..])
+        {
+            self.position = self.position + 2;
+            let low = self.hex4()?;
+            if (0xdc00..0xe000).contains(&low)
+            {
+                let code = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                return Ok(char::from_u32(code).unwrap_or('\u{fffd}'));
+            }
+            // Not a pair: leave the second escape to be read on its own.
+            self.position = self.position - 6;
+        }
+        return Ok(char::from_u32(high).unwrap_or('\u{fffd}'));
+    }
+
+    fn hex4(&mut self) -> Result<u32, String>
+    {
+        let mut code = 0;
+        for _ in 0..4
+        {
+            match self.next().and_then(|digit| digit.to_digit(16))
+            {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(self.error("Invalid \\u escape")),
+            }
+        }
+        return Ok(code);
+    }
+
+    fn number(&mut self) -> Result<Json, String>
+    {
+        let start = self.position;
+        while let Some(character) = self.peek()
+        {
+            if !(character.is_ascii_digit() || matches!(character, '-' | '+' | '.' | 'e' | 'E'))
+            {
+                break;
+            }
+            self.position = self.position + 1;
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        return text.parse::<f64>().map(Json::Number).map_err(|_| self.error("Invalid number"));
+    }
+
+    fn keyword(&mut self, keyword: &str, value: Json) -> Result<Json, String>
+    {
+        for expected in keyword.chars()
+        {
+            if self.next() != Some(expected)
+            {
+                return Err(self.error("Expected a value"));
+            }
+        }
+        return Ok(value);
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String>
+    {
+        if self.next() != Some(expected)
+        {
+            return Err(self.error(&format!("Expected '{}'", expected)));
+        }
+        return Ok(());
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r'))
+        {
+            self.position = self.position + 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char>
+    {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char>
+    {
+        let character = self.peek()?;
+        self.position = self.position + 1;
+        return Some(character);
+    }
+
+    fn error(&self, message: &str) -> String
+    {
+        format!("{} at character {}.", message, self.position)
+    }
+}
+
+impl fmt::Display for Json
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            Json::Null => write!(f, "null"),
+            Json::Bool(boolean) => write!(f, "{}", boolean),
+            // JSON has no NaN or infinities.
+            Json::Number(number) if !number.is_finite() => write!(f, "null"),
+            Json::Number(number) => write!(f, "{}", number),
+            Json::String(string) => write_string(f, string),
+            Json::Array(items) =>
+            {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate()
+                {
+                    if index > 0
+                    {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) =>
+            {
+                write!(f, "{{")?;
+                for (index, (key, value)) in fields.iter().enumerate()
+                {
+                    if index > 0
+                    {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, string: &str) -> fmt::Result
+{
+    write!(f, "\"")?;
+    for character in string.chars()
+    {
+        match character
+        {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            character if (character as u32) < 0x20 => write!(f, "\\u{:04x}", character as u32)?,
+            character => write!(f, "{}", character)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn round_trips_documents()
+    {
+        let documents = [
+            "null",
+            "[true,false,0,-1.5,250000,\"\"]",
+            "{\"name\":\"lox\",\"nested\":{\"items\":[1,[2,[]],{}]}}",
+            "\"quote \\\" backslash \\\\ newline \\n tab \\t control \\u0001 é 🦀\"",
+        ];
+        for document in documents
+        {
+            let parsed = Json::parse(document).expect("the document should parse");
+            assert_eq!(parsed.to_string(), document);
+            assert_eq!(Json::parse(&parsed.to_string()), Ok(parsed));
+        }
+    }
+
+    #[test]
+    fn parses_with_whitespace_and_escapes()
+    {
+        let parsed = Json::parse(" { \"a\" : [ 1 , \"\\/\\b\\f\\r\" ] } ").unwrap();
+        assert_eq!(parsed, Json::object(vec![("a", Json::Array(vec![Json::Number(1.0), Json::string("/\u{8}\u{c}\r")]))]));
+        assert_eq!(parsed.get("a").and_then(Json::as_array).map(|items| items.len()), Some(2));
+    }
+
+    #[test]
+    fn decodes_surrogates()
+    {
+        assert_eq!(Json::parse("\"\\ud83e\\udd80\"").unwrap(), Json::string("🦀"));
+        assert_eq!(Json::parse("\"\\ud800\\u0041\"").unwrap(), Json::string("\u{fffd}A"));
+        assert_eq!(Json::parse("\"\\ud800x\"").unwrap(), Json::string("\u{fffd}x"));
+        assert_eq!(Json::parse("\"\\udc00\"").unwrap(), Json::string("\u{fffd}"));
+    }
+
+    #[test]
+    fn rejects_malformed_documents()
+    {
+        for document in ["", "[1,", "{\"a\" 1}", "{1:2}", "\"open", "\"\\q\"", "\"\\u12\"", "tru", "-"]
+        {
+            assert!(Json::parse(document).is_err(), "{:?} parsed", document);
+        }
+    }
+}
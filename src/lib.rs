@@ -0,0 +1,29 @@
+// The interpreter follows clox's naming and control-flow style (`IsNumber`,
+// `parse_rules`, explicit `return`s), so these lints are allowed crate-wide.
+#![allow(non_snake_case, non_upper_case_globals)]
+#![allow(clippy::needless_return, clippy::redundant_guards, clippy::assign_op_pattern, clippy::redundant_field_names, clippy::new_without_default, clippy::print_with_newline, clippy::println_empty_string, clippy::suspicious_else_formatting, clippy::single_match, clippy::collapsible_if)]
+
+pub mod analysis;
+pub mod ast;
+pub mod chunk;
+pub mod codegen;
+pub mod compile;
+pub mod debug;
+pub mod debugger;
+pub mod diagnostic;
+pub mod format;
+pub mod interpreter;
+pub mod json;
+pub mod line_editor;
+pub mod lint;
+pub mod loxc;
+pub mod native;
+pub mod object;
+pub mod optimize;
+pub mod parser;
+pub mod protocol;
+pub mod scanner;
+pub mod table;
+pub mod value;
+pub mod verify;
+pub mod vm;
@@ -0,0 +1,398 @@
+//! A small line editor for the REPL and the debugger.
+//!
+//! On a terminal it puts the tty in raw mode (through `stty`, so the crate stays
+//! free of dependencies) and handles the usual Emacs-style keys and history. When
+//! input is not a terminal it falls back to plain buffered reads.
+//!
+//! `stty` only exists on Unix-like systems, and it is run three times for every
+//! line: to save the terminal mode, to enter raw mode and to restore the saved
+//! mode. Where it is missing, as on Windows, lines are read plainly, without
+//! editing or history.
+
+use std::{fs, io::{self, BufRead, IsTerminal, Read, Write}, path::PathBuf, process::{Command, Stdio}};
+
+const HISTORY_MAX: usize = 1000;
+
+pub enum ReadLine
+{
+    Line(String),
+    Interrupted, // Ctrl-C: the caller should drop any partial input.
+    Eof, // Ctrl-D on an empty line, or end of input.
+}
+
+pub struct LineEditor
+{
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Key
+{
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    KillToEnd,
+    KillToStart,
+    KillWord,
+    ClearScreen,
+    Interrupt,
+    EndOfFile,
+    Ignored,
+}
+
+impl LineEditor
+{
+    /// Creates an editor whose history is loaded from, and appended to,
+    /// `history_path`.
+    pub fn new(history_path: Option<PathBuf>) -> LineEditor
+    {
+        let mut history = Vec::new();
+        if let Some(path) = &history_path
+        {
+            if let Ok(contents) = fs::read_to_string(path)
+            {
+                history = contents.lines().map(str::to_string).collect();
+                let excess = history.len().saturating_sub(HISTORY_MAX);
+                history.drain(0..excess);
+            }
+        }
+        LineEditor { history: history, history_path: history_path }
+    }
+
+    /// `~/.rust_lox_history`, or `.rust_lox_history` in the working directory if
+    /// there is no home directory.
+    pub fn default_history_path() -> PathBuf
+    {
+        match std::env::var_os("HOME")
+        {
+            Some(home) => PathBuf::from(home).join(".rust_lox_history"),
+            None => PathBuf::from(".rust_lox_history"),
+        }
+    }
+
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<ReadLine>
+    {
+        if !io::stdin().is_terminal()
+        {
+            return read_plain_line(prompt);
+        }
+        let saved = match stty(&["-g"])
+        {
+            Ok(saved) => saved,
+            Err(_) => return read_plain_line(prompt),
+        };
+        stty(&["raw", "-echo"])?;
+        let result = self.edit(prompt);
+        stty(&[saved.trim()])?;
+        println!("");
+
+        if let Ok(ReadLine::Line(line)) = &result
+        {
+            self.add_history(line);
+        }
+        return result;
+    }
+
+    fn edit(&mut self, prompt: &str) -> io::Result<ReadLine>
+    {
+        let mut buffer: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // Index into `history` while browsing it; `history.len()` is the line
+        // being edited, which is kept in `draft`.
+        let mut browsing = self.history.len();
+        let mut draft: Vec<char> = Vec::new();
+        let mut input = io::stdin().lock();
+
+        refresh(prompt, &buffer, cursor)?;
+        loop
+        {
+            match read_key(&mut input)?
+            {
+                Key::Char(character) =>
+                {
+                    buffer.insert(cursor, character);
+                    cursor = cursor + 1;
+                }
+                Key::Enter => return Ok(ReadLine::Line(buffer.into_iter().collect())),
+                Key::Backspace if cursor > 0 =>
+                {
+                    cursor = cursor - 1;
+                    buffer.remove(cursor);
+                }
+                Key::Delete if cursor < buffer.len() =>
+                {
+                    buffer.remove(cursor);
+                }
+                Key::Left if cursor > 0 => cursor = cursor - 1,
+                Key::Right if cursor < buffer.len() => cursor = cursor + 1,
+                Key::Home => cursor = 0,
+                Key::End => cursor = buffer.len(),
+                Key::Up if browsing > 0 =>
+                {
+                    if browsing == self.history.len()
+                    {
+                        draft = buffer.clone();
+                    }
+                    browsing = browsing - 1;
+                    buffer = self.history[browsing].chars().collect();
+                    cursor = buffer.len();
+                }
+                Key::Down if browsing < self.history.len() =>
+                {
+                    browsing = browsing + 1;
+                    buffer = if browsing == self.history.len() { draft.clone() } else { self.history[browsing].chars().collect() };
+                    cursor = buffer.len();
+                }
+                Key::KillToEnd => buffer.truncate(cursor),
+                Key::KillToStart =>
+                {
+                    buffer.drain(0..cursor);
+                    cursor = 0;
+                }
+                Key::KillWord =>
+                {
+                    let mut start = cursor;
+                    while start > 0 && buffer[start - 1] == ' '
+                    {
+                        start = start - 1;
+                    }
+                    while start > 0 && buffer[start - 1] != ' '
+                    {
+                        start = start - 1;
+                    }
+                    buffer.drain(start..cursor);
+                    cursor = start;
+                }
+                Key::ClearScreen =>
+                {
+                    print!("\x1b[H\x1b[2J");
+                }
+                Key::Interrupt => return Ok(ReadLine::Interrupted),
+                Key::EndOfFile if buffer.is_empty() => return Ok(ReadLine::Eof),
+                Key::EndOfFile if cursor < buffer.len() =>
+                {
+                    buffer.remove(cursor);
+                }
+                _ => (),
+            }
+            refresh(prompt, &buffer, cursor)?;
+        }
+    }
+
+    fn add_history(&mut self, line: &str)
+    {
+        if line.trim().is_empty() || self.history.last().map(String::as_str) == Some(line)
+        {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(path) = &self.history_path
+        {
+            // History is a convenience; failing to save it is not worth an error.
+            if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path)
+            {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+}
+
+fn read_plain_line(prompt: &str) -> io::Result<ReadLine>
+{
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0
+    {
+        println!("");
+        return Ok(ReadLine::Eof);
+    }
+    return Ok(ReadLine::Line(line.trim_end_matches(&['\n', '\r'][..]).to_string()));
+}
+
+fn stty(args: &[&str]) -> io::Result<String>
+{
+    let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+    if !output.status.success()
+    {
+        return Err(io::Error::other("stty failed"));
+    }
+    return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+}
+
+// Redraws the whole line and puts the terminal cursor at `cursor`.
+fn refresh(prompt: &str, buffer: &[char], cursor: usize) -> io::Result<()>
+{
+    let line: String = buffer.iter().collect();
+    print!("\r{}{}\x1b[K\r", prompt, line);
+    let column = prompt.chars().count() + cursor;
+    if column > 0
+    {
+        print!("\x1b[{}C", column);
+    }
+    return io::stdout().flush();
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>>
+{
+    let mut byte = [0u8; 1];
+    match input.read(&mut byte)?
+    {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn read_key(input: &mut impl Read) -> io::Result<Key>
+{
+    let byte = match read_byte(input)?
+    {
+        Some(byte) => byte,
+        None => return Ok(Key::EndOfFile),
+    };
+    let key = match byte
+    {
+        1 => Key::Home, // Ctrl-A
+        2 => Key::Left, // Ctrl-B
+        3 => Key::Interrupt, // Ctrl-C
+        4 => Key::EndOfFile, // Ctrl-D
+        5 => Key::End, // Ctrl-E
+        6 => Key::Right, // Ctrl-F
+        8 | 127 => Key::Backspace,
+        11 => Key::KillToEnd, // Ctrl-K
+        12 => Key::ClearScreen, // Ctrl-L
+        10 | 13 => Key::Enter,
+        14 => Key::Down, // Ctrl-N
+        16 => Key::Up, // Ctrl-P
+        21 => Key::KillToStart, // Ctrl-U
+        23 => Key::KillWord, // Ctrl-W
+        27 => return read_escape(input),
+        byte if byte < 32 => Key::Ignored,
+        byte => Key::Char(read_utf8(input, byte)?),
+    };
+    return Ok(key);
+}
+
+// Arrow and editing keys arrive as `ESC [ ...` or `ESC O ...` sequences.
+fn read_escape(input: &mut impl Read) -> io::Result<Key>
+{
+    let introducer = read_byte(input)?;
+    if introducer != Some(b'[') && introducer != Some(b'O')
+    {
+        return Ok(Key::Ignored);
+    }
+    let key = match read_byte(input)?
+    {
+        Some(b'A') => Key::Up,
+        Some(b'B') => Key::Down,
+        Some(b'C') => Key::Right,
+        Some(b'D') => Key::Left,
+        Some(b'H') => Key::Home,
+        Some(b'F') => Key::End,
+        Some(digit @ b'0'..=b'9') =>
+        {
+            // `ESC [ n ~`
+            let mut code = (digit - b'0') as u32;
+            loop
+            {
+                match read_byte(input)?
+                {
+                    Some(digit @ b'0'..=b'9') => code = code * 10 + (digit - b'0') as u32,
+                    Some(b'~') => break,
+                    _ => return Ok(Key::Ignored),
+                }
+            }
+            match code
+            {
+                1 | 7 => Key::Home,
+                3 => Key::Delete,
+                4 | 8 => Key::End,
+                _ => Key::Ignored,
+            }
+        }
+        _ => Key::Ignored,
+    };
+    return Ok(key);
+}
+
+fn read_utf8(input: &mut impl Read, first: u8) -> io::Result<char>
+{
+    let length = match first
+    {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..length
+    {
+        match read_byte(input)?
+        {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    return Ok(String::from_utf8_lossy(&bytes).chars().next().unwrap_or('?'));
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // Every key in `bytes`, as the terminal would send them.
+    fn keys(mut bytes: &[u8]) -> Vec<Key>
+    {
+        let mut keys = Vec::new();
+        loop
+        {
+            match read_key(&mut bytes).unwrap()
+            {
+                Key::EndOfFile if bytes.is_empty() => return keys,
+                key => keys.push(key),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_control_keys()
+    {
+        assert_eq!(keys(b"a\x01\x03\x0b\x15\x17\x7f\r\n\x00"), vec![
+            Key::Char('a'), Key::Home, Key::Interrupt, Key::KillToEnd, Key::KillToStart,
+            Key::KillWord, Key::Backspace, Key::Enter, Key::Enter, Key::Ignored,
+        ]);
+    }
+
+    #[test]
+    fn reads_escape_sequences()
+    {
+        assert_eq!(keys(b"\x1b[A\x1b[B\x1b[C\x1b[D\x1bOH\x1b[F"), vec![Key::Up, Key::Down, Key::Right, Key::Left, Key::Home, Key::End]);
+        assert_eq!(keys(b"\x1b[3~\x1b[1~\x1b[4~\x1b[15~"), vec![Key::Delete, Key::Home, Key::End, Key::Ignored]);
+        assert_eq!(keys(b"\x1bxa"), vec![Key::Ignored, Key::Char('a')]);
+    }
+
+    #[test]
+    fn ignores_truncated_escape_sequences()
+    {
+        assert_eq!(keys(b"\x1b"), vec![Key::Ignored]);
+        assert_eq!(keys(b"\x1b["), vec![Key::Ignored]);
+        assert_eq!(keys(b"\x1b[3"), vec![Key::Ignored]);
+    }
+
+    #[test]
+    fn reads_multi_byte_characters()
+    {
+        assert_eq!(keys("é€🦀".as_bytes()), vec![Key::Char('é'), Key::Char('€'), Key::Char('🦀')]);
+        assert_eq!(keys(&"€".as_bytes()[..2]), vec![Key::Char('\u{fffd}')]);
+        assert_eq!(keys(&[0xff]), vec![Key::Char('\u{fffd}')]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Checks for code that compiles but is probably not what was meant.
+//!
+//! Findings are warnings in the same `Diagnostic` form as compile errors, with
+//! the rule's name at the end of the message so it can be looked up or allowed.
+
+use crate::analysis::{self, Analysis, SymbolKind};
+use crate::ast::{BinaryOp, Expr, Span, Stmt};
+use crate::diagnostic::Diagnostic;
+use crate::parser;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Rule
+{
+    UnusedVariable,
+    ShadowedGlobal,
+    SelfComparison,
+}
+
+impl Rule
+{
+    pub const ALL: [Rule; 3] = [
+        Rule::UnusedVariable,
+        Rule::ShadowedGlobal,
+        Rule::SelfComparison,
+    ];
+
+    pub fn name(self) -> &'static str
+    {
+        match self
+        {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ShadowedGlobal => "shadowed-global",
+            Rule::SelfComparison => "self-comparison",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Rule>
+    {
+        Rule::ALL.iter().copied().find(|rule| rule.name() == name)
+    }
+}
+
+/// Which rules run. Every rule is enabled by default.
+#[derive(Clone, Debug)]
+pub struct LintConfig
+{
+    enabled: Vec<Rule>,
+}
+
+impl LintConfig
+{
+    pub fn new() -> LintConfig
+    {
+        LintConfig { enabled: Rule::ALL.to_vec() }
+    }
+
+    pub fn allow(&mut self, rule: Rule)
+    {
+        self.enabled.retain(|enabled| *enabled != rule);
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool
+    {
+        self.enabled.contains(&rule)
+    }
+}
+
+/// Lints `source`, returning its warnings in source order, or its compile
+/// errors if it doesn't compile. `natives` are the host's native functions.
+pub fn lint(source: &str, natives: &[(&str, u8)], config: &LintConfig) -> Result<Vec<Diagnostic>, Vec<Diagnostic>>
+{
+    return lint_analyzed(source, &analysis::analyze(source, natives), natives, config);
+}
+
+/// Like `lint`, for a `source` that `analysis::analyze` has already resolved.
+pub fn lint_analyzed(source: &str, analysis: &Analysis, natives: &[(&str, u8)], config: &LintConfig) -> Result<Vec<Diagnostic>, Vec<Diagnostic>>
+{
+    if !analysis.diagnostics.is_empty()
+    {
+        return Err(analysis.diagnostics.clone());
+    }
+
+    let mut warnings = Vec::new();
+    for symbol in analysis.symbols.iter().filter(|symbol| symbol.kind == SymbolKind::Variable)
+    {
+        let first = match symbol.declarations.first()
+        {
+            Some(first) => first,
+            None => continue, // Used but never declared: a runtime error, not a lint.
+        };
+        if config.is_enabled(Rule::UnusedVariable) && !symbol.references.iter().any(|reference| !reference.is_write)
+        {
+            warnings.push(warning(Rule::UnusedVariable, first.name_span, format!("Variable '{}' is never read.", symbol.name)));
+        }
+        if config.is_enabled(Rule::ShadowedGlobal)
+        {
+            if natives.iter().any(|(name, _)| *name == symbol.name)
+            {
+                warnings.push(warning(Rule::ShadowedGlobal, first.name_span, format!("Variable '{}' replaces the native function.", symbol.name)));
+            }
+            for declaration in &symbol.declarations[1..]
+            {
+                let message = format!("Variable '{}' is already declared on line {}.", symbol.name, first.name_span.line);
+                warnings.push(warning(Rule::ShadowedGlobal, declaration.name_span, message));
+            }
+        }
+    }
+
+    if config.is_enabled(Rule::SelfComparison)
+    {
+        // The source compiled, so it parses without errors.
+        let (program, _) = parser::parse_recovering(source);
+        for statement in &program
+        {
+            match statement
+            {
+                Stmt::Expression { expr, .. } | Stmt::Print { expr, .. } => self_comparisons(expr, &mut warnings),
+                Stmt::Var { initializer: Some(expr), .. } => self_comparisons(expr, &mut warnings),
+                Stmt::Var { initializer: None, .. } => (),
+            }
+        }
+    }
+
+    warnings.sort_by_key(|diagnostic| diagnostic.span.offset);
+    return Ok(warnings);
+}
+
+fn warning(rule: Rule, span: Span, message: String) -> Diagnostic
+{
+    Diagnostic::warning(span, format!("{} [{}]", message, rule.name()))
+}
+
+fn self_comparisons(expr: &Expr, warnings: &mut Vec<Diagnostic>)
+{
+    match expr
+    {
+        Expr::Literal { .. } | Expr::Variable { .. } => (),
+        Expr::Grouping { expr, .. } => self_comparisons(expr, warnings),
+        Expr::Unary { operand, .. } => self_comparisons(operand, warnings),
+        Expr::Assign { value, .. } => self_comparisons(value, warnings),
+        Expr::Binary { operator, left, right, .. } =>
+        {
+            let compares = matches!(operator, BinaryOp::Equal | BinaryOp::NotEqual | BinaryOp::Greater |
+                BinaryOp::GreaterEqual | BinaryOp::Less | BinaryOp::LessEqual);
+            if compares && pure(left) && same(left, right)
+            {
+                warnings.push(warning(Rule::SelfComparison, expr.span(), "Comparing a value with itself.".to_string()));
+            }
+            self_comparisons(left, warnings);
+            self_comparisons(right, warnings);
+        }
+    }
+}
+
+// Whether evaluating `expr` twice gives the same value: nothing is assigned.
+fn pure(expr: &Expr) -> bool
+{
+    match expr
+    {
+        Expr::Literal { .. } | Expr::Variable { .. } => true,
+        Expr::Grouping { expr, .. } => pure(expr),
+        Expr::Unary { operand, .. } => pure(operand),
+        Expr::Binary { left, right, .. } => pure(left) && pure(right),
+        Expr::Assign { .. } => false,
+    }
+}
+
+// Whether `a` and `b` are the same expression, wherever they are in the source.
+fn same(a: &Expr, b: &Expr) -> bool
+{
+    match (a, b)
+    {
+        (Expr::Literal { value: a, .. }, Expr::Literal { value: b, .. }) => a == b,
+        (Expr::Grouping { expr: a, .. }, _) => same(a, b),
+        (_, Expr::Grouping { expr: b, .. }) => same(a, b),
+        (Expr::Unary { operator: a_operator, operand: a, .. }, Expr::Unary { operator: b_operator, operand: b, .. }) =>
+        {
+            a_operator == b_operator && same(a, b)
+        }
+        (Expr::Binary { operator: a_operator, left: a_left, right: a_right, .. }, Expr::Binary { operator: b_operator, left: b_left, right: b_right, .. }) =>
+        {
+            a_operator == b_operator && same(a_left, b_left) && same(a_right, b_right)
+        }
+        (Expr::Variable { name: a, .. }, Expr::Variable { name: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    const NATIVES: [(&str, u8); 1] = [("clock", 0)];
+
+    // Each warning as (line, message).
+    fn warnings(source: &str, config: &LintConfig) -> Vec<(u32, String)>
+    {
+        let warnings = lint(source, &NATIVES, config).expect("the source should compile");
+        return warnings.iter().map(|warning| (warning.span.line, warning.message.clone())).collect();
+    }
+
+    fn all(source: &str) -> Vec<(u32, String)>
+    {
+        warnings(source, &LintConfig::new())
+    }
+
+    fn only(rule: Rule, source: &str) -> Vec<(u32, String)>
+    {
+        let mut config = LintConfig::new();
+        for other in Rule::ALL.iter().filter(|other| **other != rule)
+        {
+            config.allow(*other);
+        }
+        return warnings(source, &config);
+    }
+
+    #[test]
+    fn unused_variable()
+    {
+        let source = "var read = 1;\nvar written = 2;\nwritten = read;\nvar unused;\nprint read;\n";
+        assert_eq!(only(Rule::UnusedVariable, source), vec![
+            (2, "Variable 'written' is never read. [unused-variable]".to_string()),
+            (4, "Variable 'unused' is never read. [unused-variable]".to_string()),
+        ]);
+        assert_eq!(only(Rule::UnusedVariable, "var a = 1; a = a + 1;"), vec![]);
+    }
+
+    #[test]
+    fn shadowed_global()
+    {
+        let source = "var clock = 1;\nvar a = 1;\nvar a = clock;\nprint a;\n";
+        assert_eq!(only(Rule::ShadowedGlobal, source), vec![
+            (1, "Variable 'clock' replaces the native function. [shadowed-global]".to_string()),
+            (3, "Variable 'a' is already declared on line 2. [shadowed-global]".to_string()),
+        ]);
+        assert_eq!(only(Rule::ShadowedGlobal, "var a = 1; a = 2; print a + clock;"), vec![]);
+    }
+
+    #[test]
+    fn self_comparison()
+    {
+        let source = "var a = 1;\nvar b = 2;\nprint a == a;\nprint (a + 1) >= a + (1);\nprint a < b;\nprint (a = 1) == (a = 1);\nprint -a != -b;\n";
+        assert_eq!(only(Rule::SelfComparison, source), vec![
+            (3, "Comparing a value with itself. [self-comparison]".to_string()),
+            (4, "Comparing a value with itself. [self-comparison]".to_string()),
+        ]);
+        assert_eq!(only(Rule::SelfComparison, "var c = !(1 <= 1);"), vec![(1, "Comparing a value with itself. [self-comparison]".to_string())]);
+    }
+
+    #[test]
+    fn allowed_rules_do_not_run()
+    {
+        let source = "var clock = 1 == 1;\n";
+        assert_eq!(all(source).len(), 3);
+        let mut config = LintConfig::new();
+        config.allow(Rule::UnusedVariable);
+        config.allow(Rule::SelfComparison);
+        assert_eq!(warnings(source, &config), vec![(1, "Variable 'clock' replaces the native function. [shadowed-global]".to_string())]);
+        assert_eq!(Rule::ALL.iter().map(|rule| Rule::from_name(rule.name())).collect::<Vec<_>>(), Rule::ALL.iter().copied().map(Some).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn code_that_does_not_compile_gives_its_errors()
+    {
+        let errors = lint("var a = ;", &NATIVES, &LintConfig::new()).expect_err("the source should not compile");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Expect expression");
+    }
+}
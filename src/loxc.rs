@@ -0,0 +1,372 @@
+//! The `.loxc` compiled script format.
+//!
+//! All integers are little-endian.
+//!
+//! ```text
+//! magic      "LOXC"
+//! version    u16
+//! checksum   u32    FNV-1a of everything after this field
+//! chunk:
+//!   code     u32 length, then the bytes
+//!   lines    u32 run count, then (line u32, count u32) per run
+//!   constants u32 count, then a tag byte and payload per constant:
+//!            0 nil, 1 false, 2 true, 3 number (f64), 4 string (u32 length + UTF-8)
+//! ```
+//!
+//! Function constants will get their own tag, holding a nested chunk in the
+//! same layout, once the compiler produces them.
+
+use std::fmt;
+
+use crate::chunk::{Chunk, init_chunk};
+use crate::object::{Heap, ObjType};
+use crate::value::{self, Value, ValueType};
+use crate::verify::VerifyError;
+
+pub const MAGIC: &[u8; 4] = b"LOXC";
+pub const VERSION: u16 = 1;
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+const TAG_STRING: u8 = 4;
+
+const HEADER_LENGTH: usize = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError
+{
+    BadMagic,
+    UnsupportedVersion(u16),
+    ChecksumMismatch,
+    Truncated,
+    InvalidConstantTag(u8),
+    InvalidString,
+    LineTableMismatch,
+    TrailingBytes,
+    Invalid(VerifyError),
+}
+
+impl fmt::Display for LoadError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            LoadError::BadMagic => write!(f, "Not a compiled Lox file."),
+            LoadError::UnsupportedVersion(version) => write!(f, "Unsupported bytecode version {} (expected {}).", version, VERSION),
+            LoadError::ChecksumMismatch => write!(f, "Checksum mismatch; the file is corrupt."),
+            LoadError::Truncated => write!(f, "Unexpected end of file."),
+            LoadError::InvalidConstantTag(tag) => write!(f, "Invalid constant tag {}.", tag),
+            LoadError::InvalidString => write!(f, "String constant is not valid UTF-8."),
+            LoadError::LineTableMismatch => write!(f, "Line table does not cover the code."),
+            LoadError::TrailingBytes => write!(f, "Unexpected data after the chunk."),
+            LoadError::Invalid(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+pub fn serialize(chunk: &Chunk, heap: &Heap) -> Vec<u8>
+{
+    let mut payload = Vec::new();
+    write_chunk_body(&mut payload, chunk, heap);
+
+    let mut bytes = Vec::with_capacity(HEADER_LENGTH + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    return bytes;
+}
+
+/// Strings in the constant pool are interned into `heap`.
+pub fn deserialize(bytes: &[u8], heap: &mut Heap) -> Result<Chunk, LoadError>
+{
+    if bytes.len() < MAGIC.len() || &bytes[0..MAGIC.len()] != MAGIC
+    {
+        return Err(LoadError::BadMagic);
+    }
+    if bytes.len() < HEADER_LENGTH
+    {
+        return Err(LoadError::Truncated);
+    }
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+    if version != VERSION
+    {
+        return Err(LoadError::UnsupportedVersion(version));
+    }
+    let expected = u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]);
+    let payload = &bytes[HEADER_LENGTH..];
+    if checksum(payload) != expected
+    {
+        // A file that was cut short fails the checksum too. Tell the two apart
+        // by reading the chunk into a scratch heap.
+        let mut reader = Reader { bytes: payload, position: 0 };
+        if let Err(LoadError::Truncated) = read_chunk_body(&mut reader, &mut Heap::new())
+        {
+            return Err(LoadError::Truncated);
+        }
+        return Err(LoadError::ChecksumMismatch);
+    }
+
+    let mut reader = Reader { bytes: payload, position: 0 };
+    let chunk = read_chunk_body(&mut reader, heap)?;
+    if reader.position != payload.len()
+    {
+        return Err(LoadError::TrailingBytes);
+    }
+    return Ok(chunk);
+}
+
+fn write_chunk_body(out: &mut Vec<u8>, chunk: &Chunk, heap: &Heap)
+{
+    out.extend_from_slice(&(chunk.code.len() as u32).to_le_bytes());
+    out.extend_from_slice(&chunk.code);
+
+    // Consecutive bytes almost always share a line, so store (line, count) runs.
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for line in &chunk.lines
+    {
+        match runs.last_mut()
+        {
+            Some((last, count)) if *last == *line => *count = *count + 1,
+            _ => runs.push((*line, 1)),
+        }
+    }
+    out.extend_from_slice(&(runs.len() as u32).to_le_bytes());
+    for (line, count) in runs
+    {
+        out.extend_from_slice(&line.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+    }
+
+    out.extend_from_slice(&(chunk.constants.values.len() as u32).to_le_bytes());
+    for constant in &chunk.constants.values
+    {
+        write_constant(out, *constant, heap);
+    }
+}
+
+fn write_constant(out: &mut Vec<u8>, constant: Value, heap: &Heap)
+{
+    match constant.ValueType
+    {
+        ValueType::ValNil | ValueType::ValInternalNil => out.push(TAG_NIL),
+        ValueType::ValBool(false) => out.push(TAG_FALSE),
+        ValueType::ValBool(true) => out.push(TAG_TRUE),
+        ValueType::ValNumber(number) =>
+        {
+            out.push(TAG_NUMBER);
+            out.extend_from_slice(&number.to_le_bytes());
+        }
+        ValueType::ValObj(obj) =>
+        {
+            match &heap.GetObject(obj).typeOfObject
+            {
+                ObjType::ObjString(string) =>
+                {
+                    out.push(TAG_STRING);
+                    out.extend_from_slice(&(string.str.len() as u32).to_le_bytes());
+                    out.extend_from_slice(string.str.as_bytes());
+                }
+                ObjType::ObjNative(_) => unreachable!("the compiler never puts natives in the constant pool"),
+            }
+        }
+    }
+}
+
+fn read_chunk_body(reader: &mut Reader, heap: &mut Heap) -> Result<Chunk, LoadError>
+{
+    let mut chunk = init_chunk();
+
+    let code_length = reader.u32()? as usize;
+    chunk.code = reader.take(code_length)?.to_vec();
+
+    let run_count = reader.u32()?;
+    for _ in 0..run_count
+    {
+        let line = reader.u32()?;
+        let count = reader.u32()? as usize;
+        if chunk.lines.len() + count > code_length
+        {
+            return Err(LoadError::LineTableMismatch);
+        }
+        chunk.lines.extend(std::iter::repeat_n(line, count));
+    }
+    if chunk.lines.len() != code_length
+    {
+        return Err(LoadError::LineTableMismatch);
+    }
+
+    let constant_count = reader.u32()?;
+    for _ in 0..constant_count
+    {
+        let constant = match reader.u8()?
+        {
+            TAG_NIL => value::NilAsValue(),
+            TAG_FALSE => value::BoolAsValue(false),
+            TAG_TRUE => value::BoolAsValue(true),
+            TAG_NUMBER =>
+            {
+                let mut number = [0; 8];
+                number.copy_from_slice(reader.take(8)?);
+                value::NumberAsValue(f64::from_le_bytes(number))
+            }
+            TAG_STRING =>
+            {
+                let length = reader.u32()?;
+                let string = std::str::from_utf8(reader.take(length as usize)?).map_err(|_| LoadError::InvalidString)?;
+                value::ObjAsValue(heap.CopyString(string))
+            }
+            tag => return Err(LoadError::InvalidConstantTag(tag)),
+        };
+        value::write_value_array(&mut chunk.constants, constant);
+    }
+
+    return Ok(chunk);
+}
+
+struct Reader<'a>
+{
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_>
+{
+    fn take(&mut self, length: usize) -> Result<&[u8], LoadError>
+    {
+        if self.bytes.len() - self.position < length
+        {
+            return Err(LoadError::Truncated);
+        }
+        let slice = &self.bytes[self.position..self.position + length];
+        self.position = self.position + length;
+        return Ok(slice);
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadError>
+    {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadError>
+    {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+// 32-bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u32
+{
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in bytes
+    {
+        hash = (hash ^ *byte as u32).wrapping_mul(0x01000193);
+    }
+    return hash;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::value::value_to_string;
+    use crate::vm;
+
+    const SOURCE: &str = "var greeting = \"hello\";\nprint greeting + \" world\";\nprint 1.5 + -2;\nprint nil == false;\nprint !true;\n";
+
+    fn compiled() -> Vec<u8>
+    {
+        let mut vm = vm::init_vm();
+        let chunk = vm.try_compile(SOURCE).expect("the source should compile");
+        return serialize(&chunk, &vm.heap);
+    }
+
+    // `payload` behind a header with a matching checksum.
+    fn sealed(payload: &[u8]) -> Vec<u8>
+    {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(payload).to_le_bytes());
+        bytes.extend_from_slice(payload);
+        return bytes;
+    }
+
+    #[test]
+    fn round_trips_a_compiled_chunk()
+    {
+        let mut vm = vm::init_vm();
+        let chunk = vm.try_compile(SOURCE).expect("the source should compile");
+        let bytes = serialize(&chunk, &vm.heap);
+
+        let mut heap = Heap::new();
+        let loaded = deserialize(&bytes, &mut heap).expect("the file should load");
+        assert_eq!(loaded.code, chunk.code);
+        assert_eq!(loaded.lines, chunk.lines);
+        let constants = |chunk: &Chunk, heap: &Heap| -> Vec<String>
+        {
+            chunk.constants.values.iter().map(|value| value_to_string(*value, heap)).collect()
+        };
+        assert_eq!(constants(&loaded, &heap), constants(&chunk, &vm.heap));
+        assert_eq!(serialize(&loaded, &heap), bytes);
+    }
+
+    #[test]
+    fn reports_truncated_files()
+    {
+        let bytes = compiled();
+        for length in MAGIC.len()..bytes.len()
+        {
+            assert_eq!(deserialize(&bytes[..length], &mut Heap::new()).err(), Some(LoadError::Truncated), "cut to {} bytes", length);
+        }
+    }
+
+    #[test]
+    fn reports_corrupt_files()
+    {
+        let bytes = compiled();
+
+        let mut corrupt = bytes.clone();
+        corrupt[HEADER_LENGTH + 4] = corrupt[HEADER_LENGTH + 4] ^ 0xff; // The first byte of code.
+        assert_eq!(deserialize(&corrupt, &mut Heap::new()).err(), Some(LoadError::ChecksumMismatch));
+
+        let mut corrupt = bytes.clone();
+        corrupt[6] = corrupt[6] ^ 0xff; // The checksum itself.
+        assert_eq!(deserialize(&corrupt, &mut Heap::new()).err(), Some(LoadError::ChecksumMismatch));
+
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(deserialize(&longer, &mut Heap::new()).err(), Some(LoadError::ChecksumMismatch));
+    }
+
+    #[test]
+    fn reports_bad_headers_and_bodies()
+    {
+        let bytes = compiled();
+        assert_eq!(deserialize(b"LOX", &mut Heap::new()).err(), Some(LoadError::BadMagic));
+        assert_eq!(deserialize(b"#!/usr/bin/env lox\n", &mut Heap::new()).err(), Some(LoadError::BadMagic));
+
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert_eq!(deserialize(&newer, &mut Heap::new()).err(), Some(LoadError::UnsupportedVersion(2)));
+
+        let mut trailing = bytes[HEADER_LENGTH..].to_vec();
+        trailing.push(0);
+        assert_eq!(deserialize(&sealed(&trailing), &mut Heap::new()).err(), Some(LoadError::TrailingBytes));
+
+        // No code, no line runs, one constant with an unknown tag.
+        let payload = [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 9];
+        assert_eq!(deserialize(&sealed(&payload), &mut Heap::new()).err(), Some(LoadError::InvalidConstantTag(9)));
+
+        // One byte of code, and a line run covering two.
+        let payload = [1, 0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0];
+        assert_eq!(deserialize(&sealed(&payload), &mut Heap::new()).err(), Some(LoadError::LineTableMismatch));
+
+        // A string constant that is not UTF-8.
+        let payload = [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, TAG_STRING, 1, 0, 0, 0, 0xff];
+        assert_eq!(deserialize(&sealed(&payload), &mut Heap::new()).err(), Some(LoadError::InvalidString));
+    }
+}
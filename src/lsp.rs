@@ -0,0 +1,397 @@
+use std::io;
+
+use rust_lox::{analysis::{self, Analysis, Symbol, SymbolKind}, ast::Span, diagnostic::Severity, json::Json, lint::{self, LintConfig}, native::Capabilities, protocol::{read_message, write_message}, scanner::{Lexer, TokenType}};
+
+const KEYWORDS: [&str; 16] = ["and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return", "super", "this", "true", "var", "while"];
+
+// The semantic token legend; a token's type is its index here.
+const TOKEN_TYPES: [&str; 7] = ["keyword", "variable", "function", "string", "number", "operator", "comment"];
+const TOKEN_MODIFIERS: [&str; 1] = ["declaration"];
+
+// LSP symbol and completion item kinds.
+const SYMBOL_VARIABLE: f64 = 13.0;
+const COMPLETION_FUNCTION: f64 = 3.0;
+const COMPLETION_VARIABLE: f64 = 6.0;
+const COMPLETION_KEYWORD: f64 = 14.0;
+
+struct Server
+{
+    documents: Vec<Document>,
+    natives: Vec<(&'static str, u8)>,
+    shut_down: bool,
+}
+
+// An open document, analyzed once per version so requests don't recompile it.
+struct Document
+{
+    uri: String,
+    text: String,
+    analysis: Analysis,
+}
+
+// Converts between byte offsets and LSP positions, which count characters in
+// UTF-16 code units.
+struct LineIndex<'a>
+{
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+/// Serves the Language Server Protocol over stdin and stdout until the client
+/// sends `exit`, and returns the process exit code. Documents are synced whole
+/// on every change. `capabilities` decides which natives count as declared.
+pub fn serve(capabilities: &Capabilities) -> i32
+{
+    let natives = capabilities.natives().iter().map(|native| (native.name, native.arity)).collect();
+    let mut server = Server { documents: Vec::new(), natives: natives, shut_down: false };
+    let mut input = io::stdin().lock();
+    loop
+    {
+        let message = match read_message(&mut input)
+        {
+            Ok(Some(message)) => message,
+            Ok(None) | Err(_) => return 1,
+        };
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        if method == "exit"
+        {
+            return if server.shut_down { 0 } else { 1 };
+        }
+        match message.get("id")
+        {
+            Some(id) =>
+            {
+                let response = match server.request(method, &params)
+                {
+                    Ok(result) => Json::object(vec![("jsonrpc", Json::string("2.0")), ("id", id.clone()), ("result", result)]),
+                    Err((code, error)) => Json::object(vec![
+                        ("jsonrpc", Json::string("2.0")),
+                        ("id", id.clone()),
+                        ("error", Json::object(vec![("code", Json::Number(code)), ("message", Json::string(&error))])),
+                    ]),
+                };
+                send(&response);
+            }
+            None => server.notification(method, &params),
+        }
+    }
+}
+
+impl Server
+{
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (f64, String)>
+    {
+        if method == "initialize"
+        {
+            return Ok(Json::object(vec![
+                ("capabilities", Json::object(vec![
+                    ("textDocumentSync", Json::Number(1.0)), // Full text on every change.
+                    ("hoverProvider", Json::Bool(true)),
+                    ("definitionProvider", Json::Bool(true)),
+                    ("referencesProvider", Json::Bool(true)),
+                    ("documentSymbolProvider", Json::Bool(true)),
+                    ("completionProvider", Json::object(vec![])),
+                    ("semanticTokensProvider", Json::object(vec![
+                        ("legend", Json::object(vec![
+                            ("tokenTypes", Json::Array(TOKEN_TYPES.iter().map(|name| Json::string(name)).collect())),
+                            ("tokenModifiers", Json::Array(TOKEN_MODIFIERS.iter().map(|name| Json::string(name)).collect())),
+                        ])),
+                        ("full", Json::Bool(true)),
+                    ])),
+                ])),
+                ("serverInfo", Json::object(vec![("name", Json::string("rust_lox"))])),
+            ]));
+        }
+        if method == "shutdown"
+        {
+            self.shut_down = true;
+            return Ok(Json::Null);
+        }
+
+        let uri = params.get("textDocument").and_then(|document| document.get("uri")).and_then(Json::as_str).unwrap_or("");
+        let (text, analysis) = match self.documents.iter().find(|document| document.uri == uri)
+        {
+            Some(document) => (&document.text, &document.analysis),
+            None if method.starts_with("textDocument/") => return Err((-32602.0, format!("Document '{}' is not open.", uri))),
+            None => return Err((-32601.0, format!("Unsupported method '{}'.", method))),
+        };
+        let index = LineIndex::new(text);
+        let offset = params.get("position").map(|position| index.offset(position)).unwrap_or(0);
+        let location = |span: Span| Json::object(vec![("uri", Json::string(uri)), ("range", index.range(span))]);
+
+        match method
+        {
+            "textDocument/hover" => Ok(match analysis.symbol_at(offset)
+            {
+                Some(symbol) => Json::object(vec![("contents", Json::object(vec![
+                    ("kind", Json::string("markdown")),
+                    ("value", Json::string(&hover(symbol, &index))),
+                ]))]),
+                None => Json::Null,
+            }),
+            "textDocument/definition" => Ok(match analysis.symbol_at(offset)
+            {
+                Some(symbol) if !symbol.declarations.is_empty() =>
+                {
+                    Json::Array(symbol.declarations.iter().map(|declaration| location(declaration.name_span)).collect())
+                }
+                _ => Json::Null,
+            }),
+            "textDocument/references" => Ok(match analysis.symbol_at(offset)
+            {
+                Some(symbol) =>
+                {
+                    let include_declaration = params.get("context").and_then(|context| context.get("includeDeclaration")).and_then(Json::as_bool).unwrap_or(false);
+                    let mut spans: Vec<Span> = symbol.references.iter().map(|reference| reference.span).collect();
+                    if include_declaration
+                    {
+                        spans.extend(symbol.declarations.iter().map(|declaration| declaration.name_span));
+                    }
+                    spans.sort_by_key(|span| span.offset);
+                    Json::Array(spans.into_iter().map(location).collect())
+                }
+                None => Json::Null,
+            }),
+            // Lox has no functions or classes yet, so the outline is the variables.
+            "textDocument/documentSymbol" =>
+            {
+                let mut declarations: Vec<(&Symbol, Span, Span)> = analysis.symbols.iter()
+                    .flat_map(|symbol| symbol.declarations.iter().map(move |declaration| (symbol, declaration.span, declaration.name_span)))
+                    .collect();
+                declarations.sort_by_key(|(_, span, _)| span.offset);
+                Ok(Json::Array(declarations.into_iter().map(|(symbol, span, name_span)| Json::object(vec![
+                    ("name", Json::string(&symbol.name)),
+                    ("kind", Json::Number(SYMBOL_VARIABLE)),
+                    ("range", index.range(span)),
+                    ("selectionRange", index.range(name_span)),
+                ])).collect()))
+            }
+            "textDocument/completion" => Ok(Json::Array(completions(analysis, offset))),
+            "textDocument/semanticTokens/full" => Ok(Json::object(vec![("data", Json::Array(semantic_tokens(text, analysis, &index)))])),
+            _ => Err((-32601.0, format!("Unsupported method '{}'.", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json)
+    {
+        let document = params.get("textDocument");
+        let uri = document.and_then(|document| document.get("uri")).and_then(Json::as_str).unwrap_or("").to_string();
+        match method
+        {
+            "textDocument/didOpen" =>
+            {
+                let text = document.and_then(|document| document.get("text")).and_then(Json::as_str).unwrap_or("").to_string();
+                self.documents.retain(|document| document.uri != uri);
+                let analysis = analysis::analyze(&text, &self.natives);
+                self.documents.push(Document { uri: uri.clone(), text: text, analysis: analysis });
+                self.publish_diagnostics(&uri);
+            }
+            "textDocument/didChange" =>
+            {
+                let changes = params.get("contentChanges").and_then(Json::as_array).unwrap_or(&[]);
+                if let Some(text) = changes.last().and_then(|change| change.get("text")).and_then(Json::as_str)
+                {
+                    for document in &mut self.documents
+                    {
+                        if document.uri == uri
+                        {
+                            document.text = text.to_string();
+                            document.analysis = analysis::analyze(text, &self.natives);
+                        }
+                    }
+                    self.publish_diagnostics(&uri);
+                }
+            }
+            "textDocument/didClose" =>
+            {
+                self.documents.retain(|document| document.uri != uri);
+                // Closing clears the document's diagnostics.
+                send(&publish(&uri, Vec::new()));
+            }
+            _ => (),
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str)
+    {
+        let document = match self.documents.iter().find(|document| document.uri == uri)
+        {
+            Some(document) => document,
+            None => return,
+        };
+        let index = LineIndex::new(&document.text);
+        // Lint warnings when the document compiles, its errors when it doesn't.
+        let found = match lint::lint_analyzed(&document.text, &document.analysis, &self.natives, &LintConfig::new())
+        {
+            Ok(warnings) => warnings,
+            Err(errors) => errors,
+        };
+        let diagnostics = found.iter().map(|diagnostic| Json::object(vec![
+            ("range", index.range(diagnostic.span)),
+            ("severity", Json::Number(match diagnostic.severity { Severity::Error => 1.0, Severity::Warning => 2.0 })),
+            ("source", Json::string("lox")),
+            ("message", Json::string(&diagnostic.message)),
+        ])).collect();
+        send(&publish(uri, diagnostics));
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json
+{
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("method", Json::string("textDocument/publishDiagnostics")),
+        ("params", Json::object(vec![("uri", Json::string(uri)), ("diagnostics", Json::Array(diagnostics))])),
+    ])
+}
+
+fn send(message: &Json)
+{
+    // There is no one left to tell if the client has gone away.
+    let _ = write_message(&mut io::stdout().lock(), message);
+}
+
+fn hover(symbol: &Symbol, index: &LineIndex) -> String
+{
+    match symbol.kind
+    {
+        SymbolKind::Native { arity } =>
+        {
+            let plural = if arity == 1 { "" } else { "s" };
+            format!("```lox\n<native fn> {}\n```\nNative function taking {} argument{}.", symbol.name, arity, plural)
+        }
+        SymbolKind::Variable if symbol.declarations.is_empty() => format!("```lox\n{}\n```\nUndeclared variable.", symbol.name),
+        SymbolKind::Variable =>
+        {
+            let lines: Vec<String> = symbol.declarations.iter().map(|declaration| (index.line_of(declaration.name_span.offset) + 1).to_string()).collect();
+            let plural = if lines.len() == 1 { "" } else { "s" };
+            format!("```lox\nvar {}\n```\nGlobal variable, declared on line{} {}.", symbol.name, plural, lines.join(", "))
+        }
+    }
+}
+
+fn completions(analysis: &Analysis, offset: u32) -> Vec<Json>
+{
+    let names = analysis.names_in_scope(offset).into_iter().map(|symbol|
+    {
+        let (kind, detail) = match symbol.kind
+        {
+            SymbolKind::Native { .. } => (COMPLETION_FUNCTION, "native function"),
+            SymbolKind::Variable => (COMPLETION_VARIABLE, "global variable"),
+        };
+        Json::object(vec![("label", Json::string(&symbol.name)), ("kind", Json::Number(kind)), ("detail", Json::string(detail))])
+    });
+    let keywords = KEYWORDS.iter().map(|keyword| Json::object(vec![("label", Json::string(keyword)), ("kind", Json::Number(COMPLETION_KEYWORD))]));
+    return names.chain(keywords).collect();
+}
+
+// Semantic tokens in the LSP's relative encoding: five numbers per token, the
+// line and start as deltas from the previous token. Tokens that span lines,
+// like multi-line strings, are split into one token per line.
+fn semantic_tokens(text: &str, analysis: &Analysis, index: &LineIndex) -> Vec<Json>
+{
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for token in Lexer::lossless(text)
+    {
+        let token_type = match token.token_type
+        {
+            token_type if token_type.is_keyword() => 0,
+            // Every name is global, so the name alone says what an identifier is,
+            // even inside a statement that didn't parse.
+            TokenType::TokenIdentifier => match analysis.symbols.iter().find(|symbol| symbol.name == token.start).map(|symbol| &symbol.kind)
+            {
+                Some(SymbolKind::Native { .. }) => 2,
+                _ => 1,
+            },
+            TokenType::TokenString => 3,
+            TokenType::TokenNumber => 4,
+            TokenType::TokenMinus | TokenType::TokenPlus | TokenType::TokenSlash | TokenType::TokenStar |
+            TokenType::TokenBang | TokenType::TokenBangEqual | TokenType::TokenEqual | TokenType::TokenEqualEqual |
+            TokenType::TokenGreater | TokenType::TokenGreaterEqual | TokenType::TokenLess | TokenType::TokenLessEqual => 5,
+            TokenType::TokenComment => 6,
+            _ => continue,
+        };
+        let is_declaration = token.token_type == TokenType::TokenIdentifier && analysis.symbols.iter()
+            .any(|symbol| symbol.declarations.iter().any(|declaration| declaration.name_span.offset == token.offset));
+        let modifiers = if is_declaration { 1 } else { 0 };
+
+        let mut offset = token.offset as usize;
+        for piece in text[offset..(token.offset + token.length) as usize].split('\n')
+        {
+            let piece_text = piece.trim_end_matches('\r');
+            let (line, start) = index.position(offset as u32);
+            let length: usize = piece_text.chars().map(char::len_utf16).sum();
+            if length > 0
+            {
+                let delta_start = if line == previous_line { start - previous_start } else { start };
+                data.extend([line - previous_line, delta_start, length as u32, token_type, modifiers].iter().map(|&number| Json::Number(number as f64)));
+                previous_line = line;
+                previous_start = start;
+            }
+            offset = offset + piece.len() + 1;
+        }
+    }
+    return data;
+}
+
+impl LineIndex<'_>
+{
+    fn new(text: &str) -> LineIndex<'_>
+    {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(index, _)| index + 1));
+        LineIndex { text: text, line_starts: line_starts }
+    }
+
+    // Zero-based line containing byte `offset`.
+    fn line_of(&self, offset: u32) -> usize
+    {
+        match self.line_starts.binary_search(&(offset as usize))
+        {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    fn position(&self, offset: u32) -> (u32, u32)
+    {
+        let offset = (offset as usize).min(self.text.len());
+        let line = self.line_of(offset as u32);
+        let character: usize = self.text[self.line_starts[line]..offset].chars().map(char::len_utf16).sum();
+        return (line as u32, character as u32);
+    }
+
+    // Byte offset of an LSP `{ line, character }`, clamped to the line's end.
+    fn offset(&self, position: &Json) -> u32
+    {
+        let line = position.get("line").and_then(Json::as_f64).unwrap_or(0.0) as usize;
+        let character = position.get("character").and_then(Json::as_f64).unwrap_or(0.0) as usize;
+        let start = match self.line_starts.get(line)
+        {
+            Some(start) => *start,
+            None => return self.text.len() as u32,
+        };
+        let mut units = 0;
+        for (index, c) in self.text[start..].char_indices()
+        {
+            if units >= character || c == '\n'
+            {
+                return (start + index) as u32;
+            }
+            units = units + c.len_utf16();
+        }
+        return self.text.len() as u32;
+    }
+
+    fn range(&self, span: Span) -> Json
+    {
+        let point = |offset: u32|
+        {
+            let (line, character) = self.position(offset);
+            Json::object(vec![("line", Json::Number(line as f64)), ("character", Json::Number(character as f64))])
+        };
+        Json::object(vec![("start", point(span.offset)), ("end", point(span.end()))])
+    }
+}
@@ -1,54 +1,379 @@
-use std::{env, fs, io::{self, Write}};
+#![allow(non_snake_case, clippy::needless_return, clippy::suspicious_else_formatting, clippy::assign_op_pattern, clippy::redundant_field_names)]
 
-use vm::{InterpretResult};
+use std::{env, fs, io::{self, Read}, path::{Path, PathBuf}};
 
-mod chunk;
-mod debug;
-mod value;
-mod vm;
-mod compile;
-mod scanner;
-mod object;
+mod dap;
+mod debug_shell;
+mod lsp;
+mod repl;
+
+use debug_shell::debug_shell;
+use repl::repl;
+use rust_lox::{debug, format, interpreter::Interpreter, lint::{self, LintConfig, Rule}, loxc, native::Capabilities, optimize::OptLevel, vm::{self, InterpretResult}};
+
+// The bytecode VM is the default; `--tree-walk` selects the reference interpreter.
+enum Engine
+{
+    Bytecode(Box<vm::VM>),
+    TreeWalk(Interpreter),
+}
+
+impl Engine
+{
+    fn interpret(&mut self, source: String) -> InterpretResult
+    {
+        match self
+        {
+            Engine::Bytecode(vm) => vm.interpret(source),
+            Engine::TreeWalk(interpreter) => interpreter.interpret(source),
+        }
+    }
+
+    fn globals(&self) -> Vec<(String, String)>
+    {
+        match self
+        {
+            Engine::Bytecode(vm) => vm.globals(),
+            Engine::TreeWalk(interpreter) => interpreter.globals(),
+        }
+    }
+}
+
+fn new_engine(options: &Options) -> Engine
+{
+    if options.tree_walk
+    {
+        return Engine::TreeWalk(Interpreter::with_capabilities(&cli_capabilities()));
+    }
+    return Engine::Bytecode(Box::new(new_vm(options.opt_level)));
+}
+
+fn new_vm(opt_level: OptLevel) -> vm::VM
+{
+    let mut vm = vm::VM::with_capabilities(&cli_capabilities());
+    vm.set_opt_level(opt_level);
+    return vm;
+}
+
+// Scripts run from the command line are trusted with the clock and with
+// reading files under the working directory.
+fn cli_capabilities() -> Capabilities
+{
+    Capabilities::core().with_time().with_fs(vec![PathBuf::from(".")])
+}
+
+struct Options
+{
+    tree_walk: bool,
+    opt_level: OptLevel,
+    output: Option<String>,
+    json: bool,
+    dot: bool,
+    check: bool,
+    allow: Vec<String>,
+    positional: Vec<String>,
+}
+
+fn parse_options(args: Vec<String>) -> Options
+{
+    let mut options = Options { tree_walk: false, opt_level: OptLevel::O1, output: None, json: false, dot: false, check: false, allow: Vec::new(), positional: Vec::new() };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next()
+    {
+        match arg.as_str()
+        {
+            "--tree-walk" => options.tree_walk = true,
+            "-O0" => options.opt_level = OptLevel::O0,
+            "-O1" => options.opt_level = OptLevel::O1,
+            "-o" => options.output = args.next(),
+            "--json" => options.json = true,
+            "--dot" => options.dot = true,
+            "--check" => options.check = true,
+            "--allow" => options.allow.extend(args.next()),
+            _ => options.positional.push(arg),
+        }
+    }
+    return options;
+}
 
 fn main()
 {
-    let vm = vm::init_vm();
-    let _chunk: chunk::Chunk = chunk::init_chunk();
+    let options = parse_options(env::args().skip(1).collect());
 
-    let args: Vec<String> = env::args().collect();
-    println!("args.len() = {}", args.len());
-    if args.len() == 1 
+    if options.positional.first().map(String::as_str) == Some("compile")
     {
-        repl(vm);
-    } else if args.len() == 2
+        if options.positional.len() != 2
+        {
+            println!("Usage: rust_lox compile [-O0|-O1] in.lox [-o out.loxc]");
+            std::process::exit(64);
+        }
+        CompileFile(&options.positional[1], options.output.as_deref(), options.opt_level);
+        return;
+    }
+
+    if options.positional.first().map(String::as_str) == Some("disasm")
     {
-        RunFile(&args[1]);
+        if options.positional.len() != 2
+        {
+            println!("Usage: rust_lox disasm [-O0|-O1] [--json|--dot] file.lox|file.loxc");
+            std::process::exit(64);
+        }
+        DisassembleFile(&options.positional[1], &options);
+        return;
+    }
+
+    if options.positional.first().map(String::as_str) == Some("debug")
+    {
+        if options.positional.len() != 2
+        {
+            println!("Usage: rust_lox debug file.lox");
+            std::process::exit(64);
+        }
+        let source = fs::read_to_string(&options.positional[1])
+            .expect("Something went wrong reading the file");
+        // Unoptimized code keeps an instruction on every line that has one in the source.
+        debug_shell(new_vm(OptLevel::O0), source);
+        return;
+    }
+
+    if options.positional.first().map(String::as_str) == Some("fmt")
+    {
+        std::process::exit(FormatFiles(&options.positional[1..], options.check));
+    }
+
+    if options.positional.first().map(String::as_str) == Some("lint")
+    {
+        if options.positional.len() < 2
+        {
+            println!("Usage: rust_lox lint [--allow rule]... file.lox...");
+            std::process::exit(64);
+        }
+        std::process::exit(LintFiles(&options.positional[1..], &options.allow));
+    }
+
+    if options.positional.first().map(String::as_str) == Some("dap")
+    {
+        dap::serve(new_vm(OptLevel::O0));
+        return;
+    }
+
+    if options.positional.first().map(String::as_str) == Some("lsp")
+    {
+        std::process::exit(lsp::serve(&cli_capabilities()));
+    }
+
+    if options.positional.is_empty()
+    {
+        repl(|| new_engine(&options));
+    } else if options.positional.len() == 1
+    {
+        RunFile(new_engine(&options), &options.positional[0]);
     } else {
-        println!("Usage: rust_lox [path]");
+        println!("Usage: rust_lox [--tree-walk] [-O0|-O1] [path]");
+        println!("       rust_lox compile [-O0|-O1] in.lox [-o out.loxc]");
+        println!("       rust_lox disasm [-O0|-O1] [--json|--dot] file.lox|file.loxc");
+        println!("       rust_lox debug file.lox");
+        println!("       rust_lox dap");
+        println!("       rust_lox lsp");
+        println!("       rust_lox fmt [--check] [file.lox...]");
+        println!("       rust_lox lint [--allow rule]... file.lox...");
     }
 }
 
-fn repl(mut vm: vm::VM)
+fn RunFile(mut engine: Engine, path: &str)
 {
-    let mut line = String::new();
-    loop 
+    let result = if path.ends_with(".loxc")
     {
-        print!("> ");
-        io::stdout().flush();
-        let _input = std::io::stdin().read_line(&mut line).unwrap();
-        vm.interpret(line);
-        line = String::new();
-        //break;
+        let bytes = fs::read(path)
+            .expect("Something went wrong reading the file");
+        match &mut engine
+        {
+            Engine::Bytecode(vm) => vm.interpret_loxc(&bytes).unwrap_or_else(|error|
+            {
+                println!("{}: {}", path, error);
+                std::process::exit(65);
+            }),
+            Engine::TreeWalk(_) =>
+            {
+                println!("--tree-walk cannot run compiled files.");
+                std::process::exit(64);
+            }
+        }
+    } else
+    {
+        let source = fs::read_to_string(path)
+            .expect("Something went wrong reading the file");
+        engine.interpret(source)
+    };
+
+    match result
+    {
+        InterpretResult::InterpretCompileError => std::process::exit(65),
+        InterpretResult::InterpretRuntimeError | InterpretResult::InterpretAborted(_) => std::process::exit(70),
+        InterpretResult::InterpretOk => (),
     }
 }
 
-fn RunFile(path: &String)
+fn CompileFile(path: &str, output: Option<&str>, opt_level: OptLevel)
 {
-    let _source = fs::read_to_string(path)
+    let source = fs::read_to_string(path)
         .expect("Something went wrong reading the file");
+    let output = match output
+    {
+        Some(output) => PathBuf::from(output),
+        None => Path::new(path).with_extension("loxc"),
+    };
+
+    let mut vm = vm::init_vm();
+    vm.set_opt_level(opt_level);
+    match vm.compile(source)
+    {
+        Some(chunk) => fs::write(&output, loxc::serialize(&chunk, &vm.heap))
+            .expect("Something went wrong writing the compiled file"),
+        None => std::process::exit(65),
+    }
+}
+
+// Prints a source-interleaved listing of the code compiled from `path`, or just
+// the code for a `.loxc` file, which has no source. `--json` and `--dot` print
+// the instructions or the control-flow graph instead.
+fn DisassembleFile(path: &str, options: &Options)
+{
+    let mut vm = vm::init_vm();
+    vm.set_opt_level(options.opt_level);
+    let (chunk, source) = if path.ends_with(".loxc")
+    {
+        let bytes = fs::read(path)
+            .expect("Something went wrong reading the file");
+        match loxc::deserialize(&bytes, &mut vm.heap)
+        {
+            Ok(chunk) => (chunk, None),
+            Err(error) =>
+            {
+                println!("{}: {}", path, error);
+                std::process::exit(65);
+            }
+        }
+    } else
+    {
+        let source = fs::read_to_string(path)
+            .expect("Something went wrong reading the file");
+        match vm.compile(source.clone())
+        {
+            Some(chunk) => (chunk, Some(source)),
+            None => std::process::exit(65),
+        }
+    };
 
-    let result: u8 = InterpretResult::InterpretCompileError as u8; //interpret(source);
+    if options.json
+    {
+        println!("{}", debug::render_json(path, &debug::disassemble(&chunk, &vm.heap)));
+        return;
+    }
+    if options.dot
+    {
+        print!("{}", debug::render_dot(path, &debug::disassemble(&chunk, &vm.heap)));
+        return;
+    }
+    match source
+    {
+        Some(source) => print!("{}", debug::render_listing(path, &source, &chunk, &vm.heap)),
+        None => print!("{}", debug::render_text(path, &debug::disassemble(&chunk, &vm.heap))),
+    }
+}
+
+// Rewrites each file in canonical layout, or with `check` only lists the files
+// that aren't. Without files, formats stdin to stdout. Returns the exit code:
+// 1 if `check` found unformatted code, 65 if a file didn't scan.
+fn FormatFiles(paths: &[String], check: bool) -> i32
+{
+    if paths.is_empty()
+    {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)
+            .expect("Something went wrong reading stdin");
+        return match format::format(&source)
+        {
+            Ok(formatted) if check => if formatted == source { 0 } else { 1 },
+            Ok(formatted) =>
+            {
+                print!("{}", formatted);
+                0
+            }
+            Err(diagnostics) =>
+            {
+                diagnostics.iter().for_each(|diagnostic| diagnostic.report());
+                65
+            }
+        };
+    }
 
-    if result == InterpretResult::InterpretCompileError as u8 { std::process::exit(65); }
-    if result == InterpretResult::InterpretRuntimeError as u8 { std::process::exit(70); }
+    let mut exit_code = 0;
+    for path in paths
+    {
+        let source = fs::read_to_string(path)
+            .expect("Something went wrong reading the file");
+        match format::format(&source)
+        {
+            Ok(formatted) if formatted == source => (),
+            Ok(_) if check =>
+            {
+                println!("{}: not formatted", path);
+                exit_code = exit_code.max(1);
+            }
+            Ok(formatted) => fs::write(path, formatted)
+                .expect("Something went wrong writing the file"),
+            Err(diagnostics) =>
+            {
+                for diagnostic in diagnostics
+                {
+                    println!("{}: {}", path, diagnostic);
+                }
+                exit_code = 65;
+            }
+        }
+    }
+    return exit_code;
+}
+
+// Prints the lint warnings for each file. Returns the exit code: 1 if there
+// were warnings, 65 if a file didn't compile, 64 for an unknown rule.
+fn LintFiles(paths: &[String], allow: &[String]) -> i32
+{
+    let mut config = LintConfig::new();
+    for name in allow
+    {
+        match Rule::from_name(name)
+        {
+            Some(rule) => config.allow(rule),
+            None =>
+            {
+                let rules: Vec<&str> = Rule::ALL.iter().map(|rule| rule.name()).collect();
+                println!("Unknown rule '{}'. The rules are: {}.", name, rules.join(", "));
+                return 64;
+            }
+        }
+    }
+    let natives: Vec<(&str, u8)> = cli_capabilities().natives().iter().map(|native| (native.name, native.arity)).collect();
+
+    let mut exit_code = 0;
+    for path in paths
+    {
+        let source = fs::read_to_string(path)
+            .expect("Something went wrong reading the file");
+        let (diagnostics, code) = match lint::lint(&source, &natives, &config)
+        {
+            Ok(warnings) => (warnings, 1),
+            Err(errors) => (errors, 65),
+        };
+        for diagnostic in &diagnostics
+        {
+            println!("{}: {}", path, diagnostic);
+        }
+        if !diagnostics.is_empty()
+        {
+            exit_code = exit_code.max(code);
+        }
+    }
+    return exit_code;
 }
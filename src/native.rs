@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+/// The native modules a host lets scripts use. Start from `core` and add
+/// modules with the `with_*` methods:
+///
+/// ```text
+/// let capabilities = Capabilities::core().with_time().with_fs(vec![PathBuf::from("/srv/data")]);
+/// let vm = VM::with_capabilities(&capabilities);
+/// ```
+///
+/// Natives from modules that are left out are not defined, and a script that
+/// refers to one gets an error naming the capability it needs.
+///
+/// Lox has no call expressions yet, so a native is only a named global that
+/// prints as `<native fn>`. Its body will be added along with calls.
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities
+{
+    time: bool,
+    fs: Option<Vec<PathBuf>>, // Directories scripts may read from.
+}
+
+pub struct Native
+{
+    pub name: &'static str,
+    pub arity: u8,
+    pub capability: &'static str, // The module that defines it.
+}
+
+/// Every native, for both `VM` and `Interpreter`.
+const NATIVES: [Native; 2] = [
+    Native { name: "clock", arity: 0, capability: "time" },
+    Native { name: "readFile", arity: 1, capability: "fs" },
+];
+
+impl Capabilities
+{
+    /// The pure language with no access to the host.
+    pub fn core() -> Capabilities
+    {
+        Capabilities { time: false, fs: None }
+    }
+
+    /// Adds `clock()`.
+    pub fn with_time(mut self) -> Capabilities
+    {
+        self.time = true;
+        return self;
+    }
+
+    /// Adds `readFile(path)`, which may only read files inside `allowlist`.
+    pub fn with_fs(mut self, allowlist: Vec<PathBuf>) -> Capabilities
+    {
+        self.fs = Some(allowlist);
+        return self;
+    }
+
+    /// Whether `readFile` may read `path`: the fs module is enabled and the
+    /// path is inside a directory of its allowlist. Paths are canonicalized
+    /// first, so `..` and symlinks can't escape the allowlist.
+    pub fn may_read(&self, path: &Path) -> bool
+    {
+        let allowlist = match &self.fs
+        {
+            Some(allowlist) => allowlist,
+            None => return false,
+        };
+        let path = match path.canonicalize()
+        {
+            Ok(path) => path,
+            Err(_) => return false,
+        };
+        return allowlist.iter().any(|directory| match directory.canonicalize()
+        {
+            Ok(directory) => path.starts_with(directory),
+            Err(_) => false,
+        });
+    }
+
+    /// The natives of the modules the capabilities allow.
+    pub fn natives(&self) -> Vec<&'static Native>
+    {
+        NATIVES.iter().filter(|native| self.allows(native.capability)).collect()
+    }
+
+    /// Names of natives in modules the capabilities leave out, with the
+    /// capability each one needs.
+    pub fn missing(&self) -> Vec<(&'static str, &'static str)>
+    {
+        NATIVES.iter().filter(|native| !self.allows(native.capability)).map(|native| (native.name, native.capability)).collect()
+    }
+
+    fn allows(&self, capability: &str) -> bool
+    {
+        match capability
+        {
+            "time" => self.time,
+            "fs" => self.fs.is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// The runtime error for reading `name` before it is defined. `capability` is
+/// the module that would define it, if it is a native this host left out.
+pub fn undefined_variable(name: &str, capability: Option<&str>) -> String
+{
+    let mut message = "Undefined variable '".to_owned() + name + "'";
+    if let Some(capability) = capability
+    {
+        message = message + " (needs the '" + capability + "' capability, which is not enabled)";
+    }
+    return message;
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    fn names(natives: Vec<&Native>) -> Vec<&'static str>
+    {
+        natives.iter().map(|native| native.name).collect()
+    }
+
+    #[test]
+    fn capabilities_choose_the_natives()
+    {
+        assert_eq!(names(Capabilities::core().natives()), Vec::<&str>::new());
+        assert_eq!(Capabilities::core().missing(), vec![("clock", "time"), ("readFile", "fs")]);
+        assert_eq!(names(Capabilities::core().with_time().natives()), vec!["clock"]);
+        assert_eq!(Capabilities::core().with_time().missing(), vec![("readFile", "fs")]);
+        assert_eq!(names(Capabilities::core().with_time().with_fs(Vec::new()).natives()), vec!["clock", "readFile"]);
+        assert!(Capabilities::core().with_fs(Vec::new()).with_time().missing().is_empty());
+    }
+
+    #[test]
+    fn reads_are_limited_to_the_allowlist()
+    {
+        let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        let capabilities = Capabilities::core().with_fs(vec![root.join("tests")]);
+        assert!(capabilities.may_read(&root.join("tests").join("lox").join("arithmetic.lox")));
+        assert!(!capabilities.may_read(&root.join("Cargo.toml")));
+        assert!(!capabilities.may_read(&root.join("tests").join("..").join("Cargo.toml")));
+        assert!(!capabilities.may_read(&root.join("tests").join("missing.lox")));
+        assert!(!Capabilities::core().may_read(&root.join("tests").join("lox").join("arithmetic.lox")));
+        assert!(!Capabilities::core().with_fs(Vec::new()).may_read(&root.join("Cargo.toml")));
+    }
+}
@@ -1,53 +1,210 @@
-use crate::{value::{NilAsValue, Value}, vm::VM};
-
-
-#[derive(Clone)]
-pub enum ObjType
-{
-    ObjString(Box<ObjString>),
-}
-
-#[derive(Clone)]
-pub struct Obj
-{
-    pub typeOfObject: ObjType,
-}
-
-#[derive(Clone, Hash)]
-pub struct ObjString
-{
-    pub length: u32,
-    pub str: String,
-}
-
-impl PartialEq for ObjString {
-    fn eq(&self, other: &Self) -> bool {
-        self.str.eq(&other.str)
-    }
-}
-impl Eq for ObjString {}
-
-impl Obj
-{
-    pub fn isObjType(value: Value, _object_type: ObjType) -> bool
-    {
-        if value.clone().IsObject()
-        {
-            match value.GetObject().typeOfObject
-            {
-                _object_type => return true,
-                _ => return false,
-            }
-        }
-        return false;
-        // return IS_OBJ(value) && AS_OBJ(value)->typeOfObject == objectType;
-    }
-
-    pub fn CopyString(vm: &mut VM, str: String, length: u32) -> Obj
-    {
-        //let string_table = &vm.strings;
-        vm.TableSet(&vm.strings.clone(), &ObjString { str: str.clone(), length: length }, NilAsValue() );
-        Obj { typeOfObject: ObjType::ObjString(Box::from(ObjString { str: str, length: length }) )}
-        // ObjString { str: str, length: length} } //, obj: Obj { typeOfObject: ObjType::ObjString } }
-    }
-}
\ No newline at end of file
+use crate::table::Table;
+
+
+#[derive(Clone)]
+pub enum ObjType
+{
+    ObjString(ObjString),
+    ObjNative(ObjNative),
+}
+
+#[derive(Clone)]
+pub struct Obj
+{
+    pub typeOfObject: ObjType,
+}
+
+#[derive(Clone)]
+pub struct ObjString
+{
+    pub length: u32,
+    pub hash: u32,
+    pub str: String,
+}
+
+/// A host function exposed to scripts; see `native::Capabilities`.
+#[derive(Clone)]
+pub struct ObjNative
+{
+    pub name: String,
+    pub arity: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ObjKind
+{
+    String,
+    Native,
+}
+
+/// Handle to an object in a `Heap`. It carries the object's kind so type checks
+/// such as `Value::IsString` don't need to look the object up, and for strings
+/// the string's hash so a `Table` can hash its keys without the heap.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ObjRef
+{
+    pub index: u32,
+    pub kind: ObjKind,
+    pub hash: u32,
+}
+
+/// Owns every object a program creates. Strings are interned: creating a string
+/// that already exists returns the existing handle.
+#[derive(Clone)]
+pub struct Heap
+{
+    objects: Vec<Obj>,
+    strings: Table<()>,
+    bytes_allocated: usize,
+    peak_bytes: usize,
+    run_start: usize, // bytes_allocated when the current run began.
+    quota: Option<usize>, // Bytes one run may allocate.
+    collections: usize,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HeapStats
+{
+    pub live_bytes: usize,
+    pub peak_bytes: usize,
+    pub collections: usize,
+    pub objects: usize,
+}
+
+impl Heap
+{
+    pub fn new() -> Heap
+    {
+        Heap { objects: Vec::new(), strings: Table::new(), bytes_allocated: 0, peak_bytes: 0, run_start: 0, quota: None, collections: 0 }
+    }
+
+    pub fn CopyString(&mut self, str: &str) -> ObjRef
+    {
+        let hash = hash_string(str);
+        if let Some(interned) = self.FindString(str, hash)
+        {
+            return interned;
+        }
+        return self.AllocateString(str.to_string(), hash);
+    }
+
+    pub fn TakeString(&mut self, str: String) -> ObjRef
+    {
+        let hash = hash_string(&str);
+        if let Some(interned) = self.FindString(&str, hash)
+        {
+            return interned;
+        }
+        return self.AllocateString(str, hash);
+    }
+
+    /// Like `TakeString`, but returns `None` instead of going past the current
+    /// run's allocation quota. Interning an existing string always succeeds.
+    pub fn TryTakeString(&mut self, str: String) -> Option<ObjRef>
+    {
+        let hash = hash_string(&str);
+        if let Some(interned) = self.FindString(&str, hash)
+        {
+            return Some(interned);
+        }
+        if let Some(quota) = self.quota
+        {
+            if self.bytes_allocated - self.run_start + string_size(&str) > quota
+            {
+                return None;
+            }
+        }
+        return Some(self.AllocateString(str, hash));
+    }
+
+    /// Sets the bytes `TryTakeString` may allocate in one run. Objects created
+    /// by the compiler are counted but never refused.
+    pub fn set_quota(&mut self, quota: Option<usize>)
+    {
+        self.quota = quota;
+    }
+
+    /// Starts a new run, with a fresh allocation quota.
+    pub fn begin_run(&mut self)
+    {
+        self.run_start = self.bytes_allocated;
+    }
+
+    /// Nothing is freed yet, so live bytes only grow and `collections` stays 0
+    /// until the heap has a collector.
+    pub fn stats(&self) -> HeapStats
+    {
+        HeapStats { live_bytes: self.bytes_allocated, peak_bytes: self.peak_bytes, collections: self.collections, objects: self.objects.len() }
+    }
+
+    pub fn AllocateNative(&mut self, name: &str, arity: u8) -> ObjRef
+    {
+        let handle = ObjRef { index: self.objects.len() as u32, kind: ObjKind::Native, hash: 0 };
+        self.bytes_allocated = self.bytes_allocated + std::mem::size_of::<Obj>() + name.len();
+        self.peak_bytes = self.peak_bytes.max(self.bytes_allocated);
+        self.objects.push(Obj { typeOfObject: ObjType::ObjNative(ObjNative { name: name.to_string(), arity: arity }) });
+        return handle;
+    }
+
+    fn FindString(&self, str: &str, hash: u32) -> Option<ObjRef>
+    {
+        let objects = &self.objects;
+        return self.strings.find_string(hash, |handle| match &objects[handle.index as usize].typeOfObject
+        {
+            ObjType::ObjString(string) => string.str == str,
+            _ => false,
+        });
+    }
+
+    fn AllocateString(&mut self, str: String, hash: u32) -> ObjRef
+    {
+        let handle = ObjRef { index: self.objects.len() as u32, kind: ObjKind::String, hash: hash };
+        let length = str.len() as u32;
+        self.bytes_allocated = self.bytes_allocated + string_size(&str);
+        self.peak_bytes = self.peak_bytes.max(self.bytes_allocated);
+        self.objects.push(Obj { typeOfObject: ObjType::ObjString(ObjString { str: str, length: length, hash: hash }) });
+        self.strings.set(handle, ());
+        return handle;
+    }
+
+    pub fn contains(&self, handle: ObjRef) -> bool
+    {
+        match self.objects.get(handle.index as usize)
+        {
+            Some(Obj { typeOfObject: ObjType::ObjString(string) }) => handle.kind == ObjKind::String && handle.hash == string.hash,
+            Some(Obj { typeOfObject: ObjType::ObjNative(_) }) => handle.kind == ObjKind::Native,
+            None => false,
+        }
+    }
+
+    pub fn GetObject(&self, handle: ObjRef) -> &Obj
+    {
+        &self.objects[handle.index as usize]
+    }
+
+    pub fn GetString(&self, handle: ObjRef) -> &ObjString
+    {
+        match &self.GetObject(handle).typeOfObject
+        {
+            ObjType::ObjString(string) => string,
+            _ => panic!("Attempted to get a string from a non-string object!"),
+        }
+    }
+}
+
+// Bytes charged to the heap for a string object.
+fn string_size(str: &str) -> usize
+{
+    std::mem::size_of::<Obj>() + str.len()
+}
+
+// 32-bit FNV-1a, as in clox.
+pub fn hash_string(str: &str) -> u32
+{
+    let mut hash: u32 = 2166136261;
+    for byte in str.bytes()
+    {
+        hash = (hash ^ byte as u32).wrapping_mul(16777619);
+    }
+    return hash;
+}
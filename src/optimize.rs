@@ -0,0 +1,322 @@
+use crate::chunk::{Chunk, OpCode, add_constant, has_constant_operand, init_chunk, write_chunk};
+use crate::object::Heap;
+use crate::value::{self, Value};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum OptLevel
+{
+    O0, // Run the compiler's output as is.
+    O1, // Constant folding and peephole rewrites.
+}
+
+#[derive(Clone)]
+struct Instruction
+{
+    opcode: u8,
+    constant: Option<Value>, // Operand of OpConstant and the global ops.
+    line: u32,
+}
+
+/// Rewrites a compiled chunk in place: folds arithmetic, comparisons and string
+/// concatenation on constants, fuses negated comparisons into single opcodes, and
+/// drops values that are pushed only to be popped. The constant pool is rebuilt
+/// with just the constants that are still referenced.
+///
+/// Finally, the constant-operand forms of hot instruction pairs replace the pairs
+/// (see `fuse`).
+///
+/// Folding only happens when the operation cannot fail, so runtime errors such as
+/// `-"text"` are still reported when the code runs. Folded strings are interned
+/// into `heap`.
+pub fn optimize(chunk: &mut Chunk, heap: &mut Heap)
+{
+    let mut instructions = decode(chunk);
+    while peephole(&mut instructions, heap) {}
+    fuse(&mut instructions);
+    *chunk = encode(&instructions);
+}
+
+fn decode(chunk: &Chunk) -> Vec<Instruction>
+{
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < chunk.code.len()
+    {
+        let opcode = chunk.code[offset];
+        let line = chunk.lines[offset];
+        if has_constant_operand(opcode)
+        {
+            let constant = chunk.constants.values[chunk.code[offset + 1] as usize];
+            instructions.push(Instruction { opcode: opcode, constant: Some(constant), line: line });
+            offset = offset + 2;
+        }
+        else
+        {
+            instructions.push(Instruction { opcode: opcode, constant: None, line: line });
+            offset = offset + 1;
+        }
+    }
+    return instructions;
+}
+
+fn encode(instructions: &[Instruction]) -> Chunk
+{
+    let mut chunk = init_chunk();
+    for instruction in instructions
+    {
+        write_chunk(&mut chunk, instruction.opcode, instruction.line);
+        if let Some(constant) = instruction.constant
+        {
+            let index = add_constant(&mut chunk, constant);
+            write_chunk(&mut chunk, index as u8, instruction.line);
+        }
+    }
+    return chunk;
+}
+
+// Makes one pass over the code and reports whether anything changed.
+fn peephole(instructions: &mut Vec<Instruction>, heap: &mut Heap) -> bool
+{
+    let mut changed = false;
+    let mut i = 0;
+    while i < instructions.len()
+    {
+        let opcode = instructions[i].opcode;
+        let next = instructions.get(i + 1).map(|instruction| instruction.opcode);
+        let after_next = instructions.get(i + 2).map(|instruction| instruction.opcode);
+
+        // a b <binary>  =>  (a <binary> b)
+        if let (Some(a), Some(b), Some(operator)) = (constant_value(&instructions[i]), instructions.get(i + 1).and_then(constant_value), after_next)
+        {
+            if let Some(folded) = fold_binary(operator, a, b, heap)
+            {
+                let line = instructions[i + 2].line;
+                instructions.splice(i..i + 3, std::iter::once(push_instruction(folded, line)));
+                changed = true;
+                continue;
+            }
+        }
+
+        // a <unary>  =>  (<unary> a)
+        if let (Some(a), Some(operator)) = (constant_value(&instructions[i]), next)
+        {
+            if let Some(folded) = fold_unary(operator, a)
+            {
+                let line = instructions[i + 1].line;
+                instructions.splice(i..i + 2, std::iter::once(push_instruction(folded, line)));
+                changed = true;
+                continue;
+            }
+        }
+
+        // <comparison> OpNot  =>  <negated comparison>
+        if next == Some(OpCode::OpNot as u8)
+        {
+            if let Some(fused) = negated_comparison(opcode)
+            {
+                instructions[i].opcode = fused as u8;
+                instructions.remove(i + 1);
+                changed = true;
+                continue;
+            }
+        }
+
+        if next == Some(OpCode::OpPop as u8)
+        {
+            // <constant> OpPop  =>  nothing
+            if is_constant_push(opcode)
+            {
+                instructions.drain(i..i + 2);
+                changed = true;
+                i = i.saturating_sub(2);
+                continue;
+            }
+            // OpNot OpPop  =>  OpPop
+            if opcode == OpCode::OpNot as u8
+            {
+                instructions.remove(i);
+                changed = true;
+                i = i.saturating_sub(2);
+                continue;
+            }
+            // <equality> OpPop  =>  OpPop OpPop
+            if opcode == OpCode::OpEqual as u8 || opcode == OpCode::OpNotEqual as u8
+            {
+                instructions[i].opcode = OpCode::OpPop as u8;
+                changed = true;
+                i = i.saturating_sub(2);
+                continue;
+            }
+        }
+
+        i = i + 1;
+    }
+    return changed;
+}
+
+// Replaces instruction pairs with superinstructions that do the same work in one
+// dispatch. Runs after folding so it never hides a foldable pair.
+//
+// Straight-line scripts are mostly `x = x + <constant>;`, so OpConstant OpAdd is
+// the only pair fused for now. Loops and locals will add OpGetLocal/OpSetLocal
+// and compare-and-jump pairs worth fusing.
+fn fuse(instructions: &mut Vec<Instruction>)
+{
+    let mut i = 0;
+    while i + 1 < instructions.len()
+    {
+        // OpConstant c OpAdd  =>  OpAddConstant c
+        if instructions[i].opcode == OpCode::OpConstant as u8 && instructions[i + 1].opcode == OpCode::OpAdd as u8
+        {
+            instructions[i].opcode = OpCode::OpAddConstant as u8;
+            // Keep the line of the add, where a type error is reported.
+            instructions[i].line = instructions[i + 1].line;
+            instructions.remove(i + 1);
+        }
+        i = i + 1;
+    }
+}
+
+fn is_constant_push(opcode: u8) -> bool
+{
+    opcode == OpCode::OpConstant as u8
+        || opcode == OpCode::OpNil as u8
+        || opcode == OpCode::OpTrue as u8
+        || opcode == OpCode::OpFalse as u8
+}
+
+fn constant_value(instruction: &Instruction) -> Option<Value>
+{
+    match instruction.opcode
+    {
+        x if x == OpCode::OpConstant as u8 => instruction.constant,
+        x if x == OpCode::OpNil as u8 => Some(value::NilAsValue()),
+        x if x == OpCode::OpTrue as u8 => Some(value::BoolAsValue(true)),
+        x if x == OpCode::OpFalse as u8 => Some(value::BoolAsValue(false)),
+        _ => None,
+    }
+}
+
+fn push_instruction(value: Value, line: u32) -> Instruction
+{
+    if value.IsNil()
+    {
+        return Instruction { opcode: OpCode::OpNil as u8, constant: None, line: line };
+    }
+    if value.IsBool()
+    {
+        let opcode = if value.GetBool() { OpCode::OpTrue } else { OpCode::OpFalse };
+        return Instruction { opcode: opcode as u8, constant: None, line: line };
+    }
+    return Instruction { opcode: OpCode::OpConstant as u8, constant: Some(value), line: line };
+}
+
+fn negated_comparison(opcode: u8) -> Option<OpCode>
+{
+    match opcode
+    {
+        x if x == OpCode::OpEqual as u8 => Some(OpCode::OpNotEqual),
+        x if x == OpCode::OpLess as u8 => Some(OpCode::OpGreaterEqual),
+        x if x == OpCode::OpGreater as u8 => Some(OpCode::OpLessEqual),
+        _ => None,
+    }
+}
+
+fn fold_unary(operator: u8, a: Value) -> Option<Value>
+{
+    match operator
+    {
+        x if x == OpCode::OpNot as u8 => Some(value::BoolAsValue(a.IsFalsey())),
+        x if x == OpCode::OpNegate as u8 && a.IsNumber() => Some(value::NumberAsValue(-a.GetNumber())),
+        _ => None,
+    }
+}
+
+fn fold_binary(operator: u8, a: Value, b: Value, heap: &mut Heap) -> Option<Value>
+{
+    match operator
+    {
+        x if x == OpCode::OpEqual as u8 => return Some(value::BoolAsValue(a.Equals(&b))),
+        x if x == OpCode::OpNotEqual as u8 => return Some(value::BoolAsValue(!a.Equals(&b))),
+        x if x == OpCode::OpAdd as u8 && a.IsString() && b.IsString() =>
+        {
+            let both = heap.GetString(a.GetObject()).str.clone() + &heap.GetString(b.GetObject()).str;
+            return Some(value::ObjAsValue(heap.TakeString(both)));
+        }
+        _ => (),
+    }
+
+    if !a.IsNumber() || !b.IsNumber()
+    {
+        return None;
+    }
+    let (a, b) = (a.GetNumber(), b.GetNumber());
+    match operator
+    {
+        x if x == OpCode::OpAdd as u8 => Some(value::NumberAsValue(a + b)),
+        x if x == OpCode::OpSubtract as u8 => Some(value::NumberAsValue(a - b)),
+        x if x == OpCode::OpMultiply as u8 => Some(value::NumberAsValue(a * b)),
+        x if x == OpCode::OpDivide as u8 => Some(value::NumberAsValue(a / b)),
+        x if x == OpCode::OpGreater as u8 => Some(value::BoolAsValue(a > b)),
+        x if x == OpCode::OpLess as u8 => Some(value::BoolAsValue(a < b)),
+        x if x == OpCode::OpGreaterEqual as u8 => Some(value::BoolAsValue(a.partial_cmp(&b) != Some(std::cmp::Ordering::Less))),
+        x if x == OpCode::OpLessEqual as u8 => Some(value::BoolAsValue(a.partial_cmp(&b) != Some(std::cmp::Ordering::Greater))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use crate::debug::{Operand, disassemble};
+    use crate::vm;
+
+    // The optimized code for `source`, one "OpName operand" string per instruction.
+    fn optimized(source: &str) -> Vec<String>
+    {
+        let mut vm = vm::init_vm();
+        let chunk = vm.try_compile(source).expect("the source should compile");
+        return disassemble(&chunk, &vm.heap).iter().map(|instruction|
+        {
+            match instruction.operands.first()
+            {
+                Some(Operand::Constant { preview, .. }) => format!("{} {}", instruction.name, preview),
+                _ => instruction.name.clone(),
+            }
+        }).collect();
+    }
+
+    #[test]
+    fn folds_constant_arithmetic()
+    {
+        assert_eq!(optimized("print 2 * 3 + 1;"), vec!["OpConstant 7", "OpPrint", "OpReturn"]);
+        assert_eq!(optimized("print \"a\" + \"b\" == \"ab\";"), vec!["OpTrue", "OpPrint", "OpReturn"]);
+    }
+
+    #[test]
+    fn fuses_negated_comparisons()
+    {
+        assert_eq!(optimized("print !(a < b);"), vec!["OpGetGlobal a", "OpGetGlobal b", "OpGreaterEqual", "OpPrint", "OpReturn"]);
+        assert_eq!(optimized("print !(a > b);"), vec!["OpGetGlobal a", "OpGetGlobal b", "OpLessEqual", "OpPrint", "OpReturn"]);
+        assert_eq!(optimized("print !(a == b);"), vec!["OpGetGlobal a", "OpGetGlobal b", "OpNotEqual", "OpPrint", "OpReturn"]);
+    }
+
+    #[test]
+    fn fuses_constant_adds()
+    {
+        assert_eq!(optimized("print a + 1;"), vec!["OpGetGlobal a", "OpAddConstant 1", "OpPrint", "OpReturn"]);
+    }
+
+    #[test]
+    fn removes_dead_statements()
+    {
+        assert_eq!(optimized("1;"), vec!["OpReturn"]);
+        assert_eq!(optimized("1; print 2;"), vec!["OpConstant 2", "OpPrint", "OpReturn"]);
+    }
+
+    #[test]
+    fn leaves_failing_operations_for_the_runtime()
+    {
+        assert_eq!(optimized("print -\"text\";"), vec!["OpConstant text", "OpNegate", "OpPrint", "OpReturn"]);
+    }
+}
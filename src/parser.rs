@@ -0,0 +1,309 @@
+use crate::ast::{BinaryOp, Expr, Literal, Span, Stmt, UnaryOp};
+use crate::compile::{Precedence, infix_precedence};
+use crate::diagnostic::Diagnostic;
+use crate::scanner::{self, Make_Token, Scan_Token, Scanner, Token, TokenType};
+
+/// Parses a whole program into statements, or returns every error found.
+pub fn parse(source: &str) -> Result<Vec<Stmt>, Vec<Diagnostic>>
+{
+    let (statements, diagnostics) = parse_recovering(source);
+    if diagnostics.is_empty()
+    {
+        return Ok(statements);
+    }
+    return Err(diagnostics);
+}
+
+/// Parses as much of a program as it can, for tools that work on code while
+/// it is being edited. Returns the statements that parsed cleanly, leaving out
+/// any with errors, and every error found.
+pub fn parse_recovering(source: &str) -> (Vec<Stmt>, Vec<Diagnostic>)
+{
+    let mut parser = AstParser::new(source);
+    let mut statements = Vec::new();
+
+    while !parser.match_token(TokenType::TokenEof)
+    {
+        if let Some(statement) = parser.declaration()
+        {
+            statements.push(statement);
+        }
+    }
+    return (statements, parser.diagnostics);
+}
+
+/// Parses a single expression with nothing after it, such as a debugger's
+/// watch expression.
+pub fn parse_expression(source: &str) -> Result<Expr, Vec<Diagnostic>>
+{
+    let mut parser = AstParser::new(source);
+    let expr = parser.expression();
+    parser.consume(TokenType::TokenEof, "Expect end of expression.");
+
+    if parser.diagnostics.is_empty()
+    {
+        return Ok(expr);
+    }
+    return Err(parser.diagnostics);
+}
+
+/// Pratt parser over the same grammar as `Compiler`, building a tree instead
+/// of emitting bytecode.
+pub struct AstParser
+{
+    scanner: Scanner,
+    current: Token,
+    previous: Token,
+    panic_mode: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl AstParser
+{
+    pub fn new(source: &str) -> AstParser
+    {
+        let scanner = scanner::Init_Scanner(source.to_string());
+        let start = Make_Token(TokenType::TokenEof, &scanner);
+        let mut parser = AstParser
+        {
+            scanner: scanner,
+            current: start.clone(),
+            previous: start,
+            panic_mode: false,
+            diagnostics: Vec::new(),
+        };
+        parser.advance();
+        return parser;
+    }
+
+    fn advance(&mut self)
+    {
+        self.previous = self.current.clone();
+
+        loop
+        {
+            self.current = Scan_Token(&mut self.scanner);
+            if self.current.token_type != TokenType::TokenError
+            {
+                break;
+            }
+            self.error_at_current(self.current.start.clone());
+        }
+    }
+
+    fn consume(&mut self, token_type: TokenType, message: &str)
+    {
+        if self.current.token_type == token_type
+        {
+            self.advance();
+            return;
+        }
+        self.error_at_current(message.to_string());
+    }
+
+    fn match_token(&mut self, token_type: TokenType) -> bool
+    {
+        if self.current.token_type != token_type
+        {
+            return false;
+        }
+
+        self.advance();
+        return true;
+    }
+
+    fn declaration(&mut self) -> Option<Stmt>
+    {
+        let statement = if self.match_token(TokenType::TokenVar)
+        {
+            self.var_declaration()
+        }
+        else
+        {
+            self.statement()
+        };
+
+        if self.panic_mode
+        {
+            self.synchronize();
+            return None;
+        }
+        return Some(statement);
+    }
+
+    fn var_declaration(&mut self) -> Stmt
+    {
+        let start = Span::from_token(&self.previous);
+        self.consume(TokenType::TokenIdentifier, "Expect variable name.");
+        let name = self.previous.start.clone();
+        let name_span = Span::from_token(&self.previous);
+
+        let initializer = if self.match_token(TokenType::TokenEqual)
+        {
+            Some(self.expression())
+        } else
+        {
+            None
+        };
+
+        self.consume(TokenType::TokenSemicolon, "Expect ';' after variable declaration.");
+
+        Stmt::Var { name: name, name_span: name_span, initializer: initializer, span: start.to(Span::from_token(&self.previous)) }
+    }
+
+    fn statement(&mut self) -> Stmt
+    {
+        if self.match_token(TokenType::TokenPrint)
+        {
+            let start = Span::from_token(&self.previous);
+            let expr = self.expression();
+            self.consume(TokenType::TokenSemicolon, "Expect ';' after value.");
+            return Stmt::Print { expr: expr, span: start.to(Span::from_token(&self.previous)) };
+        }
+
+        let start = Span::from_token(&self.current);
+        let expr = self.expression();
+        self.consume(TokenType::TokenSemicolon, "Expect ';' after expression.");
+        return Stmt::Expression { expr: expr, span: start.to(Span::from_token(&self.previous)) };
+    }
+
+    fn synchronize(&mut self)
+    {
+        self.panic_mode = false;
+
+        while self.current.token_type != TokenType::TokenEof
+        {
+            if self.previous.token_type == TokenType::TokenSemicolon
+            {
+                return
+            }
+
+            match self.current.token_type
+            {
+                TokenType::TokenClass | TokenType::TokenFun | TokenType::TokenVar | TokenType::TokenFor |
+                TokenType::TokenIf | TokenType::TokenWhile | TokenType::TokenPrint | TokenType::TokenReturn => return,
+                _ => (),
+            }
+
+            self.advance();
+        }
+    }
+
+    fn expression(&mut self) -> Expr
+    {
+        self.parse_precedence(Precedence::PrecAssignment)
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> Expr
+    {
+        self.advance();
+        let can_assign = precedence <= Precedence::PrecAssignment;
+
+        // Carries on into the operators after a missing operand, as `Compiler`
+        // does, so both resynchronize at the same token.
+        let mut expr = match self.prefix(can_assign)
+        {
+            Some(expr) => expr,
+            None =>
+            {
+                self.error("Expect expression".to_owned());
+                Expr::Literal { value: Literal::Nil, span: Span::from_token(&self.previous) }
+            }
+        };
+
+        while precedence as u8 <= infix_precedence(self.current.token_type) as u8
+        {
+            self.advance();
+            expr = self.binary(expr);
+        }
+
+        if can_assign && self.match_token(TokenType::TokenEqual)
+        {
+            self.error("Invalid assignment target.".to_owned());
+        }
+        return expr;
+    }
+
+    fn prefix(&mut self, can_assign: bool) -> Option<Expr>
+    {
+        let token = self.previous.clone();
+        let span = Span::from_token(&token);
+        match token.token_type
+        {
+            TokenType::TokenLeftParen =>
+            {
+                let expr = self.expression();
+                self.consume(TokenType::TokenRightParen, "Expect ')' after expression.");
+                Some(Expr::Grouping { expr: Box::new(expr), span: span.to(Span::from_token(&self.previous)) })
+            }
+            TokenType::TokenMinus | TokenType::TokenBang =>
+            {
+                let operator = if token.token_type == TokenType::TokenMinus { UnaryOp::Negate } else { UnaryOp::Not };
+                let operand = self.parse_precedence(Precedence::PrecUnary);
+                Some(Expr::Unary { operator: operator, operator_span: span, operand: Box::new(operand) })
+            }
+            TokenType::TokenIdentifier =>
+            {
+                if can_assign && self.match_token(TokenType::TokenEqual)
+                {
+                    let value = self.expression();
+                    return Some(Expr::Assign { name: token.start, name_span: span, value: Box::new(value) });
+                }
+                Some(Expr::Variable { name: token.start, span: span })
+            }
+            TokenType::TokenString =>
+            {
+                let contents = token.start[1..(token.length - 1) as usize].to_string();
+                Some(Expr::Literal { value: Literal::String(contents), span: span })
+            }
+            TokenType::TokenNumber => Some(Expr::Literal { value: Literal::Number(token.start.parse().unwrap()), span: span }),
+            TokenType::TokenFalse => Some(Expr::Literal { value: Literal::Bool(false), span: span }),
+            TokenType::TokenTrue => Some(Expr::Literal { value: Literal::Bool(true), span: span }),
+            TokenType::TokenNil => Some(Expr::Literal { value: Literal::Nil, span: span }),
+            _ => None,
+        }
+    }
+
+    fn binary(&mut self, left: Expr) -> Expr
+    {
+        let operator_type = self.previous.token_type;
+        let operator_span = Span::from_token(&self.previous);
+        let precedence = infix_precedence(operator_type);
+        let right = self.parse_precedence(Precedence::get_precedence(precedence as u8 + 1).unwrap());
+
+        let operator = match operator_type
+        {
+            TokenType::TokenPlus => BinaryOp::Add,
+            TokenType::TokenMinus => BinaryOp::Subtract,
+            TokenType::TokenStar => BinaryOp::Multiply,
+            TokenType::TokenSlash => BinaryOp::Divide,
+            TokenType::TokenBangEqual => BinaryOp::NotEqual,
+            TokenType::TokenEqualEqual => BinaryOp::Equal,
+            TokenType::TokenGreater => BinaryOp::Greater,
+            TokenType::TokenGreaterEqual => BinaryOp::GreaterEqual,
+            TokenType::TokenLess => BinaryOp::Less,
+            _ => BinaryOp::LessEqual,
+        };
+        Expr::Binary { operator: operator, operator_span: operator_span, left: Box::new(left), right: Box::new(right) }
+    }
+
+    fn error_at_current(&mut self, message: String)
+    {
+        self.error_at(&self.current.clone(), message);
+    }
+
+    fn error(&mut self, message: String)
+    {
+        self.error_at(&self.previous.clone(), message);
+    }
+
+    fn error_at(&mut self, token: &Token, message: String)
+    {
+        if self.panic_mode
+        {
+            return;
+        }
+        self.panic_mode = true;
+        self.diagnostics.push(Diagnostic::at_token(token, message));
+    }
+}
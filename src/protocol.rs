@@ -0,0 +1,51 @@
+//! The `Content-Length` framing shared by the Debug Adapter Protocol and the
+//! Language Server Protocol: a header block ended by an empty line, then a JSON
+//! body of exactly that many bytes.
+
+use std::io::{self, BufRead, Write};
+
+use crate::json::Json;
+
+/// Reads the next message, or returns None at end of input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>>
+{
+    let mut length = None;
+    loop
+    {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0
+        {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty()
+        {
+            break;
+        }
+        // Other headers, such as `Content-Type`, are allowed and ignored.
+        if let Some((name, value)) = header.split_once(':')
+        {
+            if name.trim().eq_ignore_ascii_case("Content-Length")
+            {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = match length
+    {
+        Some(length) => length,
+        None => return Err(io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length header")),
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "message body is not UTF-8"))?;
+    return Json::parse(&body).map(Some).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error));
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()>
+{
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    return output.flush();
+}
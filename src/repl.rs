@@ -0,0 +1,210 @@
+use std::{fs, time::Instant};
+
+use rust_lox::{ast::Stmt, debug::disassemble_chunk, line_editor::{LineEditor, ReadLine}, parser, scanner::{Lexer, TokenType}};
+
+use crate::Engine;
+
+const HELP: &str = "\
+:help              Show this list.
+:globals           List the defined globals and their values.
+:dis <code>        Compile <code> and show its bytecode without running it.
+:trace on|off      Print the stack and each instruction as code runs.
+:load <file>       Run a file; its globals stay defined.
+:reset             Forget every global and start over.
+:time <code>       Run <code> and report how long it took.
+:gc                Show heap statistics.
+Ctrl-D exits.";
+
+/// Reads and runs entries until end of input. An entry continues over several
+/// lines while its brackets or a string are left open, and an entry that is a
+/// lone expression without a trailing `;` has its value printed. Lines starting
+/// with `:` are commands; see `HELP`.
+pub fn repl(new_engine: impl Fn() -> Engine)
+{
+    let mut engine = new_engine();
+    let mut editor = LineEditor::new(Some(LineEditor::default_history_path()));
+    let mut source = String::new();
+    loop
+    {
+        let prompt = if source.is_empty() { "> " } else { "... " };
+        match editor.read_line(prompt)
+        {
+            Ok(ReadLine::Line(line)) =>
+            {
+                if source.is_empty() && line.trim_start().starts_with(':')
+                {
+                    command(line.trim(), &mut engine, &new_engine);
+                    continue;
+                }
+                source.push_str(&line);
+                source.push('\n');
+            }
+            Ok(ReadLine::Interrupted) =>
+            {
+                source.clear();
+                continue;
+            }
+            Ok(ReadLine::Eof) | Err(_) => return,
+        }
+
+        if source.trim().is_empty()
+        {
+            source.clear();
+            continue;
+        }
+        if is_incomplete(&source)
+        {
+            continue;
+        }
+        let entry = std::mem::take(&mut source);
+        engine.interpret(echo_bare_expression(&entry).unwrap_or(entry));
+    }
+}
+
+fn command(line: &str, engine: &mut Engine, new_engine: &impl Fn() -> Engine)
+{
+    let (name, argument) = match line.find(char::is_whitespace)
+    {
+        Some(index) => (&line[..index], line[index..].trim()),
+        None => (line, ""),
+    };
+    match (name, argument)
+    {
+        (":help", _) => println!("{}", HELP),
+        (":globals", _) =>
+        {
+            for (name, value) in engine.globals()
+            {
+                println!("{} = {}", name, value);
+            }
+        }
+        (":dis", code) if !code.is_empty() =>
+        {
+            let Engine::Bytecode(vm) = engine else
+            {
+                println!("There is no bytecode to show with --tree-walk.");
+                return;
+            };
+            let code = if code.ends_with(';') || code.ends_with('}') { code.to_string() } else { code.to_string() + ";" };
+            if let Some(chunk) = vm.compile(code)
+            {
+                disassemble_chunk(&chunk, &vm.heap, "code".to_string());
+            }
+        }
+        (":trace", setting @ ("on" | "off")) =>
+        {
+            match engine
+            {
+                Engine::Bytecode(vm) => vm.set_trace(setting == "on"),
+                Engine::TreeWalk(_) => println!("Tracing needs the bytecode VM."),
+            }
+        }
+        (":load", path) if !path.is_empty() =>
+        {
+            match fs::read_to_string(path)
+            {
+                Ok(source) =>
+                {
+                    engine.interpret(source);
+                }
+                Err(error) => println!("Could not read '{}': {}.", path, error),
+            }
+        }
+        (":reset", _) => *engine = new_engine(),
+        (":time", code) if !code.is_empty() =>
+        {
+            let code = code.to_string() + "\n";
+            let start = Instant::now();
+            engine.interpret(echo_bare_expression(&code).unwrap_or(code));
+            println!("({:.3} ms)", start.elapsed().as_secs_f64() * 1000.0);
+        }
+        (":gc", _) =>
+        {
+            match engine
+            {
+                Engine::Bytecode(vm) =>
+                {
+                    // There is no collector yet, so this only reports.
+                    let stats = vm.heap_stats();
+                    println!("{} objects, {} bytes live, {} bytes peak, {} collections", stats.objects, stats.live_bytes, stats.peak_bytes, stats.collections);
+                }
+                Engine::TreeWalk(_) => println!("Heap statistics need the bytecode VM."),
+            }
+        }
+        _ => println!("Unknown command '{}'. Type :help for the list.", line),
+    }
+}
+
+// True while a `(` or `{` is unclosed or a string is unterminated.
+fn is_incomplete(source: &str) -> bool
+{
+    let mut depth: i32 = 0;
+    for token in Lexer::new(source)
+    {
+        match token.token_type
+        {
+            TokenType::TokenLeftParen | TokenType::TokenLeftBrace => depth = depth + 1,
+            TokenType::TokenRightParen | TokenType::TokenRightBrace => depth = depth - 1,
+            TokenType::TokenError if token.start == "Unterminated string." => return true,
+            _ => (),
+        }
+    }
+    return depth > 0;
+}
+
+// If the entry only parses once a `;` is added, and then ends in an expression
+// statement, rewrites that statement as a `print`.
+fn echo_bare_expression(source: &str) -> Option<String>
+{
+    let trimmed = source.trim_end();
+    if trimmed.ends_with(';') || trimmed.ends_with('}') || parser::parse(source).is_ok()
+    {
+        return None;
+    }
+    let candidate = trimmed.to_string() + ";";
+    match parser::parse(&candidate).ok()?.last()
+    {
+        Some(Stmt::Expression { span, .. }) =>
+        {
+            let offset = span.offset as usize;
+            return Some(candidate[..offset].to_string() + "print " + &candidate[offset..] + "\n");
+        }
+        _ => return None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn open_brackets_and_strings_continue_the_entry()
+    {
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("{ var a = 1;"));
+        assert!(is_incomplete("print \"two\nlines"));
+        assert!(!is_incomplete("print (1 + 2);"));
+        assert!(!is_incomplete("{ }"));
+        assert!(!is_incomplete("print 1 +"));
+        assert!(!is_incomplete(")"));
+    }
+
+    #[test]
+    fn bare_expressions_are_printed()
+    {
+        assert_eq!(echo_bare_expression("1 + 2"), Some("print 1 + 2;\n".to_string()));
+        assert_eq!(echo_bare_expression("var a = 1; a"), Some("var a = 1; print a;\n".to_string()));
+        assert_eq!(echo_bare_expression("a = 3  "), Some("print a = 3;\n".to_string()));
+    }
+
+    #[test]
+    fn statements_are_left_alone()
+    {
+        assert_eq!(echo_bare_expression("1 + 2;"), None);
+        assert_eq!(echo_bare_expression("print 1"), None);
+        assert_eq!(echo_bare_expression("var a = 1"), None);
+        assert_eq!(echo_bare_expression("{ 1; }"), None);
+        assert_eq!(echo_bare_expression("1 +"), None);
+    }
+}
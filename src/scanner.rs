@@ -1,350 +1,471 @@
-#[derive(Clone)]
-pub struct Scanner
-{
-    source: String,
-    start: u32,
-    current: u32,
-    line: u32,
-}
-
-#[derive(Clone)]
-pub struct Token
-{
-    pub token_type: TokenType,
-    pub start: String,
-    pub length: u32,
-    pub line: u32
-}
-
-pub fn Init_Scanner(source: String) -> Scanner
-{
-    return Scanner {source: source.clone(), start: 0, current: 0, line: 1};
-}
-
-pub fn Scan_Token(scanner: &mut Scanner) -> Token
-{
-    scanner.start = scanner.current;
-    Skip_Whitespace(scanner);
-    if get_character_from_scanner_source(scanner.current, scanner).eq(&'\0')
-    {
-        return Make_Token(TokenType::TokenEof, &scanner);
-    }
-
-    let character = advance(scanner);
-
-    match character
-    {
-        x if x == '(' => return Make_Token(TokenType::TokenLeftParen, scanner),
-        x if x == ')' => return Make_Token(TokenType::TokenRightParen, scanner),
-        x if x == '{' => return Make_Token(TokenType::TokenLeftBrace, scanner),
-        x if x == '}' => return Make_Token(TokenType::TokenRightBrace, scanner),
-        x if x == ';' => return Make_Token(TokenType::TokenSemicolon, scanner),
-        x if x == ',' => return Make_Token(TokenType::TokenComma, scanner),
-        x if x == '.' => return Make_Token(TokenType::TokenDot, scanner),
-        x if x == '-' => return Make_Token(TokenType::TokenMinus, scanner),
-        x if x == '+' => return Make_Token(TokenType::TokenPlus, scanner),
-        x if x == '/' => return Make_Token(TokenType::TokenSlash, scanner),
-        x if x == '*' => return Make_Token(TokenType::TokenStar, scanner),
-        x if x == '!' => 
-        {
-            if matchCharacter('=', scanner)
-            {
-                return Make_Token(TokenType::TokenBangEqual, scanner);
-            }
-            return Make_Token(TokenType::TokenBang, scanner);
-        }
-        x if x == '=' => 
-        {
-            if matchCharacter('=', scanner)
-            {
-                return Make_Token(TokenType::TokenEqualEqual, scanner); // " ==" is made into the token " ="
-            }
-            return Make_Token(TokenType::TokenEqual, scanner);
-        }
-        x if x == '<' => 
-        {
-            if matchCharacter('=', scanner)
-            {
-                return Make_Token(TokenType::TokenLessEqual, scanner);
-            }
-            return Make_Token(TokenType::TokenLess, scanner);
-        }
-        x if x == '>' => 
-        {
-            if matchCharacter('=', scanner)
-            {
-                return Make_Token(TokenType::TokenGreaterEqual, scanner);
-            }
-            return Make_Token(TokenType::TokenGreater, scanner);
-        }
-        x if x == '"' => return string_token(scanner),
-        x if x.is_ascii_digit() => return digit(scanner),
-        x if x.is_alphanumeric() => return identifier(scanner),
-        _ => return Error_Token(&"Unexpected character".to_string(), scanner),
-    }
-}
-
-fn identifier(scanner: &mut Scanner) -> Token
-{
-    loop
-    {
-        if peek(scanner).is_alphanumeric() || peek(scanner).is_ascii_digit()
-        {
-            advance(scanner);
-        }
-        else
-        {
-            return Make_Token(identifier_type(scanner), scanner);
-        }
-    }
-}
-
-fn identifier_type(scanner: &mut Scanner) -> TokenType
-{
-    match get_character_from_scanner_source(scanner.start, scanner)
-    {
-        x if x.eq(&'a') => return check_keyword(1, 2, "nd".to_string(), TokenType::TokenAnd, scanner),
-        x if x.eq(&'c') => return check_keyword(1, 4, "lass".to_string(), TokenType::TokenClass, scanner),
-        x if x.eq(&'e') => return check_keyword(1, 3, "lse".to_string(), TokenType::TokenElse, scanner),
-        x if x.eq(&'i') => return check_keyword(1, 1, "f".to_string(), TokenType::TokenIf, scanner),
-        x if x.eq(&'n') => return check_keyword(1, 2, "il".to_string(), TokenType::TokenNil, scanner),
-        x if x.eq(&'o') => return check_keyword(1, 1, "r".to_string(), TokenType::TokenOr, scanner),
-        x if x.eq(&'p') => return check_keyword(1, 4, "rint".to_string(), TokenType::TokenPrint, scanner),
-        x if x.eq(&'r') => return check_keyword(1, 5, "eturn".to_string(), TokenType::TokenReturn, scanner),
-        x if x.eq(&'s') => return check_keyword(1, 4, "uper".to_string(), TokenType::TokenSuper, scanner),
-        x if x.eq(&'v') => return check_keyword(1, 2, "ar".to_string(), TokenType::TokenVar, scanner),
-        x if x.eq(&'w') => return check_keyword(1, 4, "hile".to_string(), TokenType::TokenWhile, scanner),
-        x if x.eq(&'f') =>
-        {
-            if (scanner.current - scanner.start) > 1
-            {
-                match get_character_from_scanner_source(scanner.start + 1, scanner)
-                {
-                    x if x.eq(&'a') => return check_keyword(2, 3, "lse".to_string(), TokenType::TokenFalse, scanner),
-                    x if x.eq(&'o') => return check_keyword(2, 1, "r".to_string(), TokenType::TokenFor, scanner),
-                    x if x.eq(&'u') => return check_keyword(2, 1, "n".to_string(), TokenType::TokenFun, scanner),
-                    _ => return TokenType::TokenIdentifier
-                }
-            }
-        }
-        x  if x.eq(&'t') =>
-        {
-            if (scanner.current - scanner.start) > 1
-            {
-                match get_character_from_scanner_source(scanner.start + 1, scanner)
-                {
-                    x if x.eq(&'h') => return check_keyword(2, 2, "is".to_string(), TokenType::TokenThis, scanner),
-                    x if x.eq(&'r') => return check_keyword(2, 2, "ue".to_string(), TokenType::TokenTrue, scanner),
-                    _ => return TokenType::TokenIdentifier
-                }
-            }
-        }
-        _ => 
-        {
-            print!("No match! {}", get_character_from_scanner_source(scanner.start, scanner));
-            return TokenType::TokenIdentifier;
-        }
-    }
-    return TokenType::TokenIdentifier;
-}
-
-fn check_keyword(start: u32, length: u32, the_rest: String, token: TokenType, scanner: &mut Scanner) -> TokenType
-{
-    println!("Debug: {}", scanner.source.clone()[(scanner.start + start) as usize..((scanner.start + start) + length) as usize].to_string());
-    println!("The rest: {}", the_rest);
-    if scanner.source.clone()[(scanner.start + start) as usize..((scanner.start + start) + length) as usize].to_string().eq(&the_rest)
-    {
-        return token;
-    }
-
-    return TokenType::TokenIdentifier;
-}
-
-fn digit(scanner: &mut Scanner) -> Token
-{
-    consume_digits(scanner);
-
-    if get_character_from_scanner_source(scanner.current, scanner).eq(&'.')
-    {
-        advance(scanner);
-        consume_digits(scanner);
-    }
-
-    return Make_Token(TokenType::TokenNumber, scanner);
-}
-
-fn consume_digits(scanner: &mut Scanner)
-{
-    loop
-    {
-        if get_character_from_scanner_source(scanner.current, scanner).is_ascii_digit()
-        {
-            advance(scanner);
-        }
-        else
-        {
-            return;
-        }
-    }
-}
-
-fn string_token(scanner: &mut Scanner) -> Token
-{
-    loop
-    {
-        if peek(scanner).ne(&'"') && !isAtEnd(scanner)
-        {
-            if peek(scanner).eq(&'\n')
-            {
-                scanner.line = scanner.line + 1;
-            }
-            advance(scanner);
-        }
-        else
-        {
-            break;
-        }
-    }
-    if isAtEnd(scanner)
-    {
-        return Error_Token(&"Unterminated string.".to_string(), scanner);
-    }
-
-    advance(scanner); // The closing quote.
-    return Make_Token(TokenType::TokenString, scanner);
-}
-
-fn Skip_Whitespace(scanner: &mut Scanner)
-{
-    loop
-    {
-        let character = peek(scanner);
-        match character
-        {
-            x if x == ' ' => consume(scanner),
-            x if x == '\r' => consume(scanner),
-            x if x == '\t' => consume(scanner),
-            x if x == '\n' => 
-            {
-                scanner.line = scanner.line + 1;
-                consume(scanner);
-                break;
-            }
-            x if x == '/' =>
-            {
-                if peekNext(scanner).eq(&'/') && !isAtEnd(scanner)
-                {
-                    loop
-                    {
-                        if peek(scanner).ne(&'\n') && !isAtEnd(scanner)
-                        {
-                            consume(scanner);
-                        }else
-                        {
-                            break;
-                        }
-                    }
-                }
-                break;
-            }
-            _ => break
-        };
-    }
-}
-
-fn consume(scanner: &mut Scanner)
-{
-    scanner.start = scanner.start + 1;
-    scanner.current = scanner.current + 1;
-}
-
-fn peek(scanner: &mut Scanner) -> char
-{
-    return get_character_from_scanner_source(scanner.current, &scanner);
-}
-
-fn isAtEnd(scanner: &Scanner) -> bool
-{
-    return get_character_from_scanner_source(scanner.current, scanner).eq(&'\0');
-}
-
-fn peekNext(scanner: &Scanner) -> char
-{
-    if isAtEnd(scanner)
-    {
-        return '\0';
-    }
-    return get_character_from_scanner_source(scanner.current + 1, scanner);
-}
-
-fn get_character_from_scanner_source(location: u32, scanner: &Scanner) -> char
-{
-    return scanner.source.chars().nth(location as usize).unwrap_or_else(|| '\0');
-}
-
-fn matchCharacter(expected_string: char, scanner: &mut Scanner) -> bool
-{
-    if get_character_from_scanner_source(scanner.current, scanner).eq(&'\0')
-    {
-        return false;
-    }
-    if get_character_from_scanner_source(scanner.current, scanner).eq(&expected_string)
-    {
-        scanner.current = scanner.current + 1;
-        return true;
-    }
-    
-    return false;
-}
-
-pub fn advance(scanner: &mut Scanner) -> char
-{
-    scanner.current = scanner.current + 1;
-    return scanner.source.clone()[(scanner.current - 1) as usize..scanner.current as usize].chars().next().unwrap_or_else(|| '❎');
-}
-
-pub fn Make_Token(tokenType: TokenType, scanner: &Scanner) -> Token
-{
-    return Token
-    {
-        token_type: tokenType,
-        start: scanner.source.clone()[scanner.start as usize..scanner.current as usize].to_string(),
-        length: (scanner.current - scanner.start),
-        line: scanner.line
-    };
-}
-
-pub fn Error_Token(error_message: &String, scanner: &Scanner) -> Token
-{
-    return Token
-    {
-        token_type: TokenType::TokenError,
-        start: error_message.clone(),
-        length: error_message.clone().len() as u32,
-        line: scanner.line
-    }
-}
-
-#[derive(Copy, Clone, PartialEq)]
-pub enum TokenType
-{
-    // Single-character tokens.
-    TokenLeftParen, TokenRightParen,
-    TokenLeftBrace, TokenRightBrace,
-    TokenComma, TokenDot, TokenMinus, TokenPlus,
-    TokenSemicolon, TokenSlash, TokenStar,
-    // One or two character tokens.
-    TokenBang, TokenBangEqual,
-    TokenEqual, TokenEqualEqual,
-    TokenGreater, TokenGreaterEqual,
-    TokenLess, TokenLessEqual,
-    // Literals.
-    TokenIdentifier, TokenString, TokenNumber,
-    // Keywords.
-    TokenAnd, TokenClass, TokenElse, TokenFalse,
-    TokenFor, TokenFun, TokenIf, TokenNil, TokenOr,
-    TokenPrint, TokenReturn, TokenSuper, TokenThis,
-    TokenTrue, TokenVar, TokenWhile,
-
-    TokenError, TokenEof
-}
\ No newline at end of file
+#[derive(Clone)]
+pub struct Scanner
+{
+    source: String,
+    start: u32,
+    current: u32,
+    line: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Token
+{
+    pub token_type: TokenType,
+    pub start: String, // The lexeme, or the message for a TokenError.
+    pub length: u32, // Length of the lexeme in bytes.
+    pub line: u32,
+    pub offset: u32, // Byte offset of the lexeme in the source.
+}
+
+/// Iterator over the tokens of a Lox source string.
+///
+/// By default whitespace and comments are skipped, so the tokens are exactly
+/// what the compiler sees. A lossless lexer (`Lexer::lossless`) also yields
+/// them as `TokenWhitespace`, `TokenNewline` and `TokenComment`, so that the
+/// lexemes of all tokens concatenated reproduce the source byte for byte.
+///
+/// Iteration ends before `TokenEof`. Errors are yielded as `TokenError`, whose
+/// `start` holds the message and whose `offset`/`length` cover the offending text.
+#[derive(Clone)]
+pub struct Lexer
+{
+    scanner: Scanner,
+    lossless: bool,
+    finished: bool,
+}
+
+impl Lexer
+{
+    pub fn new(source: &str) -> Lexer
+    {
+        Lexer { scanner: Init_Scanner(source.to_string()), lossless: false, finished: false }
+    }
+
+    pub fn lossless(source: &str) -> Lexer
+    {
+        Lexer { scanner: Init_Scanner(source.to_string()), lossless: true, finished: false }
+    }
+}
+
+impl Iterator for Lexer
+{
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token>
+    {
+        if self.finished
+        {
+            return None;
+        }
+
+        if self.lossless
+        {
+            if let Some(trivia) = Scan_Trivia(&mut self.scanner)
+            {
+                return Some(trivia);
+            }
+        }
+
+        let token = Scan_Token(&mut self.scanner);
+        if token.token_type == TokenType::TokenEof
+        {
+            self.finished = true;
+            return None;
+        }
+        return Some(token);
+    }
+}
+
+impl TokenType
+{
+    pub fn is_trivia(self) -> bool
+    {
+        matches!(self, TokenType::TokenWhitespace | TokenType::TokenNewline | TokenType::TokenComment)
+    }
+
+    pub fn is_keyword(self) -> bool
+    {
+        (self as u8) >= (TokenType::TokenAnd as u8) && (self as u8) <= (TokenType::TokenWhile as u8)
+    }
+}
+
+pub fn Init_Scanner(source: String) -> Scanner
+{
+    return Scanner {source: source, start: 0, current: 0, line: 1};
+}
+
+pub fn Scan_Token(scanner: &mut Scanner) -> Token
+{
+    Skip_Whitespace(scanner);
+    scanner.start = scanner.current;
+    if isAtEnd(scanner)
+    {
+        return Make_Token(TokenType::TokenEof, scanner);
+    }
+
+    let character = advance(scanner);
+
+    match character
+    {
+        x if x == '(' => return Make_Token(TokenType::TokenLeftParen, scanner),
+        x if x == ')' => return Make_Token(TokenType::TokenRightParen, scanner),
+        x if x == '{' => return Make_Token(TokenType::TokenLeftBrace, scanner),
+        x if x == '}' => return Make_Token(TokenType::TokenRightBrace, scanner),
+        x if x == ';' => return Make_Token(TokenType::TokenSemicolon, scanner),
+        x if x == ',' => return Make_Token(TokenType::TokenComma, scanner),
+        x if x == '.' => return Make_Token(TokenType::TokenDot, scanner),
+        x if x == '-' => return Make_Token(TokenType::TokenMinus, scanner),
+        x if x == '+' => return Make_Token(TokenType::TokenPlus, scanner),
+        x if x == '/' => return Make_Token(TokenType::TokenSlash, scanner),
+        x if x == '*' => return Make_Token(TokenType::TokenStar, scanner),
+        x if x == '!' =>
+        {
+            if matchCharacter('=', scanner)
+            {
+                return Make_Token(TokenType::TokenBangEqual, scanner);
+            }
+            return Make_Token(TokenType::TokenBang, scanner);
+        }
+        x if x == '=' =>
+        {
+            if matchCharacter('=', scanner)
+            {
+                return Make_Token(TokenType::TokenEqualEqual, scanner); // " ==" is made into the token " ="
+            }
+            return Make_Token(TokenType::TokenEqual, scanner);
+        }
+        x if x == '<' =>
+        {
+            if matchCharacter('=', scanner)
+            {
+                return Make_Token(TokenType::TokenLessEqual, scanner);
+            }
+            return Make_Token(TokenType::TokenLess, scanner);
+        }
+        x if x == '>' =>
+        {
+            if matchCharacter('=', scanner)
+            {
+                return Make_Token(TokenType::TokenGreaterEqual, scanner);
+            }
+            return Make_Token(TokenType::TokenGreater, scanner);
+        }
+        x if x == '"' => return string_token(scanner),
+        x if x.is_ascii_digit() => return digit(scanner),
+        x if x.is_alphanumeric() || x == '_' => return identifier(scanner),
+        _ => return Error_Token("Unexpected character.", scanner),
+    }
+}
+
+/// Scans a single run of trivia at the current position, if there is one.
+/// Used by the lossless `Lexer`; `Scan_Token` discards the same tokens.
+pub fn Scan_Trivia(scanner: &mut Scanner) -> Option<Token>
+{
+    scanner.start = scanner.current;
+    match peek(scanner)
+    {
+        x if x == '\n' =>
+        {
+            advance(scanner);
+            let token = Make_Token(TokenType::TokenNewline, scanner);
+            scanner.line = scanner.line + 1;
+            return Some(token);
+        }
+        x if x == ' ' || x == '\r' || x == '\t' =>
+        {
+            while peek(scanner) == ' ' || peek(scanner) == '\r' || peek(scanner) == '\t'
+            {
+                advance(scanner);
+            }
+            return Some(Make_Token(TokenType::TokenWhitespace, scanner));
+        }
+        x if x == '/' && peekNext(scanner) == '/' =>
+        {
+            while peek(scanner).ne(&'\n') && !isAtEnd(scanner)
+            {
+                advance(scanner);
+            }
+            return Some(Make_Token(TokenType::TokenComment, scanner));
+        }
+        _ => return None,
+    }
+}
+
+fn identifier(scanner: &mut Scanner) -> Token
+{
+    loop
+    {
+        if peek(scanner).is_alphanumeric() || peek(scanner) == '_'
+        {
+            advance(scanner);
+        }
+        else
+        {
+            return Make_Token(identifier_type(scanner), scanner);
+        }
+    }
+}
+
+fn identifier_type(scanner: &mut Scanner) -> TokenType
+{
+    match get_character_from_scanner_source(scanner.start, scanner)
+    {
+        x if x.eq(&'a') => return check_keyword(1, 2, "nd".to_string(), TokenType::TokenAnd, scanner),
+        x if x.eq(&'c') => return check_keyword(1, 4, "lass".to_string(), TokenType::TokenClass, scanner),
+        x if x.eq(&'e') => return check_keyword(1, 3, "lse".to_string(), TokenType::TokenElse, scanner),
+        x if x.eq(&'i') => return check_keyword(1, 1, "f".to_string(), TokenType::TokenIf, scanner),
+        x if x.eq(&'n') => return check_keyword(1, 2, "il".to_string(), TokenType::TokenNil, scanner),
+        x if x.eq(&'o') => return check_keyword(1, 1, "r".to_string(), TokenType::TokenOr, scanner),
+        x if x.eq(&'p') => return check_keyword(1, 4, "rint".to_string(), TokenType::TokenPrint, scanner),
+        x if x.eq(&'r') => return check_keyword(1, 5, "eturn".to_string(), TokenType::TokenReturn, scanner),
+        x if x.eq(&'s') => return check_keyword(1, 4, "uper".to_string(), TokenType::TokenSuper, scanner),
+        x if x.eq(&'v') => return check_keyword(1, 2, "ar".to_string(), TokenType::TokenVar, scanner),
+        x if x.eq(&'w') => return check_keyword(1, 4, "hile".to_string(), TokenType::TokenWhile, scanner),
+        x if x.eq(&'f') =>
+        {
+            if (scanner.current - scanner.start) > 1
+            {
+                match get_character_from_scanner_source(scanner.start + 1, scanner)
+                {
+                    x if x.eq(&'a') => return check_keyword(2, 3, "lse".to_string(), TokenType::TokenFalse, scanner),
+                    x if x.eq(&'o') => return check_keyword(2, 1, "r".to_string(), TokenType::TokenFor, scanner),
+                    x if x.eq(&'u') => return check_keyword(2, 1, "n".to_string(), TokenType::TokenFun, scanner),
+                    _ => return TokenType::TokenIdentifier
+                }
+            }
+        }
+        x  if x.eq(&'t') =>
+        {
+            if (scanner.current - scanner.start) > 1
+            {
+                match get_character_from_scanner_source(scanner.start + 1, scanner)
+                {
+                    x if x.eq(&'h') => return check_keyword(2, 2, "is".to_string(), TokenType::TokenThis, scanner),
+                    x if x.eq(&'r') => return check_keyword(2, 2, "ue".to_string(), TokenType::TokenTrue, scanner),
+                    _ => return TokenType::TokenIdentifier
+                }
+            }
+        }
+        _ => return TokenType::TokenIdentifier,
+    }
+    return TokenType::TokenIdentifier;
+}
+
+fn check_keyword(start: u32, length: u32, the_rest: String, token: TokenType, scanner: &mut Scanner) -> TokenType
+{
+    if scanner.current - scanner.start == start + length
+        && scanner.source[(scanner.start + start) as usize..scanner.current as usize].eq(&the_rest)
+    {
+        return token;
+    }
+
+    return TokenType::TokenIdentifier;
+}
+
+fn digit(scanner: &mut Scanner) -> Token
+{
+    consume_digits(scanner);
+
+    if peek(scanner).eq(&'.') && peekNext(scanner).is_ascii_digit()
+    {
+        advance(scanner);
+        consume_digits(scanner);
+    }
+
+    return Make_Token(TokenType::TokenNumber, scanner);
+}
+
+fn consume_digits(scanner: &mut Scanner)
+{
+    while peek(scanner).is_ascii_digit()
+    {
+        advance(scanner);
+    }
+}
+
+fn string_token(scanner: &mut Scanner) -> Token
+{
+    loop
+    {
+        if peek(scanner).ne(&'"') && !isAtEnd(scanner)
+        {
+            if peek(scanner).eq(&'\n')
+            {
+                scanner.line = scanner.line + 1;
+            }
+            advance(scanner);
+        }
+        else
+        {
+            break;
+        }
+    }
+    if isAtEnd(scanner)
+    {
+        return Error_Token("Unterminated string.", scanner);
+    }
+
+    advance(scanner); // The closing quote.
+    return Make_Token(TokenType::TokenString, scanner);
+}
+
+fn Skip_Whitespace(scanner: &mut Scanner)
+{
+    while Scan_Trivia(scanner).is_some() {}
+}
+
+fn peek(scanner: &Scanner) -> char
+{
+    return get_character_from_scanner_source(scanner.current, scanner);
+}
+
+fn isAtEnd(scanner: &Scanner) -> bool
+{
+    return scanner.current as usize >= scanner.source.len();
+}
+
+fn peekNext(scanner: &Scanner) -> char
+{
+    if isAtEnd(scanner)
+    {
+        return '\0';
+    }
+    return get_character_from_scanner_source(scanner.current + peek(scanner).len_utf8() as u32, scanner);
+}
+
+// `location` is a byte offset into the source.
+fn get_character_from_scanner_source(location: u32, scanner: &Scanner) -> char
+{
+    return scanner.source.get(location as usize..).and_then(|rest| rest.chars().next()).unwrap_or('\0');
+}
+
+fn matchCharacter(expected_string: char, scanner: &mut Scanner) -> bool
+{
+    if isAtEnd(scanner)
+    {
+        return false;
+    }
+    if peek(scanner).eq(&expected_string)
+    {
+        advance(scanner);
+        return true;
+    }
+
+    return false;
+}
+
+pub fn advance(scanner: &mut Scanner) -> char
+{
+    let character = peek(scanner);
+    if !isAtEnd(scanner)
+    {
+        scanner.current = scanner.current + character.len_utf8() as u32;
+    }
+    return character;
+}
+
+pub fn Make_Token(tokenType: TokenType, scanner: &Scanner) -> Token
+{
+    return Token
+    {
+        token_type: tokenType,
+        start: scanner.source[scanner.start as usize..scanner.current as usize].to_string(),
+        length: (scanner.current - scanner.start),
+        line: scanner.line,
+        offset: scanner.start,
+    };
+}
+
+pub fn Error_Token(error_message: &str, scanner: &Scanner) -> Token
+{
+    return Token
+    {
+        token_type: TokenType::TokenError,
+        start: error_message.to_string(),
+        length: (scanner.current - scanner.start),
+        line: scanner.line,
+        offset: scanner.start,
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TokenType
+{
+    // Single-character tokens.
+    TokenLeftParen, TokenRightParen,
+    TokenLeftBrace, TokenRightBrace,
+    TokenComma, TokenDot, TokenMinus, TokenPlus,
+    TokenSemicolon, TokenSlash, TokenStar,
+    // One or two character tokens.
+    TokenBang, TokenBangEqual,
+    TokenEqual, TokenEqualEqual,
+    TokenGreater, TokenGreaterEqual,
+    TokenLess, TokenLessEqual,
+    // Literals.
+    TokenIdentifier, TokenString, TokenNumber,
+    // Keywords.
+    TokenAnd, TokenClass, TokenElse, TokenFalse,
+    TokenFor, TokenFun, TokenIf, TokenNil, TokenOr,
+    TokenPrint, TokenReturn, TokenSuper, TokenThis,
+    TokenTrue, TokenVar, TokenWhile,
+
+    TokenError, TokenEof,
+    // Trivia, only produced by a lossless Lexer.
+    TokenWhitespace, TokenNewline, TokenComment,
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    // The source text of each token, from its offset and length: a `TokenError`
+    // holds its message instead of its lexeme.
+    fn lexemes(source: &str) -> Vec<&str>
+    {
+        let mut lexemes = Vec::new();
+        let mut end = 0;
+        for token in Lexer::lossless(source)
+        {
+            assert_eq!(token.offset as usize, end, "{:?} doesn't start where the previous token ended", token.start);
+            end = (token.offset + token.length) as usize;
+            if token.token_type != TokenType::TokenError
+            {
+                assert_eq!(token.start, &source[token.offset as usize..end]);
+            }
+            lexemes.push(&source[token.offset as usize..end]);
+        }
+        return lexemes;
+    }
+
+    #[test]
+    fn lossless_tokens_reproduce_the_source()
+    {
+        let sources = [
+            "",
+            "print 1;",
+            "  // only a comment",
+            "var a = 1; // trailing\n\n// own line\nprint a;\n",
+            "var a = 1;\r\nprint a;\r\n// comment\r\n",
+            "print \"héllo, 世界 🦀\"; // ünïcode\nvar ñame = 2;\n",
+            "print \"two\nlines\";\n",
+            "print @ 1; # €\n",
+            "print \"unterminated\n",
+            "\t\tprint\t1 ;   \n   ",
+        ];
+        for source in sources
+        {
+            assert_eq!(lexemes(source).concat(), source);
+        }
+    }
+
+    #[test]
+    fn errors_cover_the_offending_text()
+    {
+        let errors: Vec<(String, &str)> = Lexer::lossless("print @ €;")
+            .filter(|token| token.token_type == TokenType::TokenError)
+            .map(|token| (token.start, &"print @ €;"[token.offset as usize..(token.offset + token.length) as usize]))
+            .collect();
+        assert_eq!(errors, vec![("Unexpected character.".to_string(), "@"), ("Unexpected character.".to_string(), "€")]);
+    }
+}
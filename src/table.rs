@@ -0,0 +1,262 @@
+use crate::object::ObjRef;
+
+const TABLE_MAX_LOAD: f64 = 0.75;
+
+/// Open-addressing hash table keyed by interned string handles.
+///
+/// Keys are compared by handle, which is correct because equal strings share a
+/// handle, and hashed with the string hash the handle carries. Deleting shifts
+/// the rest of the probe sequence back instead of leaving tombstones, so lookups
+/// never walk over dead entries.
+#[derive(Clone)]
+pub struct Table<V: Copy>
+{
+    entries: Vec<Option<Entry<V>>>,
+    count: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Entry<V: Copy>
+{
+    key: ObjRef,
+    value: V,
+}
+
+impl<V: Copy> Table<V>
+{
+    pub fn new() -> Table<V>
+    {
+        Table { entries: Vec::new(), count: 0 }
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.count == 0
+    }
+
+    pub fn get(&self, key: ObjRef) -> Option<V>
+    {
+        if self.count == 0
+        {
+            return None;
+        }
+        return self.entries[self.find_entry(key)].map(|entry| entry.value);
+    }
+
+    /// Inserts or overwrites `key`. Returns true if the key was not already present.
+    pub fn set(&mut self, key: ObjRef, value: V) -> bool
+    {
+        if (self.count + 1) as f64 > self.entries.len() as f64 * TABLE_MAX_LOAD
+        {
+            let capacity = if self.entries.len() < 8 { 8 } else { self.entries.len() * 2 };
+            self.adjust_capacity(capacity);
+        }
+
+        let index = self.find_entry(key);
+        let is_new_key = self.entries[index].is_none();
+        if is_new_key
+        {
+            self.count = self.count + 1;
+        }
+        self.entries[index] = Some(Entry { key: key, value: value });
+        return is_new_key;
+    }
+
+    /// Removes `key`. Returns true if it was present.
+    pub fn delete(&mut self, key: ObjRef) -> bool
+    {
+        if self.count == 0
+        {
+            return false;
+        }
+        let mut hole = self.find_entry(key);
+        if self.entries[hole].is_none()
+        {
+            return false;
+        }
+        self.entries[hole] = None;
+        self.count = self.count - 1;
+
+        // Move later entries of the probe sequence into the hole unless that
+        // would put them before their home slot.
+        let mask = self.entries.len() - 1;
+        let mut index = (hole + 1) & mask;
+        while let Some(entry) = self.entries[index]
+        {
+            let home = entry.key.hash as usize & mask;
+            if (index.wrapping_sub(home) & mask) >= (index.wrapping_sub(hole) & mask)
+            {
+                self.entries[hole] = Some(entry);
+                self.entries[index] = None;
+                hole = index;
+            }
+            index = (index + 1) & mask;
+        }
+        return true;
+    }
+
+    /// Looks a key up by its hash and a content check instead of by handle. Used
+    /// to intern strings before a handle for them exists.
+    pub fn find_string(&self, hash: u32, matches: impl Fn(ObjRef) -> bool) -> Option<ObjRef>
+    {
+        if self.count == 0
+        {
+            return None;
+        }
+        let mask = self.entries.len() - 1;
+        let mut index = hash as usize & mask;
+        while let Some(entry) = self.entries[index]
+        {
+            if entry.key.hash == hash && matches(entry.key)
+            {
+                return Some(entry.key);
+            }
+            index = (index + 1) & mask;
+        }
+        return None;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ObjRef, V)> + '_
+    {
+        self.entries.iter().flatten().map(|entry| (entry.key, entry.value))
+    }
+
+    // Index of the entry holding `key`, or of the empty slot where it belongs.
+    // The load factor guarantees there is always an empty slot to stop at.
+    fn find_entry(&self, key: ObjRef) -> usize
+    {
+        let mask = self.entries.len() - 1;
+        let mut index = key.hash as usize & mask;
+        loop
+        {
+            match self.entries[index]
+            {
+                Some(entry) if entry.key != key => index = (index + 1) & mask,
+                _ => return index,
+            }
+        }
+    }
+
+    fn adjust_capacity(&mut self, capacity: usize)
+    {
+        let old = std::mem::replace(&mut self.entries, vec![None; capacity]);
+        for entry in old.into_iter().flatten()
+        {
+            let index = self.find_entry(entry.key);
+            self.entries[index] = Some(entry);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::object::ObjKind;
+
+    // A key whose home slot in an 8-entry table is `hash & 7`.
+    fn key(index: u32, hash: u32) -> ObjRef
+    {
+        ObjRef { index: index, kind: ObjKind::String, hash: hash }
+    }
+
+    // The index of the key in each slot.
+    fn slots(table: &Table<u32>) -> Vec<Option<u32>>
+    {
+        table.entries.iter().map(|entry| entry.map(|entry| entry.key.index)).collect()
+    }
+
+    #[test]
+    fn deleting_shifts_the_probe_sequence_back()
+    {
+        let mut table = Table::new();
+        let (a, b, c) = (key(0, 1), key(1, 1), key(2, 1));
+        table.set(a, 10);
+        table.set(b, 11);
+        table.set(c, 12);
+        assert_eq!(slots(&table), vec![None, Some(0), Some(1), Some(2), None, None, None, None]);
+
+        assert!(table.delete(a));
+        assert_eq!(slots(&table), vec![None, Some(1), Some(2), None, None, None, None, None]);
+        assert_eq!((table.get(a), table.get(b), table.get(c), table.len()), (None, Some(11), Some(12), 2));
+    }
+
+    #[test]
+    fn deleting_leaves_entries_in_their_home_slot()
+    {
+        let mut table = Table::new();
+        let (a, b, c, d) = (key(0, 1), key(1, 1), key(2, 3), key(3, 2));
+        table.set(a, 10);
+        table.set(b, 11);
+        table.set(c, 12);
+        table.set(d, 13);
+        assert_eq!(slots(&table), vec![None, Some(0), Some(1), Some(2), Some(3), None, None, None]);
+
+        // `c` is home already; `d` can move back to slot 2 but not further.
+        assert!(table.delete(b));
+        assert_eq!(slots(&table), vec![None, Some(0), Some(3), Some(2), None, None, None, None]);
+        assert_eq!((table.get(a), table.get(c), table.get(d)), (Some(10), Some(12), Some(13)));
+    }
+
+    #[test]
+    fn deleting_wraps_around_the_end()
+    {
+        let mut table = Table::new();
+        let (a, b, c) = (key(0, 7), key(1, 7), key(2, 0));
+        table.set(a, 10);
+        table.set(b, 11);
+        table.set(c, 12);
+        assert_eq!(slots(&table), vec![Some(1), Some(2), None, None, None, None, None, Some(0)]);
+
+        assert!(table.delete(a));
+        assert_eq!(slots(&table), vec![Some(2), None, None, None, None, None, None, Some(1)]);
+        assert_eq!((table.get(b), table.get(c)), (Some(11), Some(12)));
+    }
+
+    #[test]
+    fn deleting_a_missing_key_changes_nothing()
+    {
+        let mut table: Table<u32> = Table::new();
+        assert!(!table.delete(key(0, 1)));
+        table.set(key(0, 1), 10);
+        assert!(!table.delete(key(1, 1)));
+        assert!(table.delete(key(0, 1)));
+        assert!(!table.delete(key(0, 1)));
+        assert!(table.is_empty());
+        assert!(table.set(key(0, 1), 20));
+        assert_eq!(table.get(key(0, 1)), Some(20));
+    }
+
+    #[test]
+    fn matches_a_simple_map_through_many_collisions()
+    {
+        let mut table = Table::new();
+        let mut expected: Vec<Option<u32>> = vec![None; 64];
+        let mut seed: u32 = 12345;
+        for step in 0..5000
+        {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            let index = (seed >> 16) % 64;
+            let target = key(index, index % 5); // Few distinct hashes, so long probe runs.
+            if (seed >> 24) & 1 == 0
+            {
+                assert_eq!(table.set(target, step), expected[index as usize].is_none());
+                expected[index as usize] = Some(step);
+            } else
+            {
+                assert_eq!(table.delete(target), expected[index as usize].is_some());
+                expected[index as usize] = None;
+            }
+            assert_eq!(table.len(), expected.iter().flatten().count());
+            for (other, value) in expected.iter().enumerate()
+            {
+                assert_eq!(table.get(key(other as u32, other as u32 % 5)), *value, "step {}", step);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt;
+
+use crate::chunk::{Chunk, OpCode};
+use crate::object::Heap;
+
+/// Why a chunk was rejected, and where.
+#[derive(Debug, PartialEq)]
+pub struct VerifyError
+{
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for VerifyError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        write!(f, "Invalid bytecode at offset {}: {}", self.offset, self.message)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Operand
+{
+    None,
+    Constant, // Index of any constant.
+    Name, // Index of a string constant naming a global.
+}
+
+// (operand, values popped, values pushed)
+fn instruction_shape(opcode: u8) -> Option<(Operand, usize, usize)>
+{
+    match opcode
+    {
+        x if x == OpCode::OpConstant as u8 => Some((Operand::Constant, 0, 1)),
+        x if x == OpCode::OpReturn as u8 => Some((Operand::None, 0, 0)),
+        x if x == OpCode::OpNegate as u8 => Some((Operand::None, 1, 1)),
+        x if x == OpCode::OpAdd as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpSubtract as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpMultiply as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpDivide as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpNil as u8 => Some((Operand::None, 0, 1)),
+        x if x == OpCode::OpTrue as u8 => Some((Operand::None, 0, 1)),
+        x if x == OpCode::OpFalse as u8 => Some((Operand::None, 0, 1)),
+        x if x == OpCode::OpNot as u8 => Some((Operand::None, 1, 1)),
+        x if x == OpCode::OpEqual as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpGreater as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpLess as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpPrint as u8 => Some((Operand::None, 1, 0)),
+        x if x == OpCode::OpPop as u8 => Some((Operand::None, 1, 0)),
+        x if x == OpCode::OpDefineGlobal as u8 => Some((Operand::Name, 1, 0)),
+        x if x == OpCode::OpGetGlobal as u8 => Some((Operand::Name, 0, 1)),
+        x if x == OpCode::OpSetGlobal as u8 => Some((Operand::Name, 1, 1)),
+        x if x == OpCode::OpNotEqual as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpGreaterEqual as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpLessEqual as u8 => Some((Operand::None, 2, 1)),
+        x if x == OpCode::OpAddConstant as u8 => Some((Operand::Constant, 1, 1)),
+        _ => None,
+    }
+}
+
+/// Checks that a chunk is safe to hand to `VM::run`: every opcode is known, every
+/// operand is present and in bounds, global names are string constants, the line
+/// table covers the code, no instruction pops more than is on the stack, and the
+/// stack is empty again at every `OpReturn`. Object constants must be live
+/// handles into `heap`.
+///
+/// The code is straight-line, so a single pass visits every instruction in
+/// execution order. Jump targets will need checking against instruction
+/// boundaries once the compiler emits jumps.
+pub fn verify(chunk: &Chunk, heap: &Heap) -> Result<(), VerifyError>
+{
+    if chunk.lines.len() != chunk.code.len()
+    {
+        return Err(VerifyError { offset: 0, message: format!("Line table has {} entries for {} bytes of code.", chunk.lines.len(), chunk.code.len()) });
+    }
+
+    for (index, constant) in chunk.constants.values.iter().enumerate()
+    {
+        if constant.IsObject() && !heap.contains(constant.GetObject())
+        {
+            return Err(VerifyError { offset: 0, message: format!("Constant {} refers to an object that does not exist.", index) });
+        }
+    }
+
+    let mut depth: usize = 0;
+    let mut offset = 0;
+    while offset < chunk.code.len()
+    {
+        let opcode = chunk.code[offset];
+        let (operand, pops, pushes) = match instruction_shape(opcode)
+        {
+            Some(shape) => shape,
+            None => return Err(VerifyError { offset: offset, message: format!("Unknown opcode {}.", opcode) }),
+        };
+
+        if operand != Operand::None
+        {
+            let index = match chunk.code.get(offset + 1)
+            {
+                Some(index) => *index as usize,
+                None => return Err(VerifyError { offset: offset, message: "Missing operand.".to_string() }),
+            };
+            let constant = match chunk.constants.values.get(index)
+            {
+                Some(constant) => constant,
+                None => return Err(VerifyError { offset: offset, message: format!("Constant {} out of bounds ({} constants).", index, chunk.constants.values.len()) }),
+            };
+            if operand == Operand::Name && !constant.IsString()
+            {
+                return Err(VerifyError { offset: offset, message: format!("Constant {} used as a variable name is not a string.", index) });
+            }
+        }
+
+        if depth < pops
+        {
+            return Err(VerifyError { offset: offset, message: "Stack underflow.".to_string() });
+        }
+        depth = depth - pops + pushes;
+
+        if opcode == OpCode::OpReturn as u8 && depth != 0
+        {
+            return Err(VerifyError { offset: offset, message: format!("Returning with {} values left on the stack.", depth) });
+        }
+
+        offset = offset + if operand == Operand::None { 1 } else { 2 };
+    }
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::chunk::{add_constant, init_chunk, write_chunk};
+    use crate::value;
+
+    // A chunk of `code` on line 1, with a number and a string constant.
+    fn chunk(heap: &mut Heap, code: &[u8]) -> Chunk
+    {
+        let mut chunk = init_chunk();
+        add_constant(&mut chunk, value::NumberAsValue(1.0));
+        add_constant(&mut chunk, value::ObjAsValue(heap.CopyString("name")));
+        for byte in code
+        {
+            write_chunk(&mut chunk, *byte, 1);
+        }
+        return chunk;
+    }
+
+    fn error(code: &[u8]) -> (usize, String)
+    {
+        let mut heap = Heap::new();
+        let chunk = chunk(&mut heap, code);
+        let error = verify(&chunk, &heap).expect_err("the chunk should be rejected");
+        return (error.offset, error.message);
+    }
+
+    const CONSTANT: u8 = OpCode::OpConstant as u8;
+    const DEFINE_GLOBAL: u8 = OpCode::OpDefineGlobal as u8;
+    const GET_GLOBAL: u8 = OpCode::OpGetGlobal as u8;
+    const PRINT: u8 = OpCode::OpPrint as u8;
+    const ADD: u8 = OpCode::OpAdd as u8;
+    const RETURN: u8 = OpCode::OpReturn as u8;
+
+    #[test]
+    fn accepts_compiled_code()
+    {
+        let mut heap = Heap::new();
+        let chunk = chunk(&mut heap, &[CONSTANT, 0, DEFINE_GLOBAL, 1, GET_GLOBAL, 1, PRINT, RETURN]);
+        assert_eq!(verify(&chunk, &heap), Ok(()));
+    }
+
+    #[test]
+    fn rejects_stack_underflow()
+    {
+        assert_eq!(error(&[CONSTANT, 0, ADD, PRINT, RETURN]), (2, "Stack underflow.".to_string()));
+        assert_eq!(error(&[PRINT, RETURN]), (0, "Stack underflow.".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_constant_indexes()
+    {
+        assert_eq!(error(&[CONSTANT, 2, PRINT, RETURN]), (0, "Constant 2 out of bounds (2 constants).".to_string()));
+        assert_eq!(error(&[GET_GLOBAL, 200, PRINT, RETURN]), (0, "Constant 200 out of bounds (2 constants).".to_string()));
+        assert_eq!(error(&[CONSTANT]), (0, "Missing operand.".to_string()));
+    }
+
+    #[test]
+    fn rejects_names_that_are_not_strings()
+    {
+        assert_eq!(error(&[CONSTANT, 1, DEFINE_GLOBAL, 0, RETURN]), (2, "Constant 0 used as a variable name is not a string.".to_string()));
+    }
+
+    #[test]
+    fn rejects_unknown_opcodes()
+    {
+        assert_eq!(error(&[CONSTANT, 0, 255, RETURN]), (2, "Unknown opcode 255.".to_string()));
+    }
+
+    #[test]
+    fn rejects_values_left_at_return_and_short_line_tables()
+    {
+        assert_eq!(error(&[CONSTANT, 0, RETURN]), (2, "Returning with 1 values left on the stack.".to_string()));
+
+        let mut heap = Heap::new();
+        let mut chunk = chunk(&mut heap, &[RETURN]);
+        chunk.lines.clear();
+        assert_eq!(verify(&chunk, &heap).unwrap_err().message, "Line table has 0 entries for 1 bytes of code.");
+    }
+}
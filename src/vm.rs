@@ -1,376 +1,864 @@
-
-use std::{cell::RefCell, collections::HashMap};
-
-use crate::{chunk::{self, Chunk, init_chunk}, compile::{Compiler}, debug::disassemble_instruction, object::{Obj, ObjString, ObjType}, value::{self, InternalNil, Value, ValueType, print_value}};
-
-#[derive(Clone)]
-pub struct VM
-{
-    chunk: Chunk,
-    instructions: Vec<u8>,
-    stack: Vec<Value>,
-    StackTop: u32,
-    ip: u16,
-    pub strings: RefCell<HashMap<ObjString, Value>>,
-    globals: RefCell<HashMap<ObjString, Value>>,
-}
-
-impl VM
-{ 
-
-    pub fn interpret(&mut self, source: String) -> InterpretResult
-    {
-        let mut chunk = init_chunk();
-    
-        if !Compiler::new_compiler(&mut chunk, self).compile(source)
-        {
-            return InterpretResult::InterpretCompileError;
-        }
-    
-        self.chunk = chunk;
-        self.instructions = self.chunk.code.clone();
-    
-        let result = self.run();
-        return result;
-    }
-
-    fn peek(&self, distance: u32) -> Value
-    {
-        self.stack[((self.StackTop -1) - distance) as usize].clone()
-    }
-
-    pub fn push(&mut self, value: Value)
-    {
-        self.stack.push(value);
-        self.StackTop = self.StackTop + 1;
-    }
-
-    pub fn pop(&mut self) -> Value
-    {
-        if self.StackTop == 0
-        {
-            panic!("Attempted to pop from an empty stack!");
-        }
-
-        let stack_pop = self.stack[(self.StackTop - 1) as usize].clone();
-        self.StackTop = self.StackTop - 1;
-        self.stack.remove(self.StackTop as usize);
-
-        return stack_pop;
-    }
-
-    pub fn run(&mut self) -> InterpretResult
-    {
-        let mut slot = 0;
-        while slot < self.StackTop
-        {
-            print_value(self.stack[slot as usize].clone());
-            slot = slot + 1;
-        }
-        let mut i = 0;
-        while i < self.instructions.len()
-        {
-            print!("          ");
-            for slot in &self.stack
-            {
-                print!("[ ");
-                print_value(slot.clone());
-                print!(" ]");
-            }
-            println!("");
-            let line = self.instructions[i];
-            disassemble_instruction(&self.chunk, i as u8);
-            match line
-            {
-                x if x == chunk::OpCode::OpReturn as u8 =>
-                {
-                    //print_value(self.pop());
-                    //println!("");
-                    return InterpretResult::InterpretOk;
-                }
-                x if x == chunk::OpCode::OpConstant as u8 =>
-                {
-                    let constant = self.chunk.constants.values[self.ip as usize].clone();
-                    self.ip = self.ip + 1;
-                    i = i + 1;
-                    print_value(constant.clone());
-                    println!("");
-                    self.push(constant);
-                }
-                x if x == chunk::OpCode::OpNegate as u8 =>
-                {
-                    if !self.peek(0).IsNumber()
-                    {
-                        self.RuntimeError( "Operand must be a number.".to_string());
-                        return InterpretResult::InterpretRuntimeError;
-                    }
-                    let negated_number = -self.pop().GetNumber();
-                    self.push(crate::value::NumberAsValue(negated_number));
-                }
-                x if x == chunk::OpCode::OpAdd as u8 =>
-                {
-                    if self.peek(0).IsString() && self.peek(1).IsString()
-                    {
-                        self.Concatenate();
-                    }
-                    else if self.peek(0).IsNumber() && self.peek(1).IsNumber()
-                    {
-                        let b = self.pop().GetNumber();
-                        let a = self.pop().GetNumber();
-                        self.push(crate::value::NumberAsValue(a + b));
-    
-                    }
-                    else
-                    {
-                        self.RuntimeError("Operands must be numbers or strings.".to_string());
-                        return InterpretResult::InterpretRuntimeError;
-                    }
-                }
-                x if x == chunk::OpCode::OpSubtract as u8 =>
-                {
-                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
-                    {
-                        self.RuntimeError("Operands must be numbers.".to_string());
-                        return InterpretResult::InterpretRuntimeError;
-                    }
-                    let b = self.pop().GetNumber();
-                    let a = self.pop().GetNumber();
-                    print_value(crate::value::NumberAsValue(a - b));
-                    self.push(crate::value::NumberAsValue(a - b));
-                }
-                x if x == chunk::OpCode::OpMultiply as u8 =>
-                {
-                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
-                    {
-                        self.RuntimeError("Operands must be numbers.".to_string());
-                        return InterpretResult::InterpretRuntimeError;
-                    }
-                    let b = self.pop().GetNumber();
-                    let a = self.pop().GetNumber();
-                    print_value(crate::value::NumberAsValue(a * b));
-                    self.push(crate::value::NumberAsValue(a * b));
-                }
-                x if x == chunk::OpCode::OpDivide as u8 =>
-                {
-                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
-                    {
-                        self.RuntimeError("Operands must be numbers.".to_string());
-                        return InterpretResult::InterpretRuntimeError;
-                    }
-                    let b = self.pop().GetNumber();
-                    let a = self.pop().GetNumber();
-                    print_value(crate::value::NumberAsValue(a / b));
-                    self.push(crate::value::NumberAsValue(a / b));
-                }
-                x if x == chunk::OpCode::OpGreater as u8 =>
-                {
-                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
-                    {
-                        self.RuntimeError("Operands must be numbers.".to_string());
-                        return InterpretResult::InterpretRuntimeError;
-                    }
-                    let b = self.pop().GetNumber();
-                    let a = self.pop().GetNumber();
-                    print_value(crate::value::BoolAsValue(a > b));
-                    self.push(crate::value::BoolAsValue(a > b));
-                }
-                x if x == chunk::OpCode::OpLess as u8 =>
-                {
-                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
-                    {
-                        self.RuntimeError("Operands must be numbers.".to_string());
-                        return InterpretResult::InterpretRuntimeError;
-                    }
-                    let b = self.pop().GetNumber();
-                    let a = self.pop().GetNumber();
-                    print_value(crate::value::BoolAsValue(a < b));
-                    self.push(crate::value::BoolAsValue(a < b));
-                }
-                x if x == chunk::OpCode::OpNil as u8 => self.push(crate::value::NilAsValue()),
-                x if x == chunk::OpCode::OpTrue as u8 => self.push(crate::value::BoolAsValue(true)),
-                x if x == chunk::OpCode::OpFalse as u8 => self.push(crate::value::BoolAsValue(false)),
-                x if x == chunk::OpCode::OpNot as u8 => 
-                {
-                    let boolean_not = self.pop().IsFalsey();
-                    self.push(crate::value::BoolAsValue(boolean_not));
-                }
-                x if x == chunk::OpCode::OpEqual as u8 => 
-                {
-                    let is_equal = self.pop().Equals(self.pop());
-                    self.push(crate::value::BoolAsValue(is_equal))
-                }
-                x if x == chunk::OpCode::OpPrint as u8 => 
-                {
-                    let print = self.pop();
-                    print_value(print);
-                    println!("");
-                }
-                x if x == chunk::OpCode::OpPop as u8 => {self.pop();}
-                x if x == chunk::OpCode::OpDefineGlobal as u8 => 
-                {
-                    let name = self.read_string(); //READ_STRING
-                    match name
-                    {
-                        Some(string) =>
-                        {
-                            self.TableSet(&self.globals.clone(), &string, self.peek(0).clone());
-                            self.pop();
-                        },
-                        _ => return InterpretResult::InterpretRuntimeError,
-                    }
-                }
-                x if x == chunk::OpCode::OpGetGlobal as u8 =>
-                {
-                    let name = self.read_string();
-                    match name
-                    {
-                        Some(string) =>
-                        {
-                            let value = self.TableGet(&self.globals.clone(), &string);
-                            if !value.0
-                            {
-                                self.RuntimeError("Undefined variable '".to_owned()+&string.str.to_owned()+"'");
-                                return InterpretResult::InterpretRuntimeError
-                            } else {
-                                self.push(value.1)
-                            }
-                        }
-                        None => return InterpretResult::InterpretRuntimeError,
-                    }
-                }
-                x if x == chunk::OpCode::OpSetGlobal as u8 =>
-                {
-                    let name = self.read_string();
-                    match name
-                    {
-                        Some(string) =>
-                        {
-                            let value = self.TableSet(&self.globals.clone(), &string, self.peek(0).clone());
-                            if !value
-                            {
-                                self.TableDelete(&self.globals.clone(), &string);
-                                self.RuntimeError("Undefined variable '".to_owned()+&string.str.to_owned()+"'");
-                                return InterpretResult::InterpretRuntimeError
-                            }
-                        }
-                        None => return InterpretResult::InterpretRuntimeError,
-                    }
-                }
-                _ =>
-                {
-                    return InterpretResult::InterpretRuntimeError;
-                }
-            }
-            i = i + 1;
-        }
-        return InterpretResult::InterpretOk;
-    }
-
-    fn Concatenate(&mut self)
-    {
-        let value_of_b = self.pop().GetString();
-        let value_of_a = self.pop().GetString();
-        let length = value_of_a.length + value_of_b.length;
-
-        let both = ObjString {str: value_of_a.str + &value_of_b.str, length: length };
-        self.push(value::ObjAsValue(Obj {typeOfObject: ObjType::ObjString(Box::new(both)) }));
-    }
-
-    fn RuntimeError(&self, error: String)
-    {
-        let instruction = self.ip - self.chunk.code[self.ip as usize -1] as u16;
-        let line_number = self.chunk.lines[instruction as usize];
-        println!("[line {}] in script", line_number);
-        println!("{}", error);
-        self.ResetStack();
-    }
-
-    fn ResetStack(&self)
-    {
-
-    }
-
-    fn read_string(&mut self) -> Option<ObjString>
-    {
-        let val = self.chunk.constants.values[self.ip as usize].clone();
-        match val.ValueType
-        {
-            ValueType::ValObj(obj) =>
-            {
-                match obj.typeOfObject
-                {
-                    ObjType::ObjString(obj_str) => 
-                    {
-                        return Some(*obj_str);
-                    }
-                    _ => self.RuntimeError("Tried to grab a string from a non-string object!".to_owned())
-                }
-            }
-            _ => self.RuntimeError("Tried to grab a string from a non-obj value!".to_owned())
-        }
-        return None
-    }
-
-    pub fn TableSet(&mut self, table: &RefCell<HashMap<ObjString, Value>>, key: &ObjString, value: Value) -> bool
-    {
-        let new_entry = self.FindEntry(table.clone(), &key);
-        table.borrow_mut().insert(key.clone(), value);
-
-        match new_entry
-        {
-            _Value => true,
-            _ => false,
-        }
-    }
-
-    fn TableDelete(&mut self, table: &RefCell<HashMap<ObjString, Value>>, key: &ObjString) -> bool
-    {
-        let entry_exists = self.FindEntry(table.clone(), &key);
-        table.borrow_mut().remove(key);
-        match entry_exists.ValueType
-        {
-            ValueType::ValInternalNil => return false,
-            _ => return true,
-        }
-    }
-
-    fn FindEntry(&self, table: RefCell<HashMap<ObjString, Value>>, key: &ObjString) -> Value
-    {
-        match table.borrow().get(&key)
-        {
-            Some(val) => val.to_owned(),
-            None => InternalNil(),
-        }
-    }
-
-    fn TableGet(&self, table: &RefCell<HashMap<ObjString, Value>>, key: &ObjString) -> (bool, Value)
-    {
-        match table.borrow().get(&key)
-        {
-            Some(val) => (true, val.to_owned()),
-            None => (false, InternalNil()),
-        }
-    }
-}
-
-pub fn init_vm() -> VM
-{
-    VM
-    {
-        chunk: chunk::init_chunk(),
-        instructions: Vec::with_capacity(0),
-        stack: Vec::with_capacity(0),
-        StackTop: 0,
-        ip: 0,
-        strings: RefCell::new(HashMap::new()),
-        globals: RefCell::new(HashMap::new()),
-    }
-}
-
-pub enum InterpretResult
-{
-    InterpretOk = 1,
-    InterpretCompileError = 2,
-    InterpretRuntimeError = 3,
-}
-
+
+use std::{cmp::Ordering, convert::TryFrom, fmt, sync::{Arc, atomic::{AtomicBool, Ordering as AtomicOrdering}}, time::{Duration, Instant}};
+
+use crate::{chunk::{self, Chunk, OpCode, init_chunk}, codegen::CodeGenerator, diagnostic::Diagnostic, loxc::{self, LoadError}, optimize::{self, OptLevel}, parser, compile::{Compiler, debug_trace_execution}, debug::disassemble_instruction, debugger::{DebugHook, Frame}, native::{self, Capabilities}, object::{Heap, HeapStats, ObjRef}, table::Table, value::{self, InternalNil, Value, ValueType, print_value, value_to_string}, verify::{self, VerifyError}};
+
+pub const STACK_MAX: usize = 256;
+
+/// Receives what a script prints, and runtime error reports, in place of stdout.
+pub type OutputFn = Arc<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct VM
+{
+    chunk: Chunk,
+    instructions: Vec<u8>,
+    stack: Vec<Value>, // Preallocated to `stack_max` and never grown past it.
+    stack_max: usize,
+    ip: usize,
+    pub heap: Heap, // Objects referenced by the stack, globals and compiled constants.
+    globals: Vec<Value>, // Indexed by slot; `InternalNil` until the global is defined.
+    global_names: Vec<ObjRef>, // Name of each slot, for error messages.
+    global_slots: Table<usize>,
+    slots: Vec<usize>, // Global slot for each name constant of the running chunk.
+    missing_capabilities: Table<&'static str>, // Natives left out of this VM, and the capability each needs.
+    opt_level: OptLevel,
+    instruction_limit: Option<u64>,
+    time_limit: Option<Duration>,
+    interrupted: Arc<AtomicBool>,
+    trace: bool, // Print the stack and each instruction as it runs.
+    output: Option<OutputFn>, // None writes to stdout.
+}
+
+// How many instructions run between checks of the clock and the interrupt flag.
+const LIMIT_CHECK_INTERVAL: u64 = 1024; // Must be a power of two.
+
+/// Why `VM::run` stopped a script before it finished. The stack is reset
+/// afterwards, so the VM can run another script.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LoxError
+{
+    InstructionLimitExceeded,
+    DeadlineExceeded,
+    Interrupted,
+}
+
+impl fmt::Display for LoxError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+    {
+        match self
+        {
+            LoxError::InstructionLimitExceeded => write!(f, "Instruction limit exceeded."),
+            LoxError::DeadlineExceeded => write!(f, "Execution time limit exceeded."),
+            LoxError::Interrupted => write!(f, "Interrupted."),
+        }
+    }
+}
+
+/// Stops a running `VM` from another thread. The script is aborted with
+/// `LoxError::Interrupted` shortly after `interrupt` is called.
+///
+/// An interrupt only reaches the call that is running when it arrives. Each
+/// `interpret*` and `debug_chunk` call clears it when it starts, so one sent
+/// just before a call is dropped, and a host that cannot tell whether the
+/// call has started yet must keep interrupting until it returns.
+#[derive(Clone)]
+pub struct InterruptHandle
+{
+    interrupted: Arc<AtomicBool>,
+}
+
+impl InterruptHandle
+{
+    pub fn interrupt(&self)
+    {
+        self.interrupted.store(true, AtomicOrdering::Relaxed);
+    }
+}
+
+impl VM
+{ 
+    /// Creates a VM whose globals include the natives of every module
+    /// `capabilities` allows.
+    pub fn with_capabilities(capabilities: &Capabilities) -> VM
+    {
+        let mut vm = init_vm();
+        for native in capabilities.natives()
+        {
+            vm.define_native(native.name, native.arity);
+        }
+        for (name, capability) in capabilities.missing()
+        {
+            let name = vm.heap.CopyString(name);
+            vm.missing_capabilities.set(name, capability);
+        }
+        return vm;
+    }
+
+    /// Defines a global called `name` holding a native function.
+    pub fn define_native(&mut self, name: &str, arity: u8)
+    {
+        let native = self.heap.AllocateNative(name, arity);
+        let name = self.heap.CopyString(name);
+        let slot = self.global_slot(name);
+        self.globals[slot] = value::ObjAsValue(native);
+    }
+
+    pub fn interpret(&mut self, source: String) -> InterpretResult
+    {
+        self.clear_interrupt();
+        match self.compile(source)
+        {
+            Some(chunk) => self.run_chunk(chunk),
+            None => InterpretResult::InterpretCompileError,
+        }
+    }
+
+    /// Compiles `source` without running it, applying the VM's optimization level.
+    /// Strings are interned into this VM. Errors are reported on stdout.
+    pub fn compile(&mut self, source: String) -> Option<Chunk>
+    {
+        match self.try_compile(&source)
+        {
+            Ok(chunk) => Some(chunk),
+            Err(diagnostics) =>
+            {
+                diagnostics.iter().for_each(Diagnostic::report);
+                None
+            }
+        }
+    }
+
+    /// Like `compile`, but returns the errors instead of reporting them.
+    pub fn try_compile(&mut self, source: &str) -> Result<Chunk, Vec<Diagnostic>>
+    {
+        let mut chunk = init_chunk();
+
+        Compiler::new_compiler(&mut chunk, self).compile(source.to_string())?;
+        if self.opt_level == OptLevel::O1
+        {
+            optimize::optimize(&mut chunk, &mut self.heap);
+        }
+        return Ok(chunk);
+    }
+
+    /// Like `interpret`, but compiles through the AST front end
+    /// (`parser::parse` followed by `CodeGenerator`).
+    pub fn interpret_ast(&mut self, source: String) -> InterpretResult
+    {
+        self.clear_interrupt();
+        let program = match parser::parse(&source)
+        {
+            Ok(program) => program,
+            Err(diagnostics) =>
+            {
+                diagnostics.iter().for_each(Diagnostic::report);
+                return InterpretResult::InterpretCompileError;
+            }
+        };
+
+        let mut chunk = init_chunk();
+        if let Err(diagnostics) = CodeGenerator::new(&mut chunk, self, &source).generate(&program)
+        {
+            diagnostics.iter().for_each(Diagnostic::report);
+            return InterpretResult::InterpretCompileError;
+        }
+        if self.opt_level == OptLevel::O1
+        {
+            optimize::optimize(&mut chunk, &mut self.heap);
+        }
+
+        return self.run_chunk(chunk);
+    }
+
+    /// Runs the contents of a `.loxc` file produced by `loxc::serialize`.
+    pub fn interpret_loxc(&mut self, bytes: &[u8]) -> Result<InterpretResult, LoadError>
+    {
+        self.clear_interrupt();
+        let chunk = loxc::deserialize(bytes, &mut self.heap)?;
+        return self.verify_and_run(chunk).map_err(LoadError::Invalid);
+    }
+
+    /// Runs a chunk from outside the compiler after checking it with `verify`.
+    pub fn interpret_chunk(&mut self, chunk: Chunk) -> Result<InterpretResult, VerifyError>
+    {
+        self.clear_interrupt();
+        return self.verify_and_run(chunk);
+    }
+
+    /// Checks `chunk` with `verify` and loads it without running it, so that
+    /// `run` can execute it as many times as a benchmark needs.
+    pub fn load_chunk(&mut self, chunk: Chunk) -> Result<(), VerifyError>
+    {
+        verify::verify(&chunk, &self.heap)?;
+        self.load(chunk);
+        return Ok(());
+    }
+
+    fn verify_and_run(&mut self, chunk: Chunk) -> Result<InterpretResult, VerifyError>
+    {
+        verify::verify(&chunk, &self.heap)?;
+        return Ok(self.run_chunk(chunk));
+    }
+
+    fn run_chunk(&mut self, chunk: Chunk) -> InterpretResult
+    {
+        self.load(chunk);
+        return self.run();
+    }
+
+    fn load(&mut self, chunk: Chunk)
+    {
+        self.chunk = chunk;
+        self.instructions = self.chunk.code.clone();
+        self.link();
+        self.heap.begin_run();
+    }
+
+    /// Runs a chunk like `interpret_chunk`, calling `hook` each time execution
+    /// reaches a new source line.
+    pub fn debug_chunk(&mut self, chunk: Chunk, hook: &mut dyn DebugHook) -> Result<InterpretResult, VerifyError>
+    {
+        self.clear_interrupt();
+        verify::verify(&chunk, &self.heap)?;
+        self.load(chunk);
+        return Ok(self.execute::<true>(Some(hook)));
+    }
+
+    /// The call stack while paused in a debug hook. Scripts only have the
+    /// top-level frame.
+    pub fn frames(&self) -> Vec<Frame>
+    {
+        let line = self.chunk.lines.get(self.ip).copied().unwrap_or(0);
+        return vec![Frame { name: "script".to_string(), line: line }];
+    }
+
+    /// Evaluates `expression` against the current globals and returns its value
+    /// printed. Meant for a debugger paused in a hook, so the paused run is
+    /// saved around it and resumes unaffected, apart from any assignments the
+    /// expression makes.
+    ///
+    /// Parse and compile errors are returned. A runtime error is written to the
+    /// output like any other and gives `Ok(None)`.
+    pub fn evaluate(&mut self, expression: &str) -> Result<Option<String>, Vec<Diagnostic>>
+    {
+        let expr = parser::parse_expression(expression)?;
+        let mut chunk = init_chunk();
+        CodeGenerator::new(&mut chunk, self, expression).generate_expression(&expr)?;
+
+        let saved_chunk = std::mem::replace(&mut self.chunk, chunk);
+        let saved_instructions = std::mem::take(&mut self.instructions);
+        let saved_slots = std::mem::take(&mut self.slots);
+        let saved_stack = self.stack.clone();
+        let saved_ip = self.ip;
+        self.instructions = self.chunk.code.clone();
+        self.link();
+
+        let value = match self.execute::<false>(None)
+        {
+            InterpretResult::InterpretOk => Some(value_to_string(self.peek(0), &self.heap)),
+            _ => None,
+        };
+
+        self.chunk = saved_chunk;
+        self.instructions = saved_instructions;
+        self.slots = saved_slots;
+        self.stack.clear();
+        self.stack.extend_from_slice(&saved_stack);
+        self.ip = saved_ip;
+        return Ok(value);
+    }
+
+    pub fn set_opt_level(&mut self, opt_level: OptLevel)
+    {
+        self.opt_level = opt_level;
+    }
+
+    /// Sets the most values the stack may hold before execution fails with
+    /// "Stack overflow.". The stack is reallocated to exactly that size.
+    pub fn set_stack_max(&mut self, stack_max: usize)
+    {
+        self.stack_max = stack_max;
+        self.stack = Vec::with_capacity(stack_max);
+    }
+
+    /// Limits each run to `limit` instructions, or removes the limit.
+    pub fn set_instruction_limit(&mut self, limit: Option<u64>)
+    {
+        self.instruction_limit = limit;
+    }
+
+    /// Limits each run to `limit` of wall-clock time, or removes the limit.
+    pub fn set_time_limit(&mut self, limit: Option<Duration>)
+    {
+        self.time_limit = limit;
+    }
+
+    pub fn set_trace(&mut self, trace: bool)
+    {
+        self.trace = trace;
+    }
+
+    /// Sends printed values and runtime errors to `output` instead of stdout.
+    /// Compile errors are still printed; tracing always goes to stdout.
+    pub fn set_output(&mut self, output: Option<OutputFn>)
+    {
+        self.output = output;
+    }
+
+    fn write_output(&self, text: &str)
+    {
+        match &self.output
+        {
+            Some(output) => output(text),
+            None => print!("{}", text),
+        }
+    }
+
+    /// Each defined global's name and printed value, sorted by name.
+    pub fn globals(&self) -> Vec<(String, String)>
+    {
+        let mut globals: Vec<(String, String)> = self.global_names.iter().zip(&self.globals)
+            .filter(|(_, value)| value.ValueType != ValueType::ValInternalNil)
+            .map(|(name, value)| (self.heap.GetString(*name).str.clone(), value_to_string(*value, &self.heap)))
+            .collect();
+        globals.sort();
+        return globals;
+    }
+
+    /// Sets how many bytes of objects one run of a script may allocate. Going
+    /// past it is an "Out of memory." runtime error. This is a quota per run,
+    /// not a cap on the heap: nothing is freed yet, so a VM that runs many
+    /// scripts keeps every object they made and its heap grows without bound.
+    pub fn set_allocation_quota(&mut self, quota: Option<usize>)
+    {
+        self.heap.set_quota(quota);
+    }
+
+    pub fn heap_stats(&self) -> HeapStats
+    {
+        return self.heap.stats();
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle
+    {
+        InterruptHandle { interrupted: Arc::clone(&self.interrupted) }
+    }
+
+    // An interrupt applies to the call it arrives during, including compiling
+    // and verifying, but not to later calls: one that arrived between calls is
+    // dropped here. See `InterruptHandle`.
+    fn clear_interrupt(&self)
+    {
+        self.interrupted.store(false, AtomicOrdering::Relaxed);
+    }
+
+    // Called every instruction; the clock and the interrupt flag are only
+    // looked at every `LIMIT_CHECK_INTERVAL` instructions.
+    fn check_limits(&self, executed: u64, deadline: Option<Instant>) -> Option<LoxError>
+    {
+        if let Some(limit) = self.instruction_limit
+        {
+            if executed > limit
+            {
+                return Some(LoxError::InstructionLimitExceeded);
+            }
+        }
+        if executed & (LIMIT_CHECK_INTERVAL - 1) != 0
+        {
+            return None;
+        }
+        if self.interrupted.swap(false, AtomicOrdering::Relaxed)
+        {
+            return Some(LoxError::Interrupted);
+        }
+        if let Some(deadline) = deadline
+        {
+            if Instant::now() >= deadline
+            {
+                return Some(LoxError::DeadlineExceeded);
+            }
+        }
+        return None;
+    }
+
+    // Unlike `RuntimeError`, reports the instruction that was about to run.
+    fn abort(&mut self, error: LoxError) -> InterpretResult
+    {
+        self.write_output(&format!("[line {}] in script\n{}\n", self.chunk.lines[self.ip], error));
+        self.ResetStack();
+        return InterpretResult::InterpretAborted(error);
+    }
+
+    fn peek(&self, distance: u32) -> Value
+    {
+        self.stack[self.stack.len() - 1 - distance as usize]
+    }
+
+    /// Returns false, without pushing, if the stack is full.
+    pub fn push(&mut self, value: Value) -> bool
+    {
+        if self.stack.len() >= self.stack_max
+        {
+            return false;
+        }
+        self.stack.push(value);
+        return true;
+    }
+
+    pub fn pop(&mut self) -> Value
+    {
+        // Compiled and verified chunks never pop more than they pushed.
+        return self.stack.pop().unwrap_or_else(value::NilAsValue);
+    }
+
+    fn stack_overflow(&mut self) -> InterpretResult
+    {
+        self.RuntimeError("Stack overflow.".to_string());
+        return InterpretResult::InterpretRuntimeError;
+    }
+
+    pub fn run(&mut self) -> InterpretResult
+    {
+        if self.trace
+        {
+            return self.execute::<true>(None);
+        }
+        return self.execute::<false>(None);
+    }
+
+    // The tracing and debugging hooks are only compiled into the `HOOKED` copy
+    // of the loop, so running without them costs nothing.
+    fn execute<const HOOKED: bool>(&mut self, mut hook: Option<&mut dyn DebugHook>) -> InterpretResult
+    {
+        self.ip = 0;
+        let deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        let mut executed: u64 = 0;
+        let mut line = 0; // Line of the last instruction the hook saw.
+        while self.ip < self.instructions.len()
+        {
+            executed = executed + 1;
+            if let Some(error) = self.check_limits(executed, deadline)
+            {
+                return self.abort(error);
+            }
+            if HOOKED
+            {
+                if self.trace
+                {
+                    print!("          ");
+                    for slot in &self.stack
+                    {
+                        print!("[ ");
+                        print_value(*slot, &self.heap);
+                        print!(" ]");
+                    }
+                    println!("");
+                    disassemble_instruction(&self.chunk, &self.heap, self.ip);
+                }
+                if let Some(hook) = hook.as_deref_mut()
+                {
+                    // The closing `OpReturn` has no statement of its own to pause at.
+                    if self.chunk.lines[self.ip] != line && self.instructions[self.ip] != OpCode::OpReturn as u8
+                    {
+                        line = self.chunk.lines[self.ip];
+                        if !hook.on_line(self, line)
+                        {
+                            self.ResetStack();
+                            return InterpretResult::InterpretAborted(LoxError::Interrupted);
+                        }
+                    }
+                }
+            }
+            let instruction = self.read_byte();
+            match OpCode::try_from(instruction)
+            {
+                Ok(OpCode::OpReturn) =>
+                {
+                    //print_value(self.pop());
+                    //println!("");
+                    return InterpretResult::InterpretOk;
+                }
+                Ok(OpCode::OpConstant) =>
+                {
+                    let constant = self.read_constant();
+                    if !self.push(constant)
+                    {
+                        return self.stack_overflow();
+                    }
+                }
+                Ok(OpCode::OpNegate) =>
+                {
+                    if !self.peek(0).IsNumber()
+                    {
+                        self.RuntimeError( "Operand must be a number.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let negated_number = -self.pop().GetNumber();
+                    self.push(crate::value::NumberAsValue(negated_number));
+                }
+                Ok(OpCode::OpAdd) =>
+                {
+                    let b = self.pop();
+                    if !self.Add(b)
+                    {
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                }
+                Ok(OpCode::OpAddConstant) =>
+                {
+                    let b = self.read_constant();
+                    if !self.Add(b)
+                    {
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                }
+                Ok(OpCode::OpSubtract) =>
+                {
+                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
+                    {
+                        self.RuntimeError("Operands must be numbers.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let b = self.pop().GetNumber();
+                    let a = self.pop().GetNumber();
+                    self.push(crate::value::NumberAsValue(a - b));
+                }
+                Ok(OpCode::OpMultiply) =>
+                {
+                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
+                    {
+                        self.RuntimeError("Operands must be numbers.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let b = self.pop().GetNumber();
+                    let a = self.pop().GetNumber();
+                    self.push(crate::value::NumberAsValue(a * b));
+                }
+                Ok(OpCode::OpDivide) =>
+                {
+                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
+                    {
+                        self.RuntimeError("Operands must be numbers.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let b = self.pop().GetNumber();
+                    let a = self.pop().GetNumber();
+                    self.push(crate::value::NumberAsValue(a / b));
+                }
+                Ok(OpCode::OpGreater) =>
+                {
+                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
+                    {
+                        self.RuntimeError("Operands must be numbers.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let b = self.pop().GetNumber();
+                    let a = self.pop().GetNumber();
+                    self.push(crate::value::BoolAsValue(a > b));
+                }
+                Ok(OpCode::OpLess) =>
+                {
+                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
+                    {
+                        self.RuntimeError("Operands must be numbers.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let b = self.pop().GetNumber();
+                    let a = self.pop().GetNumber();
+                    self.push(crate::value::BoolAsValue(a < b));
+                }
+                // `a >= b` is `!(a < b)` and `a <= b` is `!(a > b)`, as in the unoptimized code.
+                Ok(OpCode::OpGreaterEqual) =>
+                {
+                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
+                    {
+                        self.RuntimeError("Operands must be numbers.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let b = self.pop().GetNumber();
+                    let a = self.pop().GetNumber();
+                    self.push(crate::value::BoolAsValue(a.partial_cmp(&b) != Some(Ordering::Less)));
+                }
+                Ok(OpCode::OpLessEqual) =>
+                {
+                    if !self.peek(0).IsNumber() || !self.peek(1).IsNumber()
+                    {
+                        self.RuntimeError("Operands must be numbers.".to_string());
+                        return InterpretResult::InterpretRuntimeError;
+                    }
+                    let b = self.pop().GetNumber();
+                    let a = self.pop().GetNumber();
+                    self.push(crate::value::BoolAsValue(a.partial_cmp(&b) != Some(Ordering::Greater)));
+                }
+                Ok(OpCode::OpNil) =>
+                {
+                    if !self.push(crate::value::NilAsValue())
+                    {
+                        return self.stack_overflow();
+                    }
+                }
+                Ok(OpCode::OpTrue) =>
+                {
+                    if !self.push(crate::value::BoolAsValue(true))
+                    {
+                        return self.stack_overflow();
+                    }
+                }
+                Ok(OpCode::OpFalse) =>
+                {
+                    if !self.push(crate::value::BoolAsValue(false))
+                    {
+                        return self.stack_overflow();
+                    }
+                }
+                Ok(OpCode::OpNot) => 
+                {
+                    let boolean_not = self.pop().IsFalsey();
+                    self.push(crate::value::BoolAsValue(boolean_not));
+                }
+                Ok(OpCode::OpEqual) => 
+                {
+                    let is_equal = self.pop().Equals(&self.pop());
+                    self.push(crate::value::BoolAsValue(is_equal));
+                }
+                Ok(OpCode::OpNotEqual) =>
+                {
+                    let is_equal = self.pop().Equals(&self.pop());
+                    self.push(crate::value::BoolAsValue(!is_equal));
+                }
+                Ok(OpCode::OpPrint) => 
+                {
+                    let print = self.pop();
+                    self.write_output(&(value_to_string(print, &self.heap) + "\n"));
+                }
+                Ok(OpCode::OpPop) => {self.pop();}
+                Ok(OpCode::OpDefineGlobal) =>
+                {
+                    let slot = self.read_slot();
+                    self.globals[slot] = self.pop();
+                }
+                Ok(OpCode::OpGetGlobal) =>
+                {
+                    let slot = self.read_slot();
+                    let value = self.globals[slot];
+                    if value.ValueType == ValueType::ValInternalNil
+                    {
+                        return self.undefined_variable(slot);
+                    }
+                    if !self.push(value)
+                    {
+                        return self.stack_overflow();
+                    }
+                }
+                Ok(OpCode::OpSetGlobal) =>
+                {
+                    let slot = self.read_slot();
+                    if self.globals[slot].ValueType == ValueType::ValInternalNil
+                    {
+                        return self.undefined_variable(slot);
+                    }
+                    self.globals[slot] = self.peek(0);
+                }
+                Err(byte) =>
+                {
+                    self.RuntimeError(format!("Unknown opcode {}.", byte));
+                    return InterpretResult::InterpretRuntimeError;
+                }
+            }
+        }
+        return InterpretResult::InterpretOk;
+    }
+
+    // Replaces the value on top of the stack with it plus `b`. Returns false
+    // after reporting a runtime error.
+    fn Add(&mut self, b: Value) -> bool
+    {
+        let a = self.peek(0);
+        if a.IsString() && b.IsString()
+        {
+            return self.Concatenate(a.GetObject(), b.GetObject());
+        }
+        else if a.IsNumber() && b.IsNumber()
+        {
+            self.pop();
+            self.push(crate::value::NumberAsValue(a.GetNumber() + b.GetNumber()));
+            return true;
+        }
+        self.RuntimeError("Operands must be numbers or strings.".to_string());
+        return false;
+    }
+
+    // Replaces `value_of_a` on top of the stack with the concatenation. Returns
+    // false after reporting a runtime error if the heap limit is reached.
+    fn Concatenate(&mut self, value_of_a: ObjRef, value_of_b: ObjRef) -> bool
+    {
+        let both = self.heap.GetString(value_of_a).str.clone() + &self.heap.GetString(value_of_b).str;
+        match self.heap.TryTakeString(both)
+        {
+            Some(both) =>
+            {
+                self.pop();
+                self.push(value::ObjAsValue(both));
+                return true;
+            }
+            None =>
+            {
+                self.RuntimeError("Out of memory.".to_string());
+                return false;
+            }
+        }
+    }
+
+    fn RuntimeError(&mut self, error: String)
+    {
+        let line_number = self.chunk.lines[self.ip - 1];
+        self.write_output(&format!("[line {}] in script\n{}\n", line_number, error));
+        self.ResetStack();
+    }
+
+    fn ResetStack(&mut self)
+    {
+        self.stack.clear();
+    }
+
+    fn read_byte(&mut self) -> u8
+    {
+        let byte = self.instructions[self.ip];
+        self.ip = self.ip + 1;
+        return byte;
+    }
+
+    fn read_constant(&mut self) -> Value
+    {
+        let index = self.read_byte();
+        return self.chunk.constants.values[index as usize];
+    }
+
+    fn read_slot(&mut self) -> usize
+    {
+        let index = self.read_byte();
+        return self.slots[index as usize];
+    }
+
+    fn undefined_variable(&mut self, slot: usize) -> InterpretResult
+    {
+        let name = self.global_names[slot];
+        let message = native::undefined_variable(&self.heap.GetString(name).str, self.missing_capabilities.get(name));
+        self.RuntimeError(message);
+        return InterpretResult::InterpretRuntimeError;
+    }
+
+    /// Returns the slot holding the global called `name`, reserving one if the
+    /// name hasn't been seen before. Slots persist for the life of the VM, so a
+    /// global defined by one chunk is visible to every chunk run after it.
+    pub fn global_slot(&mut self, name: ObjRef) -> usize
+    {
+        if let Some(slot) = self.global_slots.get(name)
+        {
+            return slot;
+        }
+        let slot = self.globals.len();
+        self.globals.push(InternalNil());
+        self.global_names.push(name);
+        self.global_slots.set(name, slot);
+        return slot;
+    }
+
+    // Resolves every global name the chunk refers to, so the global ops in `run`
+    // index `globals` directly instead of hashing the name on each access.
+    fn link(&mut self)
+    {
+        self.slots = vec![0; self.chunk.constants.values.len()];
+        let mut offset = 0;
+        while offset < self.chunk.code.len()
+        {
+            let opcode = self.chunk.code[offset];
+            if chunk::is_global_op(opcode)
+            {
+                let index = self.chunk.code[offset + 1] as usize;
+                let name = self.chunk.constants.values[index].GetObject();
+                self.slots[index] = self.global_slot(name);
+            }
+            offset = offset + if chunk::has_constant_operand(opcode) { 2 } else { 1 };
+        }
+    }
+}
+
+pub fn init_vm() -> VM
+{
+    VM
+    {
+        chunk: chunk::init_chunk(),
+        instructions: Vec::with_capacity(0),
+        stack: Vec::with_capacity(STACK_MAX),
+        stack_max: STACK_MAX,
+        ip: 0,
+        heap: Heap::new(),
+        globals: Vec::new(),
+        global_names: Vec::new(),
+        global_slots: Table::new(),
+        slots: Vec::new(),
+        missing_capabilities: Table::new(),
+        opt_level: OptLevel::O1,
+        instruction_limit: None,
+        time_limit: None,
+        interrupted: Arc::new(AtomicBool::new(false)),
+        trace: debug_trace_execution,
+        output: None,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InterpretResult
+{
+    InterpretOk,
+    InterpretCompileError,
+    InterpretRuntimeError,
+    InterpretAborted(LoxError),
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::sync::Mutex;
+
+    // The verifier rejects unknown opcodes, so this loads the chunk past it.
+    #[test]
+    fn unknown_opcodes_are_runtime_errors()
+    {
+        let printed = Arc::new(Mutex::new(String::new()));
+        let sink = printed.clone();
+        let mut vm = init_vm();
+        vm.set_output(Some(Arc::new(move |text: &str| sink.lock().unwrap().push_str(text))));
+
+        let mut chunk = init_chunk();
+        chunk::write_chunk(&mut chunk, OpCode::OpNil as u8, 1);
+        chunk::write_chunk(&mut chunk, 23, 2);
+        chunk::write_chunk(&mut chunk, OpCode::OpReturn as u8, 2);
+        vm.load(chunk);
+        assert_eq!(vm.run(), InterpretResult::InterpretRuntimeError);
+        assert_eq!(*printed.lock().unwrap(), "[line 2] in script\nUnknown opcode 23.\n");
+    }
+
+    #[test]
+    fn evaluate_returns_values_and_compile_errors()
+    {
+        let printed = Arc::new(Mutex::new(String::new()));
+        let sink = printed.clone();
+        let mut vm = init_vm();
+        vm.set_output(Some(Arc::new(move |text: &str| sink.lock().unwrap().push_str(text))));
+        assert_eq!(vm.interpret("var a = 1;".to_string()), InterpretResult::InterpretOk);
+
+        assert_eq!(vm.evaluate("a + 1"), Ok(Some("2".to_string())));
+        let errors = vm.evaluate("a +").expect_err("the expression is incomplete");
+        assert_eq!(errors.len(), 1);
+        assert!(printed.lock().unwrap().is_empty());
+
+        assert_eq!(vm.evaluate("-\"text\""), Ok(None));
+        assert!(printed.lock().unwrap().contains("Operand must be a number."), "{:?}", printed.lock().unwrap());
+    }
+}
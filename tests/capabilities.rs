@@ -0,0 +1,34 @@
+// Natives a VM leaves out are undefined, with an error naming the capability.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use std::sync::{Arc, Mutex};
+
+use rust_lox::{native::Capabilities, vm::{InterpretResult, VM}};
+
+fn run(capabilities: &Capabilities, source: &str) -> (InterpretResult, String)
+{
+    let printed = Arc::new(Mutex::new(String::new()));
+    let sink = printed.clone();
+    let mut vm = VM::with_capabilities(capabilities);
+    vm.set_output(Some(Arc::new(move |text: &str| sink.lock().unwrap().push_str(text))));
+    let result = vm.interpret(source.to_string());
+    let output = printed.lock().unwrap().clone();
+    return (result, output);
+}
+
+#[test]
+fn core_leaves_out_read_file()
+{
+    let (result, output) = run(&Capabilities::core(), "print readFile;");
+    assert_eq!(result, InterpretResult::InterpretRuntimeError);
+    assert_eq!(output, "[line 1] in script\nUndefined variable 'readFile' (needs the 'fs' capability, which is not enabled)\n");
+}
+
+#[test]
+fn with_fs_defines_read_file()
+{
+    let (result, output) = run(&Capabilities::core().with_fs(Vec::new()), "print readFile;");
+    assert_eq!(result, InterpretResult::InterpretOk);
+    assert_eq!(output, "<native fn>\n");
+}
@@ -0,0 +1,201 @@
+// Drives `rust_lox dap` through a whole debugging session, as an editor would.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use std::{io::BufReader, process::{Child, ChildStdin, ChildStdout, Command, Stdio}};
+
+use rust_lox::{json::Json, protocol::{read_message, write_message}};
+
+const PROGRAM: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/debugger/multiline.lox");
+
+struct Client
+{
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    seq: f64,
+    printed: String, // The program's output events, in order.
+}
+
+impl Client
+{
+    fn start() -> Client
+    {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rust_lox"))
+            .arg("dap")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("rust_lox should start");
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        return Client { child: child, input: input, output: output, seq: 0.0, printed: String::new() };
+    }
+
+    fn send(&mut self, command: &str, arguments: Json)
+    {
+        self.seq = self.seq + 1.0;
+        let request = Json::object(vec![
+            ("seq", Json::Number(self.seq)),
+            ("type", Json::string("request")),
+            ("command", Json::string(command)),
+            ("arguments", arguments),
+        ]);
+        write_message(&mut self.input, &request).expect("the adapter should read requests");
+    }
+
+    // Reads messages up to the first for which `wanted` holds, and returns it.
+    fn until(&mut self, wanted: impl Fn(&Json) -> bool) -> Json
+    {
+        loop
+        {
+            let message = read_message(&mut self.output).expect("the adapter should send valid messages")
+                .expect("the adapter ended the session early");
+            if event(&message) == Some("output")
+            {
+                let body = message.get("body").unwrap();
+                if body.get("category").and_then(Json::as_str) == Some("stdout")
+                {
+                    self.printed.push_str(body.get("output").and_then(Json::as_str).unwrap());
+                }
+            }
+            if wanted(&message)
+            {
+                return message;
+            }
+        }
+    }
+
+    fn request(&mut self, command: &str, arguments: Json) -> Json
+    {
+        self.send(command, arguments);
+        let response = self.until(|message| message.get("type").and_then(Json::as_str) == Some("response"));
+        assert_eq!(response.get("command").and_then(Json::as_str), Some(command));
+        return response;
+    }
+
+    fn stopped_line(&mut self) -> f64
+    {
+        let response = self.request("stackTrace", Json::object(vec![("threadId", Json::Number(1.0))]));
+        let frames = response.get("body").and_then(|body| body.get("stackFrames")).and_then(Json::as_array).unwrap();
+        return frames[0].get("line").and_then(Json::as_f64).unwrap();
+    }
+}
+
+fn event(message: &Json) -> Option<&str>
+{
+    message.get("event").and_then(Json::as_str)
+}
+
+fn body_field<'a>(message: &'a Json, field: &str) -> &'a Json
+{
+    message.get("body").and_then(|body| body.get(field)).unwrap_or(&Json::Null)
+}
+
+#[test]
+fn breakpoints_steps_and_variables()
+{
+    let mut client = Client::start();
+    client.request("initialize", Json::object(vec![("adapterID", Json::string("lox"))]));
+    client.send("launch", Json::object(vec![("program", Json::string(PROGRAM)), ("stopOnEntry", Json::Bool(true))]));
+    client.until(|message| event(message) == Some("initialized"));
+
+    // Line 4 is blank, so that breakpoint moves to line 5; line 99 is past the end.
+    let lines = [3.0, 4.0, 99.0];
+    let breakpoints = lines.iter().map(|line| Json::object(vec![("line", Json::Number(*line))])).collect();
+    let response = client.request("setBreakpoints", Json::object(vec![
+        ("source", Json::object(vec![("path", Json::string(PROGRAM))])),
+        ("breakpoints", Json::Array(breakpoints)),
+    ]));
+    let placed: Vec<(bool, f64)> = body_field(&response, "breakpoints").as_array().unwrap().iter()
+        .map(|breakpoint| (breakpoint.get("verified").and_then(Json::as_bool).unwrap(), breakpoint.get("line").and_then(Json::as_f64).unwrap()))
+        .collect();
+    assert_eq!(placed, vec![(true, 3.0), (true, 5.0), (false, 99.0)]);
+
+    client.request("configurationDone", Json::Null);
+    let stopped = client.until(|message| event(message) == Some("stopped"));
+    assert_eq!(body_field(&stopped, "reason").as_str(), Some("entry"));
+    assert_eq!(client.stopped_line(), 1.0);
+
+    client.request("next", Json::Null);
+    client.until(|message| event(message) == Some("stopped"));
+    assert_eq!(client.stopped_line(), 2.0);
+
+    // The `+ 2` of the statement started on line 2 runs on line 3.
+    client.request("continue", Json::Null);
+    let stopped = client.until(|message| event(message) == Some("stopped"));
+    assert_eq!(body_field(&stopped, "reason").as_str(), Some("breakpoint"));
+    assert_eq!(client.stopped_line(), 3.0);
+
+    client.request("continue", Json::Null);
+    client.until(|message| event(message) == Some("stopped"));
+    assert_eq!(client.stopped_line(), 5.0);
+
+    let response = client.request("variables", Json::object(vec![("variablesReference", Json::Number(1.0))]));
+    let variables: Vec<(String, String)> = body_field(&response, "variables").as_array().unwrap().iter()
+        .map(|variable| (variable.get("name").and_then(Json::as_str).unwrap().to_string(), variable.get("value").and_then(Json::as_str).unwrap().to_string()))
+        .collect();
+    assert!(variables.contains(&("a".to_string(), "1".to_string())), "{:?}", variables);
+    assert!(variables.contains(&("b".to_string(), "3".to_string())), "{:?}", variables);
+
+    client.request("continue", Json::Null);
+    let exited = client.until(|message| event(message) == Some("exited"));
+    assert_eq!(body_field(&exited, "exitCode").as_f64(), Some(0.0));
+    client.until(|message| event(message) == Some("terminated"));
+    assert_eq!(client.printed, "4\nx\n");
+
+    client.request("disconnect", Json::Null);
+    assert!(client.child.wait().unwrap().success());
+}
+
+// With a single frame, `stepIn` is `next` and `stepOut` is `continue`.
+#[test]
+fn step_in_and_step_out()
+{
+    let mut client = Client::start();
+    client.request("initialize", Json::Null);
+    client.send("launch", Json::object(vec![("program", Json::string(PROGRAM)), ("stopOnEntry", Json::Bool(true))]));
+    client.until(|message| event(message) == Some("initialized"));
+    client.request("setBreakpoints", Json::object(vec![
+        ("source", Json::object(vec![("path", Json::string(PROGRAM))])),
+        ("breakpoints", Json::Array(vec![Json::object(vec![("line", Json::Number(6.0))])])),
+    ]));
+    client.request("configurationDone", Json::Null);
+    client.until(|message| event(message) == Some("stopped"));
+    assert_eq!(client.stopped_line(), 1.0);
+
+    client.request("stepIn", Json::Null);
+    let stopped = client.until(|message| event(message) == Some("stopped"));
+    assert_eq!(body_field(&stopped, "reason").as_str(), Some("step"));
+    assert_eq!(client.stopped_line(), 2.0);
+
+    client.request("stepOut", Json::Null);
+    let stopped = client.until(|message| event(message) == Some("stopped"));
+    assert_eq!(body_field(&stopped, "reason").as_str(), Some("breakpoint"));
+    assert_eq!(client.stopped_line(), 6.0);
+    assert_eq!(client.printed, "4\n");
+
+    client.request("stepOut", Json::Null);
+    client.until(|message| event(message) == Some("terminated"));
+    assert_eq!(client.printed, "4\nx\n");
+
+    client.request("disconnect", Json::Null);
+    assert!(client.child.wait().unwrap().success());
+}
+
+#[test]
+fn compile_errors_fail_the_launch()
+{
+    let mut client = Client::start();
+    client.request("initialize", Json::Null);
+    let program = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/debugger/syntax_error.lox");
+    client.send("launch", Json::object(vec![("program", Json::string(program))]));
+    let report = client.until(|message| event(message) == Some("output"));
+    assert_eq!(body_field(&report, "category").as_str(), Some("stderr"));
+    assert_eq!(body_field(&report, "output").as_str(), Some("[line 2] Error at ;: Expect expression\n"));
+    let response = client.until(|message| message.get("type").and_then(Json::as_str) == Some("response"));
+    assert_eq!(response.get("success"), Some(&Json::Bool(false)));
+
+    client.request("disconnect", Json::Null);
+    assert!(client.child.wait().unwrap().success());
+}
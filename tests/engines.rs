@@ -0,0 +1,73 @@
+// Runs every script in tests/lox through the bytecode VM, with and without the
+// optimizer, and through the tree-walking interpreter, and checks that all
+// three print the same thing and exit the same way. In process, the VM's AST
+// front end (`interpret_ast`) must agree with its single-pass compiler.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use std::{fs, path::PathBuf, process::Command, sync::{Arc, Mutex}};
+
+use rust_lox::vm::{self, InterpretResult};
+
+const ENGINES: [&[&str]; 3] = [&["-O0"], &["-O1"], &["--tree-walk"]];
+
+fn run(flags: &[&str], script: &PathBuf) -> (String, Option<i32>)
+{
+    let output = Command::new(env!("CARGO_BIN_EXE_rust_lox"))
+        .args(flags)
+        .arg(script)
+        .output()
+        .expect("rust_lox should start");
+    return (String::from_utf8_lossy(&output.stdout).into_owned(), output.status.code());
+}
+
+fn scripts() -> Vec<PathBuf>
+{
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("lox");
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&directory).expect("tests/lox should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty());
+    return scripts;
+}
+
+// Runs `source` on a fresh VM with `interpret`, or `interpret_ast` if `ast`,
+// and returns what it printed and how it ended.
+fn run_in_process(source: &str, ast: bool) -> (String, InterpretResult)
+{
+    let printed = Arc::new(Mutex::new(String::new()));
+    let sink = printed.clone();
+    let mut vm = vm::init_vm();
+    vm.set_output(Some(Arc::new(move |text: &str| sink.lock().unwrap().push_str(text))));
+    let result = if ast { vm.interpret_ast(source.to_string()) } else { vm.interpret(source.to_string()) };
+    let output = printed.lock().unwrap().clone();
+    return (output, result);
+}
+
+#[test]
+fn engines_agree_on_every_script()
+{
+    for script in &scripts()
+    {
+        let expected = run(ENGINES[0], script);
+        assert!(!expected.0.is_empty(), "{} printed nothing", script.display());
+        for flags in &ENGINES[1..]
+        {
+            let actual = run(flags, script);
+            assert_eq!(expected, actual, "{} differs between {:?} and {:?}", script.display(), ENGINES[0], flags);
+        }
+    }
+}
+
+#[test]
+fn front_ends_agree_on_every_script()
+{
+    for script in &scripts()
+    {
+        let source = fs::read_to_string(script).unwrap();
+        let expected = run_in_process(&source, false);
+        assert_eq!(run_in_process(&source, true), expected, "{} differs between the front ends", script.display());
+    }
+}
@@ -0,0 +1,112 @@
+// `format` on the scripts in tests/format, and `rust_lox fmt --check`.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use std::{env, fs, io::Write, path::PathBuf, process::{Command, Output, Stdio}};
+
+use rust_lox::format::{MAX_WIDTH, format};
+
+fn fixtures() -> Vec<PathBuf>
+{
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("format");
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&directory).expect("tests/format should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty());
+    return scripts;
+}
+
+fn fmt(args: &[&str], stdin: &str) -> Output
+{
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust_lox"))
+        .arg("fmt")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("rust_lox should start");
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    return child.wait_with_output().unwrap();
+}
+
+#[test]
+fn formatted_code_formats_to_itself()
+{
+    for script in fixtures()
+    {
+        let source = fs::read_to_string(&script).unwrap();
+        let once = format(&source).expect("fixtures should scan");
+        assert_ne!(once, source, "{} is already formatted", script.display());
+        assert_eq!(format(&once).unwrap(), once, "formatting {} again changed it", script.display());
+    }
+}
+
+// Each fixture's `.expected` file is its exact formatted text.
+#[test]
+fn fixtures_format_as_expected()
+{
+    for script in fixtures()
+    {
+        let expected = fs::read_to_string(script.with_extension("expected")).expect("each fixture should have a .expected file");
+        let formatted = format(&fs::read_to_string(&script).unwrap()).expect("fixtures should scan");
+        assert_eq!(formatted, expected, "{} formatted differently", script.display());
+    }
+}
+
+// Whatever the operands, a line only passes the width when one token can't fit.
+#[test]
+fn broken_lines_fit_the_width()
+{
+    for width in 1..12
+    {
+        let terms: Vec<String> = (0..40).map(|term| "x".repeat(term % width + 1)).collect();
+        let formatted = format(&format!("print ({}) * -total;", terms.join(" + "))).unwrap();
+        assert!(formatted.lines().count() > 1);
+        for line in formatted.lines()
+        {
+            assert!(line.chars().count() <= MAX_WIDTH, "{:?} is too long", line);
+        }
+        assert_eq!(format(&formatted).unwrap(), formatted);
+    }
+}
+
+#[test]
+fn unary_minuses_keep_apart()
+{
+    assert_eq!(format("a = - - a;").unwrap(), "a = - -a;\n");
+    assert_eq!(format("a = - -  - 1;").unwrap(), "a = - - -1;\n");
+    assert_eq!(format("a = b - - a;").unwrap(), "a = b - -a;\n");
+    assert_eq!(format("a = ! - a;").unwrap(), "a = !-a;\n");
+}
+
+#[test]
+fn check_reports_unformatted_files_without_changing_them()
+{
+    let script = fixtures().remove(0);
+    let source = fs::read_to_string(&script).unwrap();
+    let formatted = env::temp_dir().join(format!("rust_lox_fmt_check_{}.lox", std::process::id()));
+    fs::write(&formatted, format(&source).unwrap()).unwrap();
+
+    let output = fmt(&["--check", script.to_str().unwrap(), formatted.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), format!("{}: not formatted\n", script.display()));
+    assert_eq!(fs::read_to_string(&script).unwrap(), source);
+
+    let output = fmt(&["--check", formatted.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+    fs::remove_file(&formatted).unwrap();
+}
+
+#[test]
+fn check_reads_stdin_without_files()
+{
+    assert_eq!(fmt(&["--check"], "print   1;").status.code(), Some(1));
+    assert_eq!(fmt(&["--check"], "print 1;\n").status.code(), Some(0));
+    assert_eq!(fmt(&["--check"], "print @;").status.code(), Some(65));
+    let output = fmt(&[], "print   1;");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "print 1;\n");
+}
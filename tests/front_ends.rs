@@ -0,0 +1,108 @@
+// `Compiler` and `parser` + `CodeGenerator` must agree on the code, line table
+// and errors for every source.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use rust_lox::{chunk::{Chunk, init_chunk}, codegen::CodeGenerator, compile::Compiler, diagnostic::Diagnostic, parser, value::value_to_string, vm::{self, VM}};
+
+// What a front end produced, with constants printed so they compare across heaps.
+#[derive(Debug, PartialEq)]
+struct Output
+{
+    code: Vec<u8>,
+    lines: Vec<u32>,
+    constants: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+fn output(chunk: &Chunk, vm: &VM, diagnostics: Vec<Diagnostic>) -> Output
+{
+    Output
+    {
+        code: chunk.code.clone(),
+        lines: chunk.lines.clone(),
+        constants: chunk.constants.values.iter().map(|value| value_to_string(*value, &vm.heap)).collect(),
+        diagnostics: diagnostics,
+    }
+}
+
+fn compile_direct(source: &str) -> Output
+{
+    let mut vm = vm::init_vm();
+    let mut chunk = init_chunk();
+    let diagnostics = Compiler::new_compiler(&mut chunk, &mut vm).compile(source.to_string()).err().unwrap_or_default();
+    return output(&chunk, &vm, diagnostics);
+}
+
+fn compile_ast(source: &str) -> Output
+{
+    let mut vm = vm::init_vm();
+    let mut chunk = init_chunk();
+    let diagnostics = match parser::parse(source)
+    {
+        Ok(program) => CodeGenerator::new(&mut chunk, &mut vm, source).generate(&program).err().unwrap_or_default(),
+        Err(diagnostics) => diagnostics,
+    };
+    return output(&chunk, &vm, diagnostics);
+}
+
+fn assert_same(source: &str)
+{
+    let direct = compile_direct(source);
+    let ast = compile_ast(source);
+    if direct.diagnostics.is_empty()
+    {
+        assert_eq!(direct, ast, "front ends differ for {:?}", source);
+    } else
+    {
+        // The code emitted before an error is thrown away, so only the errors matter.
+        assert_eq!(direct.diagnostics, ast.diagnostics, "front ends report different errors for {:?}", source);
+    }
+}
+
+#[test]
+fn statements_and_expressions()
+{
+    assert_same("");
+    assert_same("// only a comment\n\n");
+    assert_same("print 1 + 2 * 3 - 4 / 5;");
+    assert_same("print -(1 + 2) == !true;");
+    assert_same("print 1 != 2; print 1 >= 2; print 1 <= 2; print 1 < 2; print 1 > 2;");
+    assert_same("var a; var b = \"text\"; a = b = nil; print a;");
+    assert_same("var a = 1;\nvar b = a;\nb = a + b;\nprint b;\n");
+    assert_same("\"unused\";\nfalse;\n");
+}
+
+#[test]
+fn lines_of_statements_split_across_lines()
+{
+    assert_same("print 1\n+\n2;");
+    assert_same("var a\n=\n1\n;\nprint\n-\na\n;\n");
+    assert_same("var a;\na\n=\n(\n1\n)\n;\n");
+    assert_same("print \"multi\nline\" + \"string\";\n\n\n");
+}
+
+#[test]
+fn syntax_errors()
+{
+    assert_same("x = 1 = 2;");
+    assert_same("var a; var b; a + b = 1;");
+    assert_same("print ;");
+    assert_same("print ; + 1; print x x;");
+    assert_same("var = 1; print 2;");
+    assert_same("var a = print 3;");
+    assert_same("print (1;");
+    assert_same("print 1");
+    assert_same("print \"unterminated;");
+    assert_same("print @; print 1 #;");
+}
+
+#[test]
+fn too_many_constants()
+{
+    let numbers: Vec<String> = (0..300).map(|number| number.to_string()).collect();
+    assert_same(&format!("print {};", numbers.join(" + ")));
+    assert_same(&format!("print {};\nprint {};", numbers.join(" + "), numbers.join(" - ")));
+    let names: Vec<String> = (0..300).map(|number| format!("var v{} = \"s{}\";", number, number)).collect();
+    assert_same(&names.join("\n"));
+}
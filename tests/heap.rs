@@ -0,0 +1,48 @@
+// The allocation quota of a VM that runs one script after another.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use std::sync::{Arc, Mutex};
+
+use rust_lox::vm::{self, InterpretResult, VM};
+
+fn vm_with_quota(quota: usize) -> (VM, Arc<Mutex<String>>)
+{
+    let printed = Arc::new(Mutex::new(String::new()));
+    let sink = printed.clone();
+    let mut vm = vm::init_vm();
+    vm.set_output(Some(Arc::new(move |text: &str| sink.lock().unwrap().push_str(text))));
+    vm.set_allocation_quota(Some(quota));
+    return (vm, printed);
+}
+
+#[test]
+fn each_run_gets_the_whole_quota()
+{
+    let (mut vm, printed) = vm_with_quota(2000);
+    for run in 0..100
+    {
+        let source = format!("var s{} = \"run\"; s{} = s{} + \"{}\";", run, run, run, run);
+        assert_eq!(vm.interpret(source), InterpretResult::InterpretOk, "run {} failed", run);
+    }
+    assert!(!printed.lock().unwrap().contains("Out of memory."));
+
+    // The quota is per run, not a cap on the heap: nothing is freed, so the
+    // heap holds what every run allocated.
+    let stats = vm.heap_stats();
+    assert!(stats.live_bytes > 2000, "{:?}", stats);
+    assert_eq!(stats.collections, 0);
+}
+
+#[test]
+fn a_run_over_the_quota_does_not_spoil_the_next()
+{
+    let (mut vm, printed) = vm_with_quota(2000);
+    let grow = "var s = \"0123456789\"; s = s + s; s = s + s; s = s + s; s = s + s; s = s + s; s = s + s; s = s + s; s = s + s;";
+    assert_eq!(vm.interpret(grow.to_string()), InterpretResult::InterpretRuntimeError);
+    assert!(printed.lock().unwrap().contains("Out of memory."));
+
+    printed.lock().unwrap().clear();
+    assert_eq!(vm.interpret("print \"still\" + \" running\";".to_string()), InterpretResult::InterpretOk);
+    assert_eq!(*printed.lock().unwrap(), "still running\n");
+}
@@ -0,0 +1,102 @@
+// The instruction limit, the time limit and interrupts abort `VM::run` with
+// their own `LoxError`, and leave the VM able to run the next script. So does
+// a full stack, as a runtime error.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use std::{sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, thread, time::Duration};
+
+use rust_lox::{chunk::{Chunk, OpCode, init_chunk, write_chunk}, vm::{self, InterpretResult, LoxError, VM}};
+
+fn vm_with_output() -> (VM, Arc<Mutex<String>>)
+{
+    let printed = Arc::new(Mutex::new(String::new()));
+    let sink = printed.clone();
+    let mut vm = vm::init_vm();
+    vm.set_output(Some(Arc::new(move |text: &str| sink.lock().unwrap().push_str(text))));
+    return (vm, printed);
+}
+
+// `pairs` times `nil;`, as a chunk: there are no loops yet, so a long run
+// needs long code.
+fn long_chunk(pairs: usize) -> Chunk
+{
+    let mut chunk = init_chunk();
+    for _ in 0..pairs
+    {
+        write_chunk(&mut chunk, OpCode::OpNil as u8, 1);
+        write_chunk(&mut chunk, OpCode::OpPop as u8, 1);
+    }
+    write_chunk(&mut chunk, OpCode::OpReturn as u8, 1);
+    return chunk;
+}
+
+// The VM still runs scripts after an abort.
+fn assert_reusable(vm: &mut VM, printed: &Arc<Mutex<String>>)
+{
+    printed.lock().unwrap().clear();
+    assert_eq!(vm.interpret("print 1 + 2;".to_string()), InterpretResult::InterpretOk);
+    assert_eq!(*printed.lock().unwrap(), "3\n");
+}
+
+#[test]
+fn the_instruction_limit_aborts_the_run()
+{
+    let (mut vm, printed) = vm_with_output();
+    vm.set_instruction_limit(Some(10));
+    let result = vm.interpret("print 1;\nprint 2;\nprint 3;\nprint 4;\nprint 5;\nprint 6;\n".to_string());
+    assert_eq!(result, InterpretResult::InterpretAborted(LoxError::InstructionLimitExceeded));
+    assert_eq!(*printed.lock().unwrap(), "1\n2\n3\n4\n5\n[line 6] in script\nInstruction limit exceeded.\n");
+
+    assert_reusable(&mut vm, &printed);
+}
+
+#[test]
+fn the_time_limit_aborts_the_run()
+{
+    let (mut vm, printed) = vm_with_output();
+    vm.set_time_limit(Some(Duration::from_millis(1)));
+    let result = vm.interpret_chunk(long_chunk(2_000_000)).expect("the chunk should verify");
+    assert_eq!(result, InterpretResult::InterpretAborted(LoxError::DeadlineExceeded));
+    assert!(printed.lock().unwrap().ends_with("Execution time limit exceeded.\n"), "{:?}", printed.lock().unwrap());
+
+    assert_reusable(&mut vm, &printed);
+}
+
+#[test]
+fn an_interrupt_from_another_thread_aborts_the_run()
+{
+    let (mut vm, printed) = vm_with_output();
+    let handle = vm.interrupt_handle();
+    let finished = Arc::new(AtomicBool::new(false));
+    let interrupter =
+    {
+        let finished = finished.clone();
+        // An interrupt sent before the run starts is dropped, so keep sending.
+        thread::spawn(move || while !finished.load(Ordering::Relaxed)
+        {
+            handle.interrupt();
+            thread::sleep(Duration::from_millis(1));
+        })
+    };
+    let result = vm.interpret_chunk(long_chunk(2_000_000)).expect("the chunk should verify");
+    finished.store(true, Ordering::Relaxed);
+    interrupter.join().unwrap();
+    assert_eq!(result, InterpretResult::InterpretAborted(LoxError::Interrupted));
+    assert!(printed.lock().unwrap().ends_with("Interrupted.\n"), "{:?}", printed.lock().unwrap());
+
+    assert_reusable(&mut vm, &printed);
+}
+
+#[test]
+fn a_small_stack_overflows()
+{
+    let (mut vm, printed) = vm_with_output();
+    vm.set_stack_max(3);
+    // Globals, so the optimizer can't fold the sum: it needs a slot per operand.
+    assert_eq!(vm.interpret("var a = 1;\nprint a + (a + a);\n".to_string()), InterpretResult::InterpretOk);
+    assert_eq!(vm.interpret("print a + (a + (a + a));".to_string()), InterpretResult::InterpretRuntimeError);
+    assert_eq!(*printed.lock().unwrap(), "3\n[line 1] in script\nStack overflow.\n");
+
+    assert_reusable(&mut vm, &printed);
+}
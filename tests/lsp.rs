@@ -0,0 +1,232 @@
+// Drives `rust_lox lsp` over its protocol, as an editor would.
+
+#![allow(clippy::needless_return, clippy::redundant_field_names, clippy::suspicious_else_formatting, clippy::assign_op_pattern)]
+
+use std::{io::BufReader, process::{Child, ChildStdin, ChildStdout, Command, Stdio}};
+
+use rust_lox::{json::Json, protocol::{read_message, write_message}, vm};
+
+const URI: &str = "file:///test.lox";
+
+struct Client
+{
+    child: Child,
+    input: ChildStdin,
+    output: BufReader<ChildStdout>,
+    id: f64,
+}
+
+impl Client
+{
+    fn start() -> Client
+    {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_rust_lox"))
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("rust_lox should start");
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+        let mut client = Client { child: child, input: input, output: output, id: 0.0 };
+        client.request("initialize", Json::object(vec![("capabilities", Json::object(vec![]))]));
+        client.notify("initialized", Json::object(vec![]));
+        return client;
+    }
+
+    fn notify(&mut self, method: &str, params: Json)
+    {
+        let message = Json::object(vec![("jsonrpc", Json::string("2.0")), ("method", Json::string(method)), ("params", params)]);
+        write_message(&mut self.input, &message).expect("the server should read messages");
+    }
+
+    fn request(&mut self, method: &str, params: Json) -> Json
+    {
+        self.id = self.id + 1.0;
+        let message = Json::object(vec![
+            ("jsonrpc", Json::string("2.0")),
+            ("id", Json::Number(self.id)),
+            ("method", Json::string(method)),
+            ("params", params),
+        ]);
+        write_message(&mut self.input, &message).expect("the server should read messages");
+        let id = self.id;
+        let response = self.receive(|message| message.get("id").and_then(Json::as_f64) == Some(id));
+        return response.get("result").cloned().unwrap_or(Json::Null);
+    }
+
+    fn receive(&mut self, wanted: impl Fn(&Json) -> bool) -> Json
+    {
+        loop
+        {
+            let message = read_message(&mut self.output).expect("the server should send valid messages")
+                .expect("the server stopped early");
+            if wanted(&message)
+            {
+                return message;
+            }
+        }
+    }
+
+    // Opens `text` and returns the diagnostics published for it.
+    fn open(&mut self, text: &str) -> Vec<Json>
+    {
+        self.notify("textDocument/didOpen", Json::object(vec![("textDocument", Json::object(vec![
+            ("uri", Json::string(URI)),
+            ("languageId", Json::string("lox")),
+            ("version", Json::Number(1.0)),
+            ("text", Json::string(text)),
+        ]))]));
+        let published = self.receive(|message| message.get("method").and_then(Json::as_str) == Some("textDocument/publishDiagnostics"));
+        return published.get("params").and_then(|params| params.get("diagnostics")).and_then(Json::as_array).unwrap().to_vec();
+    }
+
+    fn at(&mut self, method: &str, line: f64, character: f64) -> Json
+    {
+        self.request(method, Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::string(URI))])),
+            ("position", Json::object(vec![("line", Json::Number(line)), ("character", Json::Number(character))])),
+            ("context", Json::object(vec![("includeDeclaration", Json::Bool(true))])),
+        ]))
+    }
+
+    fn document_request(&mut self, method: &str) -> Json
+    {
+        self.request(method, Json::object(vec![("textDocument", Json::object(vec![("uri", Json::string(URI))]))]))
+    }
+
+    fn stop(mut self)
+    {
+        self.request("shutdown", Json::Null);
+        self.notify("exit", Json::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn start_line(json: &Json) -> f64
+{
+    json.get("range").and_then(|range| range.get("start")).and_then(|start| start.get("line")).and_then(Json::as_f64).unwrap()
+}
+
+#[test]
+fn publishes_the_compiler_errors()
+{
+    let text = "var a = 1;\nx = 1 = 2;\nprint a\n";
+    let expected = vm::init_vm().try_compile(text).err().expect("the text should not compile");
+
+    let mut client = Client::start();
+    let published = client.open(text);
+    let messages: Vec<(f64, &str)> = published.iter()
+        .map(|diagnostic| (start_line(diagnostic), diagnostic.get("message").and_then(Json::as_str).unwrap()))
+        .collect();
+    let compiled: Vec<(f64, &str)> = expected.iter().map(|diagnostic| ((diagnostic.span.line - 1) as f64, diagnostic.message.as_str())).collect();
+    assert_eq!(messages, compiled);
+    assert_eq!(messages, vec![(1.0, "Invalid assignment target."), (3.0, "Expect ';' after value.")]);
+    assert!(published.iter().all(|diagnostic| diagnostic.get("severity").and_then(Json::as_f64) == Some(1.0)));
+    client.stop();
+}
+
+#[test]
+fn publishes_lint_warnings_for_code_that_compiles()
+{
+    let mut client = Client::start();
+    let published = client.open("var unused = 1;\nprint clock;\n");
+    assert_eq!(published.len(), 1);
+    assert_eq!(published[0].get("message").and_then(Json::as_str), Some("Variable 'unused' is never read. [unused-variable]"));
+    assert_eq!(published[0].get("severity").and_then(Json::as_f64), Some(2.0));
+    client.stop();
+}
+
+#[test]
+fn navigates_between_declarations_and_uses()
+{
+    let mut client = Client::start();
+    client.open("var total = 1;\ntotal = total + 2;\nprint total;\n");
+
+    let definitions = client.at("textDocument/definition", 2.0, 7.0);
+    let definitions = definitions.as_array().unwrap();
+    assert_eq!(definitions.len(), 1);
+    assert_eq!(start_line(&definitions[0]), 0.0);
+    assert_eq!(definitions[0].get("uri").and_then(Json::as_str), Some(URI));
+
+    let references = client.at("textDocument/references", 0.0, 5.0);
+    let lines: Vec<f64> = references.as_array().unwrap().iter().map(start_line).collect();
+    assert_eq!(lines, vec![0.0, 1.0, 1.0, 2.0]);
+
+    let hover = client.at("textDocument/hover", 1.0, 0.0);
+    assert!(hover.get("contents").is_some(), "{:?}", hover);
+    assert_eq!(client.at("textDocument/hover", 1.0, 14.0), Json::Null);
+    client.stop();
+}
+
+#[test]
+fn encodes_semantic_tokens()
+{
+    let mut client = Client::start();
+    client.open("var s = \"a\nbc\"; // 🦀 é\nprint \"🦀\" + clock;\n");
+    let tokens = client.document_request("textDocument/semanticTokens/full");
+    let data: Vec<f64> = tokens.get("data").and_then(Json::as_array).unwrap().iter().map(|number| number.as_f64().unwrap()).collect();
+    // Each token is (line delta, start delta, length, type, modifiers); columns
+    // count UTF-16 code units, and the string spanning two lines is split.
+    assert_eq!(data, vec![
+        0.0, 0.0, 3.0, 0.0, 0.0, // var
+        0.0, 4.0, 1.0, 1.0, 1.0, // s, declared
+        0.0, 2.0, 1.0, 5.0, 0.0, // =
+        0.0, 2.0, 2.0, 3.0, 0.0, // "a
+        1.0, 0.0, 3.0, 3.0, 0.0, // bc"
+        0.0, 5.0, 7.0, 6.0, 0.0, // // 🦀 é
+        1.0, 0.0, 5.0, 0.0, 0.0, // print
+        0.0, 6.0, 4.0, 3.0, 0.0, // "🦀"
+        0.0, 5.0, 1.0, 5.0, 0.0, // +
+        0.0, 2.0, 5.0, 2.0, 0.0, // clock
+    ]);
+    client.stop();
+}
+
+#[test]
+fn completes_names_in_scope_and_keywords()
+{
+    let mut client = Client::start();
+    client.open("var alpha = 1;\n\nvar beta = 2;\n");
+    let completions = client.at("textDocument/completion", 1.0, 0.0);
+    let items: Vec<(&str, f64)> = completions.as_array().unwrap().iter()
+        .map(|item| (item.get("label").and_then(Json::as_str).unwrap(), item.get("kind").and_then(Json::as_f64).unwrap()))
+        .collect();
+    assert_eq!(items[..3], [("clock", 3.0), ("readFile", 3.0), ("alpha", 6.0)]);
+    assert!(!items.iter().any(|(label, _)| *label == "beta"));
+    assert!(items[3..].iter().all(|(_, kind)| *kind == 14.0));
+    assert!(items.contains(&("print", 14.0)));
+    client.stop();
+}
+
+#[test]
+fn outlines_the_declarations()
+{
+    let mut client = Client::start();
+    client.open("var a = 1;\nprint a;\nvar b =\n  2;\n");
+    let symbols = client.document_request("textDocument/documentSymbol");
+    let outline: Vec<(&str, f64, f64, f64)> = symbols.as_array().unwrap().iter().map(|symbol|
+    {
+        let end_line = symbol.get("range").and_then(|range| range.get("end")).and_then(|end| end.get("line")).and_then(Json::as_f64).unwrap();
+        let name_start = symbol.get("selectionRange").and_then(|range| range.get("start")).and_then(|start| start.get("character")).and_then(Json::as_f64).unwrap();
+        (symbol.get("name").and_then(Json::as_str).unwrap(), start_line(symbol), end_line, name_start)
+    }).collect();
+    assert_eq!(outline, vec![("a", 0.0, 0.0, 4.0), ("b", 2.0, 3.0, 4.0)]);
+    assert!(symbols.as_array().unwrap().iter().all(|symbol| symbol.get("kind").and_then(Json::as_f64) == Some(13.0)));
+    client.stop();
+}
+
+#[test]
+fn requests_see_the_latest_version()
+{
+    let mut client = Client::start();
+    client.open("var old = 1;\nprint old;\n");
+    client.notify("textDocument/didChange", Json::object(vec![
+        ("textDocument", Json::object(vec![("uri", Json::string(URI)), ("version", Json::Number(2.0))])),
+        ("contentChanges", Json::Array(vec![Json::object(vec![("text", Json::string("var new = 1;\nprint new;\n"))])])),
+    ]));
+    let symbols = client.document_request("textDocument/documentSymbol");
+    let names: Vec<&str> = symbols.as_array().unwrap().iter().map(|symbol| symbol.get("name").and_then(Json::as_str).unwrap()).collect();
+    assert_eq!(names, vec!["new"]);
+    client.stop();
+}